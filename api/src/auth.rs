@@ -0,0 +1,129 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::{
+    extract::{FromRequestParts, OptionalFromRequestParts, Request, State},
+    http::{header, request::Parts, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::custom_exceptions::{BadResponseObject, ErrorCode};
+use my_core::config::CONFIG;
+use services::AppState;
+use services::auth::{AuthError, Claims};
+
+
+/// Проверяет bearer-токен, если он передан, и кладет утверждения в расширения запроса.
+/// Запрос без токена проходит дальше: нужен ли пользователь, решают маршруты.
+/// Без ключей JWT заголовок `Authorization` не разбирается
+pub async fn authenticate(
+    State(app_state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(jwt) = app_state.jwt.as_ref() else {
+        return next.run(request).await;
+    };
+    let Some(token) = bearer_token(&request) else {
+        return next.run(request).await;
+    };
+
+    match jwt.verify(token) {
+        Ok(claims) => {
+            request.extensions_mut().insert(claims);
+            next.run(request).await
+        }
+        Err(err) => {
+            tracing::info!("Bearer token rejected: {}", err);
+            let status = match err {
+                AuthError::Inactive => StatusCode::FORBIDDEN,
+                AuthError::Config(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::UNAUTHORIZED,
+            };
+            reject(BadResponseObject::from(err), status)
+        }
+    }
+}
+
+/// Пропускает на маршруты загрузки только пользователей со scope `jwt_upload_scope`.
+/// Без ключей JWT маршруты открыты, как и раньше; OPTIONS не проверяется
+pub async fn require_upload_scope(
+    State(app_state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if app_state.jwt.is_none() || request.method() == Method::OPTIONS {
+        return next.run(request).await;
+    }
+
+    let checked = match request.extensions().get::<Claims>() {
+        Some(claims) => check_scope(claims, &CONFIG.jwt_upload_scope).map_err(|err| (err, StatusCode::FORBIDDEN)),
+        None => Err((ErrorCode::NotAuthenticated.details(), StatusCode::UNAUTHORIZED)),
+    };
+    match checked {
+        Ok(()) => next.run(request).await,
+        Err((err, status)) => reject(err, status),
+    }
+}
+
+/// Есть ли у пользователя scope
+pub(crate) fn check_scope(claims: &Claims, scope: &str) -> Result<(), BadResponseObject> {
+    if claims.has_scope(scope) {
+        return Ok(());
+    }
+    Err(ErrorCode::ForbiddenError.details()
+        .with("reason", "Token does not grant the required scope")
+        .with("scope", scope))
+}
+
+/// Проверка в обработчике вместо `require_upload_scope`: с настроенным JWT нужен
+/// пользователь со scope `jwt_upload_scope`, без JWT доступ открыт
+pub(crate) fn check_upload_scope(app_state: &AppState, user: Option<&AuthUser>) -> Result<(), BadResponseObject> {
+    if app_state.jwt.is_none() {
+        return Ok(());
+    }
+    let AuthUser(claims) = user.ok_or_else(|| ErrorCode::NotAuthenticated.details())?;
+    check_scope(claims, &CONFIG.jwt_upload_scope)
+}
+
+fn bearer_token(request: &Request) -> Option<&str> {
+    let value = request.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+/// Ошибка аутентификации с настоящим HTTP-статусом и `WWW-Authenticate` для 401
+fn reject(err: BadResponseObject, status: StatusCode) -> Response {
+    let mut response = err.into_response_with_status(status);
+    if status == StatusCode::UNAUTHORIZED {
+        response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+    response
+}
+
+
+/// Пользователь из проверенного bearer-токена. Обязательный экстрактор отвечает
+/// `NotAuthenticated`, `Option<AuthUser>` - `None` без токена
+#[derive(Debug, Clone)]
+pub struct AuthUser(pub Claims);
+
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions
+            .get::<Claims>()
+            .cloned()
+            .map(AuthUser)
+            .ok_or_else(|| reject(ErrorCode::NotAuthenticated.details(), StatusCode::UNAUTHORIZED))
+    }
+}
+
+impl<S: Send + Sync> OptionalFromRequestParts<S> for AuthUser {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Option<Self>, Self::Rejection> {
+        Ok(parts.extensions.get::<Claims>().cloned().map(AuthUser))
+    }
+}
@@ -0,0 +1,36 @@
+use axum::{
+    extract::{DefaultBodyLimit, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use tower::{Layer, Service};
+
+use crate::custom_exceptions::ErrorCode;
+
+
+/// Лимит тела запроса маршрута, в байтах. Запрос с `Content-Length` больше лимита
+/// отклоняется до чтения тела; тело без `Content-Length` обрывается на лимите
+/// экстракторами (`Multipart`, `Json`), их ошибки дают 413
+pub async fn limit_body(
+    State(limit): State<u64>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let content_length = request.headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if let Some(content_length) = content_length.filter(|length| *length > limit) {
+        tracing::info!("Rejected request body of {} bytes, limit is {}", content_length, limit);
+        return ErrorCode::PayloadTooLarge.details()
+            .with("reason", "Request body is too large")
+            .with("limit", limit)
+            .with("content_length", content_length)
+            .into_response_with_status(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    // Лимит маршрута заменяет общий `DefaultBodyLimit` из main.rs
+    let mut next = DefaultBodyLimit::max(usize::try_from(limit).unwrap_or(usize::MAX)).layer(next);
+    next.call(request).await.unwrap_or_else(|never| match never {})
+}
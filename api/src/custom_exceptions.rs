@@ -13,6 +13,10 @@ use strum_macros::{EnumIter, AsRefStr};
 use utoipa::ToSchema;
 use serde_json::json;
 use lazy_regex::regex;
+use services::auth::AuthError;
+use services::links::LinkError;
+use services::progress::ProgressError;
+use services::sessions::{SessionError, TrackRole};
 
 #[macro_export]
 macro_rules! json_err {
@@ -120,6 +124,13 @@ impl BadResponseObject {
         }
     }
 
+    // Ответ с точным HTTP-статусом; глобальный обработчик его не переписывает
+    pub fn into_response_with_status(self, status: StatusCode) -> Response {
+        let mut response = (status, AxumJson(self)).into_response();
+        response.extensions_mut().insert(RawResponse);
+        response
+    }
+
     // Методы для установки флагов
     pub fn redirect(mut self) -> Self { self.redirect = true; self }
     pub fn notify(mut self) -> Self { self.notification = true; self }
@@ -175,6 +186,85 @@ impl From<ErrorCode> for BadResponseObject {
     }
 }
 
+impl From<SessionError> for BadResponseObject {
+    fn from(err: SessionError) -> Self {
+        match err {
+            SessionError::SessionNotFound(session_id) => ErrorCode::SessionNotFound.details()
+                .with("session_id", session_id),
+            SessionError::SessionAlreadyExists(session_id) => ErrorCode::SessionAlreadyExists.details()
+                .with("session_id", session_id),
+            SessionError::TrackNotFound { session_id, track_id } => ErrorCode::NotFoundError.details()
+                .with("reason", "Track not found")
+                .with("session_id", session_id)
+                .with("track_id", track_id),
+            SessionError::TrackAlreadyExists { session_id, track_id } => ErrorCode::NameAlreadyExists.details()
+                .with("reason", "Track already exists")
+                .with("session_id", session_id)
+                .with("track_id", track_id),
+            SessionError::SlotOccupied { track_id, role: TrackRole::Instrumental } => ErrorCode::InstrumentalTrackExists.details()
+                .with("track_id", track_id),
+            SessionError::SlotOccupied { track_id, role: TrackRole::Vocal } => ErrorCode::VocalTrackExists.details()
+                .with("track_id", track_id),
+            SessionError::SlotBusy { track_id, role } => ErrorCode::UploadInProgress.details()
+                .with("track_id", track_id)
+                .with("role", role.as_str()),
+            SessionError::Contended { track_id } => ErrorCode::TooManyRequestsError.details()
+                .with("reason", "Track is being changed by another request, retry later")
+                .with("track_id", track_id),
+            SessionError::TrackFinalized { track_id } => ErrorCode::ProjectLocked.details()
+                .with("reason", "Track is finalized")
+                .with("track_id", track_id),
+            SessionError::TrackNotReady { track_id, reason } => ErrorCode::ValidationError.details()
+                .with("reason", reason)
+                .with("track_id", track_id),
+            SessionError::Redis(_) | SessionError::Serialization(_) => {
+                tracing::error!("Session registry error: {}", err);
+                ErrorCode::InternalError.details()
+            }
+        }
+    }
+}
+
+impl From<ProgressError> for BadResponseObject {
+    fn from(err: ProgressError) -> Self {
+        match err {
+            ProgressError::UnknownUpload(upload_id) => ErrorCode::NotFoundError.details()
+                .with("reason", "Upload not found")
+                .with("upload_id", upload_id),
+            ProgressError::UploadIdInUse(upload_id) => ErrorCode::ValidationError.details()
+                .with("reason", "Upload id is already in use")
+                .with("upload_id", upload_id),
+        }
+    }
+}
+
+impl From<LinkError> for BadResponseObject {
+    fn from(err: LinkError) -> Self {
+        match err {
+            LinkError::Redis(_) => {
+                tracing::error!("Upload link check failed: {}", err);
+                ErrorCode::InternalError.details()
+            }
+            _ => ErrorCode::AuthorizeError.details().with("reason", err.to_string()),
+        }
+    }
+}
+
+impl From<AuthError> for BadResponseObject {
+    fn from(err: AuthError) -> Self {
+        match err {
+            AuthError::Expired => ErrorCode::UserExpiredSignatureError.details(),
+            AuthError::Inactive => ErrorCode::InactiveUser.details(),
+            AuthError::InvalidToken(_) | AuthError::KeyNotFound(_) => ErrorCode::CouldNotValidateUserCreds.details()
+                .with("reason", err.to_string()),
+            AuthError::Config(_) => {
+                tracing::error!("Bearer token check failed: {}", err);
+                ErrorCode::InternalError.details()
+            }
+        }
+    }
+}
+
 //---------------------------------------------------------------------------
 // Упрощенная функция очистки сообщения об ошибке
 fn clean_error_message(message: &str) -> String {
@@ -186,6 +276,11 @@ fn clean_error_message(message: &str) -> String {
 }
 
 
+/// Маркер в extensions ответа: обработчик сам выставил статус и заголовки
+/// (например, по протоколу tus), глобальный обработчик их не переписывает
+#[derive(Debug, Clone, Copy)]
+pub struct RawResponse;
+
 // Упрощенный глобальный обработчик ошибок
 pub async fn global_error_handler(
     request: Request,
@@ -195,8 +290,8 @@ pub async fn global_error_handler(
     let response = next.run(request).await;
 
 
-    // Если ответ успешный - просто возвращаем
-    if response.status().is_success() {
+    // Если ответ успешный или помечен как готовый - просто возвращаем
+    if response.status().is_success() || response.extensions().get::<RawResponse>().is_some() {
         return Ok(response);
     }
 
@@ -358,6 +453,8 @@ define_error_codes! {
     AvailableEditsLimitExceeded => 4043, "Available edits limit exceeded";
     NameAlreadyExists => 4044, "This name already exists";
     InstrumentalTrackExists => 4045, "Instrumental track already exists";
+    VocalTrackExists => 4046, "Vocal track already exists";
+    UploadInProgress => 4047, "Upload into this slot is already in progress";
 
     // 4061 - 4081: Task Management Errors
     TaskNotFound => 4061, "Task not found";
@@ -373,6 +470,8 @@ define_error_codes! {
 
     // 4401-4500: General Validation Errors
     WrongFormat => 4411, "Wrong format";
+    ChecksumMismatch => 4412, "Checksum mismatch";
+    TrackPairMismatch => 4413, "Vocal and instrumental tracks do not match";
 
     // 4501 - 4508: API and Request Errors
     PayloadTooLarge => 4513, "Payload too large";
@@ -388,10 +487,12 @@ define_error_codes! {
     BrideError => 5010, "Bride in prison";
     CoreOffline => 5021, "Core is offline";
     CoreFileUploadingError => 5022, "Core file uploading error";
+    InsufficientStorage => 5023, "Not enough disk space";
 
     // 5041-5060: Database Errors
     DbError => 5041, "Bad Gateway";
 
     // 5061 - 5999: System and Server Errors
+    ShuttingDown => 5061, "Server is shutting down";
     UnknownError => 5999, "Internal Server Error";
 }
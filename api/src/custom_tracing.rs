@@ -1,11 +1,12 @@
 // src/logging.rs
+use std::convert::Infallible;
 use std::net::IpAddr;
 use std::time::Duration;
 
 use axum::{
     body::Body,
-    extract::ConnectInfo,
-    http::{HeaderMap, Request, StatusCode},
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, Extensions, HeaderMap, Request, StatusCode},
     response::Response,
     middleware::Next,
 };
@@ -16,6 +17,7 @@ use tower_http::classify::{ServerErrorsAsFailures, ServerErrorsFailureClass, Sha
 use tower_http::trace::TraceLayer;
 use tracing::{Span};
 use std::net::SocketAddr;
+use my_core::config::CONFIG;
 use std::sync::Arc;
 
 use std::fmt::Debug;
@@ -32,80 +34,114 @@ pub struct RealIp(pub Option<IpAddr>);
 
 impl From<&Request<Body>> for RealIp {
     fn from(req: &Request<Body>) -> Self {
-        // Порядок проверки:
-        // 1. X-Real-IP заголовок
-        if let Some(real_ip) = extract_ip_from_header(req, "x-real-ip") {
-            return RealIp(Some(real_ip));
-        }
+        RealIp(resolve_real_ip(req.headers(), req.extensions()))
+    }
+}
 
-        // 2. Forwarded заголовок (RFC 7239)
-        if let Some(real_ip) = extract_ip_from_forwarded(req) {
-            return RealIp(Some(real_ip));
-        }
+/// Экстрактор для обработчиков: `RealIp(Some(ip))` или `RealIp(None)`,
+/// если адрес соединения неизвестен
+impl<S: Send + Sync> FromRequestParts<S> for RealIp {
+    type Rejection = Infallible;
 
-        // 3. X-Forwarded-For заголовок
-        if let Some(real_ip) = extract_ip_from_x_forwarded_for(req) {
-            return RealIp(Some(real_ip));
-        }
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(RealIp(resolve_real_ip(&parts.headers, &parts.extensions)))
+    }
+}
 
-        // 4. Оригинальный IP из ConnectInfo
-        if let Some(ip) = extract_ip_from_connect_info(req) {
-            return RealIp(Some(ip));
-        }
+/// Адрес клиента. Заголовки прокси учитываются, только если соединение пришло
+/// от доверенного прокси (`Config::trusted_proxies`). Цепочка адресов проходится
+/// справа налево, пока адреса принадлежат доверенным прокси; первый недоверенный
+/// адрес и есть клиент
+fn resolve_real_ip(headers: &HeaderMap, extensions: &Extensions) -> Option<IpAddr> {
+    let peer = extract_ip_from_connect_info(extensions)?;
+    Some(resolve_client(peer, headers, |ip| CONFIG.is_trusted_proxy(ip)))
+}
 
-        RealIp(None)
+/// Адрес клиента за соединением от `peer`; `is_trusted` - принадлежит ли адрес доверенному прокси
+fn resolve_client(peer: IpAddr, headers: &HeaderMap, is_trusted: impl Fn(IpAddr) -> bool) -> IpAddr {
+    if !is_trusted(peer) {
+        return peer;
+    }
+
+    // Порядок проверки: Forwarded (RFC 7239), X-Forwarded-For, X-Real-IP
+    let mut chain = extract_chain_from_forwarded(headers);
+    if chain.is_empty() {
+        chain = extract_chain_from_x_forwarded_for(headers);
+    }
+    if chain.is_empty() {
+        chain = extract_ip_from_header(headers, "x-real-ip").into_iter().map(Some).collect();
     }
+
+    let mut client = peer;
+    for hop in chain.into_iter().rev() {
+        if !is_trusted(client) {
+            break;
+        }
+        // Неразборчивый адрес (`unknown`, обфусцированный) дальше не проверить
+        match hop {
+            Some(ip) => client = ip,
+            None => break,
+        }
+    }
+    client
 }
 
 /// Извлекает IP из обычного заголовка
-fn extract_ip_from_header(req: &Request<Body>, header_name: &str) -> Option<IpAddr> {
-    req.headers()
+fn extract_ip_from_header(headers: &HeaderMap, header_name: &str) -> Option<IpAddr> {
+    headers
         .get(header_name)
         .and_then(|value| value.to_str().ok())
-        .and_then(|value| value.parse::<IpAddr>().ok())
+        .and_then(parse_hop)
 }
 
-/// Извлекает IP из заголовка Forwarded (RFC 7239)
-fn extract_ip_from_forwarded(req: &Request<Body>) -> Option<IpAddr> {
-    req.headers()
-        .get("forwarded")
-        .and_then(|value| value.to_str().ok())
-        .and_then(|value| rfc7239::parse(value).collect::<Result<Vec<_>, _>>().ok())
-        .and_then(|forwarded| {
-            forwarded
-                .into_iter()
-                .find_map(|item| match item.forwarded_for {
-                    Some(NodeIdentifier {
-                             name: NodeName::Ip(ip_addr),
-                             ..
-                         }) => Some(ip_addr),
-                    _ => None,
-                })
+/// Цепочка адресов `for=` из заголовков Forwarded (RFC 7239), от клиента к последнему прокси
+fn extract_chain_from_forwarded(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers
+        .get_all("forwarded")
+        .iter()
+        .flat_map(|value| {
+            let elements = value.to_str().ok()
+                .and_then(|value| rfc7239::parse(value).collect::<Result<Vec<_>, _>>().ok());
+            // Испорченный заголовок - неразборчивый адрес
+            elements.map_or_else(|| vec![None], |elements| {
+                elements
+                    .into_iter()
+                    .filter(|item| item.forwarded_for.is_some())
+                    .map(|item| match item.forwarded_for {
+                        Some(NodeIdentifier { name: NodeName::Ip(ip_addr), .. }) => Some(ip_addr),
+                        _ => None,
+                    })
+                    .collect()
+            })
         })
+        .collect()
 }
 
-/// Извлекает IP из заголовка X-Forwarded-For
-fn extract_ip_from_x_forwarded_for(req: &Request<Body>) -> Option<IpAddr> {
-    req.headers()
-        .get("x-forwarded-for")
-        .and_then(|value| value.to_str().ok())
-        .and_then(|value| {
-            value
-                .split(',')
-                .map(|value| value.trim())
-                .find_map(|value| value.parse::<IpAddr>().ok())
+/// Цепочка адресов из заголовков X-Forwarded-For, от клиента к последнему прокси
+fn extract_chain_from_x_forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .flat_map(|value| match value.to_str() {
+            Ok(value) => value.split(',').map(parse_hop).collect::<Vec<_>>(),
+            Err(_) => vec![None],
         })
+        .collect()
+}
+
+/// Адрес из цепочки прокси: голый IP или IP с портом (`1.2.3.4:80`, `[::1]:80`)
+fn parse_hop(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    value.parse::<IpAddr>()
+        .or_else(|_| value.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()
 }
 
 /// Извлекает IP из ConnectInfo
-fn extract_ip_from_connect_info(req: &Request<Body>) -> Option<IpAddr> {
-    if let Some(ConnectInfo(SocketAddr::V4(addr))) = req.extensions().get::<ConnectInfo<SocketAddr>>() {
-        return Some(IpAddr::V4(*addr.ip()));
-    }
-    if let Some(ConnectInfo(SocketAddr::V6(addr))) = req.extensions().get::<ConnectInfo<SocketAddr>>() {
-        return Some(IpAddr::V6(*addr.ip()));
-    }
-    None
+fn extract_ip_from_connect_info(extensions: &Extensions) -> Option<IpAddr> {
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical())
 }
 
 /// Возвращает реальный IP клиента
@@ -271,3 +307,75 @@ fn on_failure(error: ServerErrorsFailureClass, latency: Duration, span: &Span) {
 
     tracing::warn!(target: "http_failure", "\"http_failure\":{}", safe_json_to_string(log));
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use super::*;
+
+    /// Доверенные прокси - сеть 10.0.0.0/8
+    fn is_trusted(ip: IpAddr) -> bool {
+        matches!(ip, IpAddr::V4(ip) if ip.octets()[0] == 10)
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    fn request_headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn headers_from_untrusted_peer_are_ignored() {
+        let headers = request_headers(&[("x-forwarded-for", "1.1.1.1"), ("x-real-ip", "2.2.2.2")]);
+        assert_eq!(resolve_client(ip("8.8.8.8"), &headers, is_trusted), ip("8.8.8.8"));
+    }
+
+    #[test]
+    fn chain_is_walked_right_to_left_past_trusted_proxies() {
+        // Клиент подставил 6.6.6.6 сам, 5.5.5.5 добавил первый доверенный прокси
+        let headers = request_headers(&[("x-forwarded-for", "6.6.6.6, 5.5.5.5, 10.0.0.2")]);
+        assert_eq!(resolve_client(ip("10.0.0.1"), &headers, is_trusted), ip("5.5.5.5"));
+
+        // Цепочка из одних доверенных прокси дает самый левый адрес
+        let headers = request_headers(&[("x-forwarded-for", "10.0.0.3, 10.0.0.2")]);
+        assert_eq!(resolve_client(ip("10.0.0.1"), &headers, is_trusted), ip("10.0.0.3"));
+    }
+
+    #[test]
+    fn several_headers_form_one_chain() {
+        let headers = request_headers(&[("x-forwarded-for", "6.6.6.6"), ("x-forwarded-for", "5.5.5.5, 10.0.0.2")]);
+        assert_eq!(resolve_client(ip("10.0.0.1"), &headers, is_trusted), ip("5.5.5.5"));
+    }
+
+    #[test]
+    fn forwarded_takes_precedence_and_accepts_ports() {
+        let headers = request_headers(&[
+            ("forwarded", r#"for=6.6.6.6, for="[2001:db8::1]:4711", for=10.0.0.2"#),
+            ("x-forwarded-for", "7.7.7.7"),
+        ]);
+        assert_eq!(resolve_client(ip("10.0.0.1"), &headers, is_trusted), ip("2001:db8::1"));
+    }
+
+    #[test]
+    fn unreadable_hop_stops_the_walk() {
+        // За неразборчивым адресом клиент не определить, остается последний доверенный
+        let headers = request_headers(&[("forwarded", "for=6.6.6.6, for=unknown, for=10.0.0.2")]);
+        assert_eq!(resolve_client(ip("10.0.0.1"), &headers, is_trusted), ip("10.0.0.2"));
+
+        let headers = request_headers(&[("x-forwarded-for", "6.6.6.6, garbage")]);
+        assert_eq!(resolve_client(ip("10.0.0.1"), &headers, is_trusted), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn x_real_ip_is_the_last_resort() {
+        let headers = request_headers(&[("x-real-ip", "5.5.5.5")]);
+        assert_eq!(resolve_client(ip("10.0.0.1"), &headers, is_trusted), ip("5.5.5.5"));
+        assert_eq!(resolve_client(ip("10.0.0.1"), &HeaderMap::new(), is_trusted), ip("10.0.0.1"));
+    }
+}
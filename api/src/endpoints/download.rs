@@ -0,0 +1,405 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::DateTime;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::auth::AuthUser;
+use crate::custom_exceptions::{BadResponseObject, ErrorCode, RawResponse};
+use crate::endpoints::files::{validate_ids, UploadTarget};
+use crate::endpoints::links::{authorize, authorize_bearer, LinkQuery};
+use my_core::config::CONFIG;
+use services::AppState;
+use services::storage::{ByteRange, ObjectMeta, StorageError};
+use services::storage::key::{decode_metadata_value, ORIGINAL_FILENAME_METADATA};
+
+
+const TAG: &str = "Download";
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+pub fn get_router(app_state: Arc<AppState>) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(download_file))
+        .with_state(app_state)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DownloadQuery {
+    /// Ключ объекта в бакете загрузок
+    key: String,
+    /// Отдать как вложение (`attachment`) вместо `inline`.
+    /// Файлы с типом не `audio/*` всегда отдаются как вложение
+    #[serde(default)]
+    attachment: bool,
+}
+
+
+#[utoipa::path(
+    get,
+    path = "/file",
+    tag = TAG,
+    description = "Stream a stored file. Supports Range/If-Range for seeking and ETag/If-None-Match for caching. \
+        Files of a track are allowed with the upload scope or an upload link to the track, \
+        any other key requires the upload scope",
+    params(
+        DownloadQuery,
+        LinkQuery,
+        ("Range" = Option<String>, Header, description = "Single byte range, e.g. `bytes=0-1023`"),
+        ("If-Range" = Option<String>, Header, description = "ETag or Last-Modified the Range is valid for"),
+        ("If-None-Match" = Option<String>, Header, description = "ETags the client already has"),
+    ),
+    responses(
+        (status = 200, description = "Whole file", body = Vec<u8>, content_type = "application/octet-stream"),
+        (status = 206, description = "Requested range", body = Vec<u8>, content_type = "application/octet-stream"),
+        (status = 304, description = "Client copy is up to date"),
+        (status = 403, description = "Access to the file is not allowed", body = BadResponseObject),
+        (status = 404, description = "File not found", body = BadResponseObject),
+        (status = 416, description = "Range not satisfiable"),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn download_file(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Query(query): Query<DownloadQuery>,
+    Query(link): Query<LinkQuery>,
+    headers: HeaderMap,
+) -> Response {
+    // Доступ проверяется до обращения к хранилищу, чтобы не раскрывать, есть ли объект
+    let user = user.as_ref().map(|AuthUser(claims)| claims);
+    let access = match key_target(&query.key) {
+        Some(target) => authorize(&app_state, user, link.token.as_deref(), &target, &[]).map(|_| ()),
+        None => authorize_bearer(&app_state, user),
+    };
+    if let Err(err) = access {
+        return err.into_response_with_status(StatusCode::FORBIDDEN);
+    }
+
+    let storage = app_state.storage.as_ref();
+    let bucket = CONFIG.upload_bucket_name.as_str();
+
+    let meta = match storage.head_object(bucket, &query.key).await {
+        Ok(Some(meta)) => meta,
+        Ok(None) | Err(StorageError::InvalidKey(_)) => return not_found(&query.key),
+        Err(err) => return internal_error(&query.key, err),
+    };
+
+    let mut response_headers = object_headers(&meta, query.attachment);
+
+    if not_modified(&headers, &meta) {
+        return raw_response(StatusCode::NOT_MODIFIED, response_headers, Body::empty());
+    }
+
+    let range = match headers.get(header::RANGE) {
+        Some(range) if if_range_matches(&headers, &meta) => parse_range(range, meta.size),
+        _ => RangeRequest::Full,
+    };
+
+    let (status, object) = match range {
+        RangeRequest::Full => (StatusCode::OK, storage.get_object(bucket, &query.key).await),
+        RangeRequest::Partial(range) => {
+            let content_range = format!("bytes {}-{}/{}", range.start, range.end, meta.size);
+            response_headers.insert(header::CONTENT_RANGE, HeaderValue::from_str(&content_range).unwrap());
+            (StatusCode::PARTIAL_CONTENT, storage.get_object_range(bucket, &query.key, range).await)
+        }
+        RangeRequest::Unsatisfiable => {
+            let content_range = format!("bytes */{}", meta.size);
+            response_headers.insert(header::CONTENT_RANGE, HeaderValue::from_str(&content_range).unwrap());
+            return raw_response(StatusCode::RANGE_NOT_SATISFIABLE, response_headers, Body::empty());
+        }
+    };
+
+    let object = match object {
+        Ok(object) => object,
+        Err(StorageError::ObjectNotFound { .. }) => return not_found(&query.key),
+        Err(err) => return internal_error(&query.key, err),
+    };
+
+    response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(object.meta.size));
+    raw_response(status, response_headers, Body::from_stream(object.body))
+}
+
+
+/// Разобранный заголовок `Range`
+#[derive(Debug, PartialEq)]
+enum RangeRequest {
+    Full,
+    Partial(ByteRange),
+    Unsatisfiable,
+}
+
+/// Поддерживается один диапазон `bytes=a-b`, `bytes=a-` или `bytes=-n`.
+/// Несколько диапазонов и непонятный синтаксис игнорируются (RFC 9110 это разрешает)
+fn parse_range(value: &HeaderValue, size: u64) -> RangeRequest {
+    let Some(spec) = value.to_str().ok().and_then(|value| value.trim().strip_prefix("bytes=")) else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let range = match (start.parse::<u64>().ok(), end.parse::<u64>().ok()) {
+        // Последние n байт
+        (None, Some(suffix)) if start.is_empty() => {
+            if suffix == 0 || size == 0 {
+                return RangeRequest::Unsatisfiable;
+            }
+            ByteRange { start: size.saturating_sub(suffix), end: size - 1 }
+        }
+        (Some(start), None) if end.is_empty() => ByteRange { start, end: size.saturating_sub(1) },
+        (Some(start), Some(end)) if start <= end => ByteRange { start, end: end.min(size.saturating_sub(1)) },
+        _ => return RangeRequest::Full,
+    };
+
+    if range.start >= size {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Partial(range)
+}
+
+/// `If-None-Match`: клиент уже имеет актуальную версию (слабое сравнение ETag)
+fn not_modified(headers: &HeaderMap, meta: &ObjectMeta) -> bool {
+    let (Some(value), Some(e_tag)) = (headers.get(header::IF_NONE_MATCH), meta.e_tag.as_deref()) else {
+        return false;
+    };
+    let Ok(value) = value.to_str() else { return false };
+
+    value.trim() == "*" || value.split(',').any(|candidate| weak_tag(candidate) == weak_tag(e_tag))
+}
+
+/// `If-Range`: диапазон применяется, только если объект не изменился.
+/// ETag сравнивается строго, дата - на точное совпадение с Last-Modified
+fn if_range_matches(headers: &HeaderMap, meta: &ObjectMeta) -> bool {
+    let Some(value) = headers.get(header::IF_RANGE) else { return true };
+    let Ok(value) = value.to_str() else { return false };
+    let value = value.trim();
+
+    if value.starts_with('"') || value.starts_with("W/") {
+        return !value.starts_with("W/") && meta.e_tag.as_deref() == Some(value);
+    }
+    match (DateTime::parse_from_rfc2822(value), meta.last_modified) {
+        (Ok(date), Some(last_modified)) => date.timestamp() == last_modified.timestamp(),
+        _ => false,
+    }
+}
+
+fn weak_tag(tag: &str) -> &str {
+    let tag = tag.trim();
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+fn object_headers(meta: &ObjectMeta, attachment: bool) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+
+    // Inline отдается только аудио: любой другой тип из хранилища мог бы
+    // выполниться в браузере на домене API
+    let audio_type = meta.content_type.as_deref()
+        .filter(|content_type| content_type.to_ascii_lowercase().starts_with("audio/"))
+        .and_then(|content_type| HeaderValue::from_str(content_type).ok());
+    let inline = audio_type.is_some() && !attachment;
+    headers.insert(
+        header::CONTENT_TYPE,
+        audio_type.unwrap_or(HeaderValue::from_static("application/octet-stream")),
+    );
+    if let Some(value) = meta.e_tag.as_deref().and_then(|e_tag| HeaderValue::from_str(e_tag).ok()) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Some(last_modified) = meta.last_modified {
+        let value = last_modified.format(HTTP_DATE_FORMAT).to_string();
+        headers.insert(header::LAST_MODIFIED, HeaderValue::from_str(&value).unwrap());
+    }
+
+    // Ключ строится по шаблону и обычно содержит ULID, исходное имя хранится в метаданных
+    let file_name = match meta.metadata.get(ORIGINAL_FILENAME_METADATA) {
+        Some(original) => decode_metadata_value(original),
+        None => meta.key.rsplit('/').next().unwrap_or(&meta.key).to_string(),
+    };
+    let disposition = content_disposition(if inline { "inline" } else { "attachment" }, &file_name);
+    if let Ok(value) = HeaderValue::from_str(&disposition) {
+        headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+
+    headers
+}
+
+/// `Content-Disposition` с ASCII-именем для старых клиентов и полным
+/// UTF-8 именем в `filename*` по RFC 5987
+fn content_disposition(disposition: &str, file_name: &str) -> String {
+    let fallback: String = file_name
+        .chars()
+        .map(|char| if char.is_ascii_graphic() && char != '"' && char != '\\' || char == ' ' { char } else { '_' })
+        .collect();
+
+    let mut encoded = String::with_capacity(file_name.len() * 3);
+    for byte in file_name.bytes() {
+        // attr-char из RFC 5987 передается как есть, остальное - %XX
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    format!("{disposition}; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
+
+/// Трек, которому принадлежит ключ: шаблон ключей всегда начинается
+/// с `{session_id}/{track_id}/`
+fn key_target(key: &str) -> Option<UploadTarget> {
+    let mut segments = key.splitn(3, '/');
+    let (session_id, track_id, _) = (segments.next()?, segments.next()?, segments.next()?);
+    validate_ids(&[("session_id", session_id), ("track_id", track_id)]).ok()?;
+    Some(UploadTarget { session_id: session_id.to_string(), track_id: track_id.to_string() })
+}
+
+fn raw_response(status: StatusCode, headers: HeaderMap, body: Body) -> Response {
+    let mut response = (status, headers, body).into_response();
+    response.extensions_mut().insert(RawResponse);
+    response
+}
+
+fn not_found(key: &str) -> Response {
+    ErrorCode::NotFoundError.details()
+        .with("key", key)
+        .into_response_with_status(StatusCode::NOT_FOUND)
+}
+
+fn internal_error(key: &str, err: StorageError) -> Response {
+    tracing::error!("Failed to download {}: {}", key, err);
+    ErrorCode::InternalError.details().into_response_with_status(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use services::storage::key::encode_metadata_value;
+    use super::*;
+
+    fn range(value: &str, size: u64) -> RangeRequest {
+        parse_range(&HeaderValue::from_str(value).unwrap(), size)
+    }
+
+    fn partial(start: u64, end: u64) -> RangeRequest {
+        RangeRequest::Partial(ByteRange { start, end })
+    }
+
+    #[test]
+    fn single_ranges_are_clamped_to_the_object() {
+        assert_eq!(range("bytes=0-99", 1000), partial(0, 99));
+        assert_eq!(range(" bytes=10-", 1000), partial(10, 999));
+        assert_eq!(range("bytes=-100", 1000), partial(900, 999));
+        // Конец и суффикс длиннее объекта обрезаются
+        assert_eq!(range("bytes=990-2000", 1000), partial(990, 999));
+        assert_eq!(range("bytes=-5000", 1000), partial(0, 999));
+        assert_eq!(range("bytes=999-999", 1000), partial(999, 999));
+    }
+
+    #[test]
+    fn ranges_outside_the_object_are_unsatisfiable() {
+        assert_eq!(range("bytes=1000-", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(range("bytes=1000-1001", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+        assert_eq!(range("bytes=-10", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn unsupported_syntax_serves_the_whole_object() {
+        for value in ["bytes=0-1,5-6", "items=0-1", "bytes=5-1", "bytes=a-b", "bytes=-", "bytes=10", "0-1"] {
+            assert_eq!(range(value, 1000), RangeRequest::Full, "{value}");
+        }
+    }
+
+    fn meta() -> ObjectMeta {
+        ObjectMeta {
+            key: "s/t/vocal-01jq3v6k2m8e4x0y9z7w5t1r3s.wav".to_string(),
+            size: 1000,
+            e_tag: Some("\"abc\"".to_string()),
+            last_modified: Some(Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap()),
+            ..Default::default()
+        }
+    }
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        HeaderMap::from_iter([(name, HeaderValue::from_str(value).unwrap())])
+    }
+
+    #[test]
+    fn if_range_requires_a_strong_match() {
+        assert!(if_range_matches(&HeaderMap::new(), &meta()));
+        assert!(if_range_matches(&headers(header::IF_RANGE, "\"abc\""), &meta()));
+        assert!(!if_range_matches(&headers(header::IF_RANGE, "W/\"abc\""), &meta()));
+        assert!(!if_range_matches(&headers(header::IF_RANGE, "\"other\""), &meta()));
+        assert!(if_range_matches(&headers(header::IF_RANGE, "Sat, 01 Mar 2025 12:00:00 GMT"), &meta()));
+        assert!(!if_range_matches(&headers(header::IF_RANGE, "Sat, 01 Mar 2025 12:00:01 GMT"), &meta()));
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        assert!(not_modified(&headers(header::IF_NONE_MATCH, "W/\"abc\""), &meta()));
+        assert!(not_modified(&headers(header::IF_NONE_MATCH, "\"x\", \"abc\""), &meta()));
+        assert!(not_modified(&headers(header::IF_NONE_MATCH, "*"), &meta()));
+        assert!(!not_modified(&headers(header::IF_NONE_MATCH, "\"x\""), &meta()));
+        assert!(!not_modified(&HeaderMap::new(), &meta()));
+    }
+
+    #[test]
+    fn disposition_prefers_the_original_file_name() {
+        let headers = object_headers(&meta(), true);
+        assert_eq!(
+            headers[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"vocal-01jq3v6k2m8e4x0y9z7w5t1r3s.wav\"; filename*=UTF-8''vocal-01jq3v6k2m8e4x0y9z7w5t1r3s.wav",
+        );
+
+        let mut meta = meta();
+        meta.content_type = Some("audio/wav".to_string());
+        meta.metadata.insert(ORIGINAL_FILENAME_METADATA.to_string(), encode_metadata_value("Мой \"трек\".wav"));
+        let headers = object_headers(&meta, false);
+        assert_eq!(
+            headers[header::CONTENT_DISPOSITION],
+            "inline; filename=\"___ ______.wav\"; filename*=UTF-8''%D0%9C%D0%BE%D0%B9%20%22%D1%82%D1%80%D0%B5%D0%BA%22.wav",
+        );
+    }
+
+    #[test]
+    fn only_audio_is_served_inline() {
+        let mut meta = meta();
+        meta.content_type = Some("audio/flac".to_string());
+        let headers = object_headers(&meta, false);
+        assert_eq!(headers[header::CONTENT_TYPE], "audio/flac");
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert!(headers[header::CONTENT_DISPOSITION].to_str().unwrap().starts_with("inline;"));
+
+        for content_type in [Some("text/html"), Some("application/javascript"), Some("image/svg+xml"), None] {
+            meta.content_type = content_type.map(ToString::to_string);
+            let headers = object_headers(&meta, false);
+            assert_eq!(headers[header::CONTENT_TYPE], "application/octet-stream", "{content_type:?}");
+            assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+            assert!(headers[header::CONTENT_DISPOSITION].to_str().unwrap().starts_with("attachment;"), "{content_type:?}");
+        }
+    }
+
+    #[test]
+    fn only_track_keys_belong_to_a_track() {
+        let target = key_target("s1/t1/vocal-01.wav").unwrap();
+        assert_eq!((target.session_id.as_str(), target.track_id.as_str()), ("s1", "t1"));
+
+        for key in ["s1/vocal.wav", "s1", ".quarantine/s1/t1/vocal.wav", "../t1/vocal.wav", "s1//vocal.wav"] {
+            assert!(key_target(key).is_none(), "{key}");
+        }
+    }
+}
@@ -2,13 +2,14 @@
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use axum::{
     body::Bytes,
-    extract::{Multipart, State},
-
+    extract::{multipart::MultipartError, Multipart, Path as AxumPath, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
 };
 
 use tower_http::{classify::ServerErrorsFailureClass, trace::TraceLayer};
@@ -16,25 +17,112 @@ use tracing::{info_span, Span};
 use bytes::{Bytes as BBytes, BytesMut};
 use crate::custom_exceptions::{JsonResponse, ErrorCode, BadResponseObject};
 use once_cell::sync::Lazy;
+use crate::auth::AuthUser;
+use crate::body_limit::limit_body;
+use crate::endpoints::links::{authorize_upload, LinkQuery};
+use crate::shutdown::track_upload;
 use crate::{json_err, json_opt};
+use futures_util::TryStreamExt;
+use lazy_regex::regex_is_match;
 
-use services::{AppState, s3::S3Manager};
+use services::auth::Claims;
+use services::audio::{validate_pair, AudioError, AudioFormat, AudioMetadata, AudioProbe, PairIssue, PairRules};
+use services::filesystem::{self, AtomicFile, FilesystemError};
+use services::progress::{ProgressTracker, UploadPhase};
+use services::sessions::{SessionRegistry, TrackRole, UploadRecord, UploadStatus};
+use services::shutdown::TrackedMultipart;
+use services::{AppState, storage::{
+    checksum::decode_digest, key::{encode_metadata_value, ORIGINAL_FILENAME_METADATA}, KeyParams, MultipartUpload, MultipartUploadGuard, MultipartUploadOptions, ObjectDigest,
+    ByteRange, ObjectHasher, ObjectStore, PartUploadPipeline,
+}};
+use my_core::config::CONFIG;
+use chrono::Utc;
+use ulid::Ulid;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 
 const TAG: &str = "Upload";
 pub fn get_router(app_state: Arc<AppState>) -> OpenApiRouter {
     OpenApiRouter::new()
-        .routes(routes!(upload_tracks))
-        .routes(routes!(upload_track_single))
+        .merge(OpenApiRouter::new()
+            .routes(routes!(upload_tracks))
+            .route_layer(middleware::from_fn_with_state(UploadLimits::PAIR.request(), limit_body)))
+        .merge(OpenApiRouter::new()
+            .routes(routes!(upload_track_single))
+            .route_layer(middleware::from_fn_with_state(UploadLimits::SINGLE.request(), limit_body)))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&app_state), track_upload))
+        .with_state(app_state)
+}
+
+/// Загрузка в файловую систему сервера, без хранилища объектов
+pub fn get_filesystem_router(app_state: Arc<AppState>) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(upload_filesystem_multiple))
+        .route_layer(middleware::from_fn_with_state(UploadLimits::PAIR.request(), limit_body))
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&app_state), track_upload))
         .with_state(app_state)
 }
 
+/// Сколько байт начала сохраненного объекта читается для разбора заголовка
+const PROBE_HEAD_LEN: u64 = 4 * 1024 * 1024;
 const CHUNK_SIZE: usize = 1024 * 1024 * 20; // 5 MB chunks, adjust as needed
-static ALLOWED_EXTENSIONS: Lazy<Vec<&'static str>> = Lazy::new(|| {
-    vec![".ogg", ".mp3", ".wav", ".flac", ".m4a", "",]
+/// Разрешенные расширения из `upload_allowed_extensions`, с точкой и в нижнем регистре
+pub(crate) static ALLOWED_EXTENSIONS: Lazy<Vec<String>> = Lazy::new(|| {
+    CONFIG.upload_allowed_extensions
+        .iter()
+        .map(|extension| extension.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|extension| !extension.is_empty())
+        .map(|extension| format!(".{extension}"))
+        .collect()
 });
 
+/// Лимиты размера multipart-загрузки: на один файл и на весь запрос маршрута
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct UploadLimits {
+    /// Сколько файлов в форме маршрута
+    files: u64,
+}
+
+impl UploadLimits {
+    pub(crate) const SINGLE: Self = Self { files: 1 };
+    pub(crate) const PAIR: Self = Self { files: 2 };
+    /// Запас на заголовки и границы частей формы сверх размера файлов
+    const FORM_OVERHEAD: u64 = 64 * 1024;
+
+    fn file(&self) -> u64 {
+        CONFIG.upload_max_file_size
+    }
+
+    /// Лимит всего тела запроса маршрута
+    pub(crate) fn request(&self) -> u64 {
+        self.file().saturating_mul(self.files).saturating_add(Self::FORM_OVERHEAD)
+    }
+
+    /// Ошибка чтения формы; превышение лимита запроса - `PayloadTooLarge`
+    fn read_error(&self, err: MultipartError) -> BadResponseObject {
+        if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            return ErrorCode::PayloadTooLarge.details()
+                .with("reason", "Request body is too large")
+                .with("limit", self.request());
+        }
+        tracing::error!("Error reading multipart form: {}", err);
+        ErrorCode::CoreFileUploadingError.details()
+    }
+
+    /// Проверяет, что файл поля `field_name` еще не превысил лимит
+    fn check_file(&self, field_name: &str, size: u64) -> Result<(), BadResponseObject> {
+        if size <= self.file() {
+            return Ok(());
+        }
+        tracing::info!("Aborted upload of field {}: file exceeds {} bytes", field_name, self.file());
+        Err(ErrorCode::PayloadTooLarge.details()
+            .with("reason", "File is too large")
+            .with("field_name", field_name)
+            .with("limit", self.file()))
+    }
+}
+
 /// Just a schema for axum native multipart
 #[derive(Deserialize, ToSchema)]
 #[allow(unused)]
@@ -49,34 +137,175 @@ struct UploadTracksForm {
 #[derive(Deserialize, ToSchema)]
 #[allow(unused)]
 struct UploadTrackForm {
-    path: String,
     #[schema(format = Binary, content_media_type = "application/octet-stream")]
     track: String,
 }
 
 #[derive(Deserialize, Serialize, ToSchema, Default)]
 #[schema(example = json!({
+    "vocal_key": "session_1/track_1/vocal-01jq3v6k2m8e4x0y9z7w5t1r3s.mp3",
     "vocal_name": "vocal.mp3",
     "vocal_size": 1024,
+    "vocal_sha256": "5f70bf18a086007016e948b04aed3b82103a36bea41755b6cddfaf10ace3c6ef",
+    "vocal_audio": {"codec": "mp3", "duration_secs": 183.46, "sample_rate": 44100, "channels": 2, "bits_per_sample": null, "bitrate": 320000},
+    "vocal_metadata_stored": true,
+    "instrumental_key": "session_1/track_1/instrumental-01jq3v6k2n1b5c7d9e0f2g4h6j.mp3",
     "instrumental_name": "instrumental.mp3",
-    "instrumental_size": 1024
+    "instrumental_size": 1024,
+    "instrumental_sha256": "785b0b0f4a4c1d8c8a1e3a0e2a5c1f8e5f1a4b1f8a2c6e1d5b7f2d0e4a9c3b1d",
+    "instrumental_audio": {"codec": "mp3", "duration_secs": 183.46, "sample_rate": 44100, "channels": 2, "bits_per_sample": null, "bitrate": 320000},
+    "instrumental_metadata_stored": true
 }))]
 struct FilesUploadResult {
+    vocal_key: String,
     vocal_name: String,
     vocal_size: u64,
+    vocal_sha256: String,
+    #[schema(value_type = Object)]
+    vocal_audio: AudioMetadata,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vocal_metadata_stored: Option<bool>,
+    instrumental_key: String,
     instrumental_name: String,
     instrumental_size: u64,
+    instrumental_sha256: String,
+    #[schema(value_type = Object)]
+    instrumental_audio: AudioMetadata,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instrumental_metadata_stored: Option<bool>,
+}
+
+impl FilesUploadResult {
+    fn new(vocal: FileUploadResult, instrumental: FileUploadResult) -> Self {
+        Self {
+            vocal_key: vocal.key,
+            vocal_name: vocal.name,
+            vocal_size: vocal.size,
+            vocal_sha256: vocal.sha256,
+            vocal_audio: vocal.audio,
+            vocal_metadata_stored: vocal.metadata_stored,
+            instrumental_key: instrumental.key,
+            instrumental_name: instrumental.name,
+            instrumental_size: instrumental.size,
+            instrumental_sha256: instrumental.sha256,
+            instrumental_audio: instrumental.audio,
+            instrumental_metadata_stored: instrumental.metadata_stored,
+        }
+    }
+}
+
+/// Сессия и трек, к которым относятся загружаемые файлы
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UploadTarget {
+    pub session_id: String,
+    pub track_id: String,
+}
+
+/// Идентификаторы сессии и трека становятся сегментами ключа, поэтому
+/// допускаются только безопасные символы
+pub(crate) fn validate_ids(ids: &[(&str, &str)]) -> Result<(), BadResponseObject> {
+    for (name, value) in ids {
+        if !regex_is_match!(r"^[A-Za-z0-9_-]{1,64}$", value) {
+            return Err(ErrorCode::ValidationError.details()
+                .with("reason", format!("Invalid {name}")));
+        }
+    }
+    Ok(())
+}
+
+/// Трек и слот, в который загружается один файл
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SingleUploadTarget {
+    pub session_id: String,
+    pub track_id: String,
+    /// `vocal` или `instrumental`
+    pub role: String,
 }
 
 /// Результат загрузки файла
 #[derive(Deserialize, Serialize, ToSchema, Default)]
 #[schema(example = json!({
+    "key": "session_1/track_1/track-01jq3v6k2m8e4x0y9z7w5t1r3s.mp3",
     "name": "track.mp3",
     "size": 1024,
+    "sha256": "5f70bf18a086007016e948b04aed3b82103a36bea41755b6cddfaf10ace3c6ef",
+    "audio": {"codec": "flac", "duration_secs": 183.46, "sample_rate": 48000, "channels": 2, "bits_per_sample": 24, "bitrate": 2116800},
+    "metadata_stored": true,
 }))]
 struct FileUploadResult {
+    /// Ключ объекта в хранилище
+    key: String,
     name: String,
     size: u64,
+    /// SHA-256 содержимого в hex
+    sha256: String,
+    /// CRC32C содержимого в hex, если включен `upload_crc32c`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crc32c: Option<String>,
+    /// Параметры аудио: кодек, длительность, частота, каналы, разрядность, битрейт
+    #[schema(value_type = Object)]
+    audio: AudioMetadata,
+    /// Сохранены ли имя файла и параметры аудио в метаданных объекта.
+    /// При `false` файл загружен, но обработке придется разобрать его заново.
+    /// Не передается для загрузок на диск
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata_stored: Option<bool>,
+}
+
+/// Дайджесты файла, присланные клиентом в заголовках
+/// `X-Checksum-Sha256-<поле>` и `X-Checksum-Crc32c-<поле>` (hex или base64)
+#[derive(Default)]
+struct ExpectedDigest {
+    sha256: Option<Vec<u8>>,
+    crc32c: Option<Vec<u8>>,
+}
+
+impl ExpectedDigest {
+    fn from_headers(headers: &HeaderMap, field_name: &str) -> Result<Self, BadResponseObject> {
+        let parse = |algorithm: &str| {
+            let header = format!("x-checksum-{algorithm}-{}", field_name.to_ascii_lowercase());
+            headers
+                .get(header.as_str())
+                .map(|value| {
+                    value.to_str().ok().and_then(decode_digest).ok_or_else(|| {
+                        ErrorCode::ValidationError.details()
+                            .with("reason", "Invalid checksum header")
+                            .with("header", &header)
+                    })
+                })
+                .transpose()
+        };
+
+        Ok(Self {
+            sha256: parse("sha256")?,
+            crc32c: parse("crc32c")?,
+        })
+    }
+
+    /// Сверяет дайджесты; при расхождении возвращает ошибку с обоими значениями
+    fn verify(&self, field_name: &str, digest: &ObjectDigest) -> Result<(), BadResponseObject> {
+        let actual_crc32c = digest.crc32c.map(u32::to_be_bytes);
+        let checks = [
+            ("sha256", self.sha256.as_deref(), Some(&digest.sha256[..]), Some(digest.sha256_hex())),
+            ("crc32c", self.crc32c.as_deref(), actual_crc32c.as_ref().map(|crc| &crc[..]), digest.crc32c_hex()),
+        ];
+
+        for (algorithm, expected, actual, actual_hex) in checks {
+            let Some(expected) = expected else { continue };
+            if Some(expected) != actual {
+                tracing::warn!("Checksum mismatch for field {}: {}", field_name, algorithm);
+                return Err(ErrorCode::ChecksumMismatch.details()
+                    .with("field", field_name)
+                    .with("algorithm", algorithm)
+                    .with("expected", expected.iter().map(|byte| format!("{byte:02x}")).collect::<String>())
+                    .with("actual", actual_hex));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 
@@ -84,7 +313,10 @@ struct FileUploadResult {
     post,
     path = "/upload-tracks",
     tag = TAG,
-    description = "Endpoint for uploading two files: vocal and instrumental",
+    description = "Endpoint for uploading two files: vocal and instrumental. \
+        If the pair does not match (error 4413), neither track slot is filled and the pair has to be uploaded again. \
+        Unless `pair_delete_invalid` is set, the rejected files are kept for manual review under `quarantine_keys`",
+    params(UploadTarget, LinkQuery),
     request_body(content = UploadTracksForm, content_type = "multipart/form-data", description = "Hello guys!"),
     responses(
         (status = 200, body = FilesUploadResult, description = "Tracks uploaded successfully!"),
@@ -94,64 +326,95 @@ struct FileUploadResult {
 )]
 pub async fn upload_tracks(
     State(app_state): State<Arc<AppState>>,
-    mut multipart: Multipart
+    user: Option<AuthUser>,
+    Query(target): Query<UploadTarget>,
+    Query(link): Query<LinkQuery>,
+    headers: HeaderMap,
+    multipart: Multipart
 ) -> JsonResponse {
-    let s3 = &app_state.s3;
-    let bucket = "svaha-mini-input";
+    json_err!(validate_ids(&[("session_id", &target.session_id), ("track_id", &target.track_id)]));
+    json_err!(authorize_upload(&app_state, user.as_ref().map(|AuthUser(claims)| claims), link.token.as_deref(), &target, &TrackRole::ALL).await);
 
-    let mut vocal_result: Option<FileUploadResult> = None;
-    let mut instrumental_result: Option<FileUploadResult> = None;
+    let mut slots = SlotClaims::new(&app_state, &target);
+    let result = upload_pair(&app_state, &target, &headers, multipart, &mut slots).await;
+    if result.is_err() {
+        slots.fail().await;
+    }
+    JsonResponse::Ok(json!(json_err!(result)))
+}
 
+/// Загружает вокал и минус в хранилище; файлы появляются, только если пришли оба
+async fn upload_pair(
+    app_state: &AppState,
+    target: &UploadTarget,
+    headers: &HeaderMap,
+    mut multipart: Multipart,
+    slots: &mut SlotClaims,
+) -> Result<FilesUploadResult, BadResponseObject> {
+    let storage = app_state.storage.as_ref();
+    let bucket = CONFIG.upload_bucket_name.as_str();
 
+    // Файлы остаются незавершенными загрузками, пока не придут оба: при любом
+    // выходе с ошибкой guard'ы отменят их и в хранилище ничего не останется
+    let mut vocal_staged: Option<StagedFile> = None;
+    let mut instrumental_staged: Option<StagedFile> = None;
 
     // Обрабатываем каждую часть формы
-    while let Some(field) = json_err!(multipart.next_field().await.map_err(|_| {
-        ErrorCode::CoreFileUploadingError.details()
-    })) {
+    while let Some(field) = multipart.next_field().await.map_err(|err| UploadLimits::PAIR.read_error(err))? {
         let name = field.name().unwrap_or_default();
-        let file_name = json_err!(field.file_name()
+        let file_name = field.file_name()
             .map(ToString::to_string)
             .ok_or_else(|| {
                 tracing::error!("Missing file name");
                 ErrorCode::ValidationError.details()
-            }));
-
-        let path = "test2".to_string();
-
-        match name {
-            "vocal" => {
-                vocal_result = Some(json_err!(upload_file(s3, bucket, &file_name, &path, field).await));
-                // Читаем чанки данных из поля формы
-                // json_err!(process_chunk(field).await);
+            })?;
 
-            }
-            "instrumental" => {
-                instrumental_result = Some(json_err!(upload_file(s3, bucket, &file_name, &path, field).await));
-                // json_err!(process_chunk(field).await);
-            }
+        let (role, slot) = match name {
+            "vocal" => (TrackRole::Vocal, &mut vocal_staged),
+            "instrumental" => (TrackRole::Instrumental, &mut instrumental_staged),
             _ => {
-                return ErrorCode::CoreFileUploadingError.into();
+                return Err(ErrorCode::CoreFileUploadingError.details()
+                    .with("reason", "Unknown field in multipart form")
+                    .with("field_name", name));
             }
+        };
+        if slot.is_some() {
+            return Err(ErrorCode::CoreFileUploadingError.details()
+                .with("reason", "Duplicate field in multipart form")
+                .with("field_name", name));
         }
+        let file = TrackFile { target, role, filename: &file_name, limits: UploadLimits::PAIR };
+        *slot = Some(stage_file(app_state, file, slots, headers, field).await?);
     }
 
     // Проверяем, что оба файла загружены
-    if vocal_result.is_none() || instrumental_result.is_none() {
-        return ErrorCode::CoreFileUploadingError.into();
-    }
+    let (Some(vocal), Some(instrumental)) = (vocal_staged, instrumental_staged) else {
+        return Err(ErrorCode::CoreFileUploadingError.details()
+            .with("reason", "Both vocal and instrumental files are required"));
+    };
 
-    // Формируем ответ
-    let vocal = vocal_result.unwrap();
-    let instrumental = instrumental_result.unwrap();
+    // Проверяем, что вокал и минус подходят друг к другу. Негодная пара
+    // в слоты трека не попадает; без удаления она переносится в карантин,
+    // чтобы ее можно было разобрать вручную
+    let issues = pair_issues(&vocal.result.audio, &instrumental.result.audio);
+    if !issues.is_empty() {
+        tracing::warn!("Rejected track pair {} / {}: {:?}", vocal.result.key, instrumental.result.key, issues);
+        let quarantined = if CONFIG.pair_delete_invalid {
+            vocal.upload.abort().await;
+            instrumental.upload.abort().await;
+            None
+        } else {
+            let (vocal, instrumental) = promote_pair(storage, bucket, vocal, instrumental).await?;
+            Some(quarantine_pair(storage, bucket, &vocal.key, &instrumental.key).await?)
+        };
+        return Err(pair_mismatch(issues, quarantined));
+    }
 
-    let response = FilesUploadResult {
-        vocal_name: vocal.name,
-        vocal_size: vocal.size,
-        instrumental_name: instrumental.name,
-        instrumental_size: instrumental.size,
-    };
+    let (vocal, instrumental) = promote_pair(storage, bucket, vocal, instrumental).await?;
+    slots.complete(app_state, target, TrackRole::Vocal, &vocal).await?;
+    slots.complete(app_state, target, TrackRole::Instrumental, &instrumental).await?;
 
-    JsonResponse::Ok(json!(response))
+    Ok(FilesUploadResult::new(vocal, instrumental))
 }
 
 
@@ -159,7 +422,8 @@ pub async fn upload_tracks(
     post,
     path = "/upload-track-single",
     tag = TAG,
-    description = "Endpoint for uploading one file",
+    description = "Endpoint for uploading one file into the vocal or instrumental slot of a track",
+    params(SingleUploadTarget, LinkQuery),
     request_body(content = UploadTrackForm, content_type = "multipart/form-data", description = "Upload file body"),
     responses(
         (status = 200, body = FileUploadResult, description = "Track uploaded successfully!"),
@@ -169,141 +433,836 @@ pub async fn upload_tracks(
 )]
 pub async fn upload_track_single(
     State(app_state): State<Arc<AppState>>,
-    mut multipart: Multipart
+    user: Option<AuthUser>,
+    Query(query): Query<SingleUploadTarget>,
+    Query(link): Query<LinkQuery>,
+    headers: HeaderMap,
+    multipart: Multipart
 ) -> JsonResponse {
-    let s3 = &app_state.s3;
-    let bucket = "svaha-mini-input";
+    let target = UploadTarget { session_id: query.session_id, track_id: query.track_id };
+    let role = json_opt!(
+        TrackRole::from_name(&query.role),
+        ErrorCode::ValidationError.details()
+            .with("reason", "Role must be vocal or instrumental")
+            .with("role", &query.role)
+    );
+    let user = user.as_ref().map(|AuthUser(claims)| claims);
+    upload_into_track(&app_state, &target, role, user, link.token.as_deref(), &headers, multipart).await
+}
 
-    let mut result: FileUploadResult = FileUploadResult::default();
-    let mut path: String = "test".to_string(); // Значение по умолчанию
-    let mut filename: Option<String> = None;
+/// Загружает один файл в слот `role` трека и отмечает результат в реестре сессий
+pub(crate) async fn upload_into_track(
+    app_state: &AppState,
+    target: &UploadTarget,
+    role: TrackRole,
+    user: Option<&Claims>,
+    token: Option<&str>,
+    headers: &HeaderMap,
+    multipart: Multipart,
+) -> JsonResponse {
+    json_err!(validate_ids(&[("session_id", &target.session_id), ("track_id", &target.track_id)]));
+    json_err!(authorize_upload(app_state, user, token, target, &[role]).await);
+
+    let mut slots = SlotClaims::new(app_state, target);
+    let result = upload_single(app_state, target, role, headers, multipart, &mut slots).await;
+    if result.is_err() {
+        slots.fail().await;
+    }
+    JsonResponse::Ok(json!(json_err!(result)))
+}
+
+async fn upload_single(
+    app_state: &AppState,
+    target: &UploadTarget,
+    role: TrackRole,
+    headers: &HeaderMap,
+    mut multipart: Multipart,
+    slots: &mut SlotClaims,
+) -> Result<FileUploadResult, BadResponseObject> {
+    let storage = app_state.storage.as_ref();
+    let bucket = CONFIG.upload_bucket_name.as_str();
+    let mut result: Option<FileUploadResult> = None;
 
     // Обрабатываем каждую часть формы
-    while let Some(field) = json_err!(
-        multipart.next_field().await, 
-        ErrorCode::CoreFileUploadingError.details()
-            .with("reason", "Failed to get form field")
-    ) {
+    while let Some(field) = multipart.next_field().await.map_err(|err| UploadLimits::SINGLE.read_error(err))? {
         let name = field.name().unwrap_or_default();
 
         match name {
-            "track" => {
+            "track" if result.is_none() => {
                 // Получаем имя файла из поля
-                filename = field.file_name().map(ToString::to_string);
-                let file_name = json_opt!(
-                    filename.clone(), 
+                let file_name = field.file_name().map(ToString::to_string).ok_or_else(|| {
                     ErrorCode::CoreFileUploadingError.details()
                         .with("reason", "Missing file name in the uploaded file")
-                );
+                })?;
 
-                // Сохраняем field для последующей обработки после того, как определим все параметры
-                // let track_field = field;
+                let file = TrackFile { target, role, filename: &file_name, limits: UploadLimits::SINGLE };
+                let staged = stage_file(app_state, file, slots, headers, field).await?;
+                let uploaded = promote_file(storage, bucket, staged).await?;
+                slots.complete(app_state, target, role, &uploaded).await?;
+                result = Some(uploaded);
+            }
+            "track" => {
+                return Err(ErrorCode::CoreFileUploadingError.details()
+                    .with("reason", "Duplicate field in multipart form")
+                    .with("field_name", name));
+            }
+            _ => {
+                return Err(ErrorCode::CoreFileUploadingError.details()
+                    .with("reason", "Unknown field in multipart form")
+                    .with("field_name", name));
+            }
+        }
+    }
 
-                // Если уже собрали все необходимые данные, загружаем файл
-                // if let Some(file_name) = &filename {
-                //     result = Some(json_err!(upload_file(s3, bucket, file_name, &path, field).await));
-                // }
-                // return .into();
+    result.ok_or_else(|| ErrorCode::CoreFileUploadingError.details()
+        .with("reason", "Missing track field in multipart form"))
+}
 
-                result = json_err!(upload_file(s3, bucket, &file_name, &path, field).await);
-                // json_err!(process_chunk(field).await);
 
-            }
-            "path" => {
-                // Читаем значение path как текст
-                path = json_err!(
-                    field.text().await, 
-                    ErrorCode::CoreFileUploadingError.details()
-                        .with("reason", "Failed to read path field as text")
-                );
+#[utoipa::path(
+    post,
+    path = "/{session_id}/{track_id}",
+    tag = TAG,
+    description = "Endpoint for uploading vocal and instrumental straight to the server filesystem \
+        (`base_upload_dir/{session_id}/{track_id}/`), for installations without S3. \
+        A mismatched pair (error 4413) is handled as in `/upload-tracks`",
+    params(
+        ("session_id" = String, Path, description = "Session id"),
+        ("track_id" = String, Path, description = "Id of track"),
+        LinkQuery,
+    ),
+    request_body(content = UploadTracksForm, content_type = "multipart/form-data", description = "Vocal and instrumental files"),
+    responses(
+        (status = 200, body = FilesUploadResult, description = "Tracks uploaded successfully!"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+pub async fn upload_filesystem_multiple(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    AxumPath((session_id, track_id)): AxumPath<(String, String)>,
+    Query(link): Query<LinkQuery>,
+    headers: HeaderMap,
+    multipart: Multipart
+) -> JsonResponse {
+    let target = UploadTarget { session_id, track_id };
+    json_err!(validate_ids(&[("session_id", &target.session_id), ("track_id", &target.track_id)]));
+    json_err!(authorize_upload(&app_state, user.as_ref().map(|AuthUser(claims)| claims), link.token.as_deref(), &target, &TrackRole::ALL).await);
 
-                // Проверяем, что путь не пустой
-                if path.trim().is_empty() {
-                    path = "test".to_string(); // Используем значение по умолчанию
-                }
-            }
+    let mut slots = SlotClaims::new(&app_state, &target);
+    let result = upload_pair_to_disk(&app_state, &target, &headers, multipart, &mut slots).await;
+    if result.is_err() {
+        slots.fail().await;
+    }
+    JsonResponse::Ok(json!(json_err!(result)))
+}
+
+/// Пишет вокал и минус в `base_upload_dir/{session_id}/{track_id}/`;
+/// файлы получают свои имена, только если пришли оба
+async fn upload_pair_to_disk(
+    app_state: &AppState,
+    target: &UploadTarget,
+    headers: &HeaderMap,
+    mut multipart: Multipart,
+    slots: &mut SlotClaims,
+) -> Result<FilesUploadResult, BadResponseObject> {
+    // Места должно хватить на весь запрос еще до начала записи
+    let dir = Path::new(&CONFIG.base_upload_dir).join(&target.session_id).join(&target.track_id);
+    let expected_size = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or_default();
+    filesystem::prepare_dir(&dir, expected_size, CONFIG.filesystem_min_free_bytes).await
+        .map_err(filesystem_error)?;
+
+    // Файлы пишутся во временные и переименовываются, только когда пришли оба
+    let mut vocal_staged: Option<DiskStagedFile> = None;
+    let mut instrumental_staged: Option<DiskStagedFile> = None;
+
+    while let Some(field) = multipart.next_field().await.map_err(|err| UploadLimits::PAIR.read_error(err))? {
+        let name = field.name().unwrap_or_default();
+        let file_name = field.file_name()
+            .map(ToString::to_string)
+            .ok_or_else(|| {
+                tracing::error!("Missing file name");
+                ErrorCode::ValidationError.details()
+            })?;
+
+        let (role, slot) = match name {
+            "vocal" => (TrackRole::Vocal, &mut vocal_staged),
+            "instrumental" => (TrackRole::Instrumental, &mut instrumental_staged),
             _ => {
-                return ErrorCode::CoreFileUploadingError.details()
+                return Err(ErrorCode::CoreFileUploadingError.details()
                     .with("reason", "Unknown field in multipart form")
-                    .with("field_name", name)
-                    .into();
+                    .with("field_name", name));
             }
+        };
+        if slot.is_some() {
+            return Err(ErrorCode::CoreFileUploadingError.details()
+                .with("reason", "Duplicate field in multipart form")
+                .with("field_name", name));
         }
+        // Ключ - путь относительно `base_upload_dir`
+        let disk_name = filesystem::track_file_name(role.as_str(), &file_name);
+        let key = format!("{}/{}/{disk_name}", target.session_id, target.track_id);
+        let progress = slots.claim(app_state, target, role, &key, upload_id(headers, name)?).await?;
+        *slot = Some(stage_disk_file(dir.join(disk_name), key, &file_name, headers, field, &progress, UploadLimits::PAIR).await?);
     }
 
-    // Возвращаем результат
-    JsonResponse::Ok(json!(result))
+    let (Some(vocal), Some(instrumental)) = (vocal_staged, instrumental_staged) else {
+        return Err(ErrorCode::CoreFileUploadingError.details()
+            .with("reason", "Both vocal and instrumental files are required"));
+    };
+
+    let issues = pair_issues(&vocal.result.audio, &instrumental.result.audio);
+    if !issues.is_empty() {
+        tracing::warn!("Rejected track pair {} / {}: {:?}", vocal.result.key, instrumental.result.key, issues);
+        // Временные файлы удаляются при сбросе
+        let quarantined = if CONFIG.pair_delete_invalid {
+            None
+        } else {
+            Some(quarantine_disk_pair(vocal, instrumental).await?)
+        };
+        return Err(pair_mismatch(issues, quarantined));
+    }
+
+    let (vocal, instrumental) = commit_disk_pair(vocal, instrumental).await?;
+    slots.complete(app_state, target, TrackRole::Vocal, &vocal).await?;
+    slots.complete(app_state, target, TrackRole::Instrumental, &instrumental).await?;
+
+    Ok(FilesUploadResult::new(vocal, instrumental))
 }
 
+/// Ошибка несовпадения пары. Слоты трека остаются свободными: клиент должен
+/// загрузить исправленную пару заново; сохраненные файлы лежат в карантине
+fn pair_mismatch(issues: Vec<PairIssue>, quarantined: Option<Vec<String>>) -> BadResponseObject {
+    ErrorCode::TrackPairMismatch.details()
+        .with("issues", issues)
+        .with("deleted", quarantined.is_none())
+        .with("quarantine_keys", quarantined)
+}
 
-/// Функция для загрузки файла в S3
-async fn upload_file(
-    s3: &S3Manager,
+/// Ключ пары, не прошедшей проверку
+fn quarantine_key(key: &str) -> String {
+    format!("{}/{key}", CONFIG.pair_quarantine_prefix)
+}
+
+/// Переносит оба файла пары под префикс карантина; если перенос не удался,
+/// файлы удаляются, чтобы не остаться под ключами трека
+async fn quarantine_pair(
+    storage: &dyn ObjectStore,
     bucket: &str,
+    vocal: &str,
+    instrumental: &str,
+) -> Result<Vec<String>, BadResponseObject> {
+    let mut quarantined = Vec::with_capacity(2);
+    let mut failed = false;
+    for key in [vocal, instrumental] {
+        let target = quarantine_key(key);
+        match storage.copy_object(bucket, key, bucket, &target).await {
+            Ok(()) => quarantined.push(target),
+            Err(err) => {
+                tracing::error!("Failed to quarantine {}: {}", key, err);
+                failed = true;
+            }
+        }
+        if let Err(err) = storage.delete_object(bucket, key).await {
+            tracing::error!("Failed to delete {} after quarantine: {}", key, err);
+            failed = true;
+        }
+    }
+
+    if failed {
+        return Err(ErrorCode::CoreFileUploadingError.details()
+            .with("reason", "Failed to quarantine a mismatched track pair"));
+    }
+    Ok(quarantined)
+}
+
+/// Переносит временные файлы пары в каталог карантина
+async fn quarantine_disk_pair(
+    vocal: DiskStagedFile,
+    instrumental: DiskStagedFile,
+) -> Result<Vec<String>, BadResponseObject> {
+    let vocal_key = quarantine_key(&vocal.result.key);
+    let instrumental_key = quarantine_key(&instrumental.result.key);
+    let base = Path::new(&CONFIG.base_upload_dir);
+    if let Some(dir) = base.join(&vocal_key).parent() {
+        tokio::fs::create_dir_all(dir).await.map_err(|err| filesystem_error(err.into()))?;
+    }
+
+    let vocal = DiskStagedFile { file: vocal.file.retarget(base.join(&vocal_key)), ..vocal };
+    let instrumental = DiskStagedFile { file: instrumental.file.retarget(base.join(&instrumental_key)), ..instrumental };
+    commit_disk_pair(vocal, instrumental).await?;
+    Ok(vec![vocal_key, instrumental_key])
+}
+
+/// Несоответствия пары по допускам из конфигурации
+fn pair_issues(vocal: &AudioMetadata, instrumental: &AudioMetadata) -> Vec<PairIssue> {
+    let rules = PairRules {
+        duration_tolerance_secs: CONFIG.pair_duration_tolerance_secs,
+        silence_threshold_dbfs: CONFIG.pair_silence_threshold_dbfs,
+    };
+    validate_pair(vocal, instrumental, &rules)
+}
+
+/// Идентификатор загрузки для подписки на прогресс: из заголовка
+/// `X-Upload-Id-<поле>`, если клиент хочет знать его заранее, иначе новый ULID.
+/// Занятый другой загрузкой идентификатор отклоняется при занятии слота
+fn upload_id(headers: &HeaderMap, field_name: &str) -> Result<String, BadResponseObject> {
+    let header = format!("x-upload-id-{}", field_name.to_ascii_lowercase());
+    let Some(value) = headers.get(header.as_str()) else {
+        return Ok(Ulid::new().to_string().to_ascii_lowercase());
+    };
+
+    let upload_id = value.to_str().unwrap_or_default();
+    validate_ids(&[("upload_id", upload_id)])?;
+    Ok(upload_id.to_string())
+}
+
+/// Слоты трека, в которые идет загрузка в рамках одного запроса, и прогресс
+/// их загрузок; при сбросе незавершенные загрузки публикуются как неудачные,
+/// а их слоты освобождаются, даже если future обработчика был сброшен
+struct SlotClaims {
+    sessions: Arc<dyn SessionRegistry>,
+    session_id: String,
+    track_id: String,
+    claimed: Vec<(TrackRole, String, Arc<ProgressTracker>)>,
+}
+
+impl SlotClaims {
+    fn new(app_state: &AppState, target: &UploadTarget) -> Self {
+        Self {
+            sessions: Arc::clone(&app_state.sessions),
+            session_id: target.session_id.clone(),
+            track_id: target.track_id.clone(),
+            claimed: Vec::new(),
+        }
+    }
+
+    /// Занимает слот под загрузку; трек должен существовать, не быть финализирован,
+    /// а в слот не должна идти другая загрузка
+    async fn claim(
+        &mut self,
+        app_state: &AppState,
+        target: &UploadTarget,
+        role: TrackRole,
+        key: &str,
+        upload_id: String,
+    ) -> Result<Arc<ProgressTracker>, BadResponseObject> {
+        // Идентификатор загрузки занимаем до слота: если он уже занят, слот не меняется
+        let progress = app_state.progress.track(upload_id, &target.session_id, &target.track_id, role)?;
+        claim_slot(app_state, target, role, key, 0).await?;
+        self.claimed.push((role, key.to_string(), Arc::clone(&progress)));
+        Ok(progress)
+    }
+
+    async fn complete(
+        &mut self,
+        app_state: &AppState,
+        target: &UploadTarget,
+        role: TrackRole,
+        result: &FileUploadResult,
+    ) -> Result<(), BadResponseObject> {
+        record_slot(app_state, target, role, &result.key, result.size, UploadStatus::Uploaded).await?;
+        self.claimed.retain(|(claimed, _, progress)| {
+            if *claimed == role {
+                progress.set_phase(UploadPhase::Completed);
+            }
+            *claimed != role
+        });
+        Ok(())
+    }
+
+    /// Отмечает незавершенные слоты как неудачные до ответа клиенту, чтобы он
+    /// мог сразу повторить загрузку
+    async fn fail(mut self) {
+        let claimed = std::mem::take(&mut self.claimed);
+        release_slots(self.sessions.as_ref(), &self.session_id, &self.track_id, claimed).await;
+    }
+}
+
+impl Drop for SlotClaims {
+    fn drop(&mut self) {
+        if self.claimed.is_empty() {
+            return;
+        }
+
+        let claimed = std::mem::take(&mut self.claimed);
+        let sessions = Arc::clone(&self.sessions);
+        let (session_id, track_id) = (self.session_id.clone(), self.track_id.clone());
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::error!("No runtime to release dropped slots of track {}", track_id);
+            return;
+        };
+
+        runtime.spawn(async move {
+            release_slots(sessions.as_ref(), &session_id, &track_id, claimed).await;
+        });
+    }
+}
+
+/// Отмечает слоты как неудачные; ошибки реестра только логируются
+async fn release_slots(
+    sessions: &dyn SessionRegistry,
+    session_id: &str,
+    track_id: &str,
+    claimed: Vec<(TrackRole, String, Arc<ProgressTracker>)>,
+) {
+    for (role, key, _) in claimed {
+        let record = UploadRecord { key, size: 0, status: UploadStatus::Failed, updated_at: Utc::now() };
+        if let Err(err) = sessions.record_upload(session_id, track_id, role, record).await {
+            tracing::error!("Failed to mark {} of track {} as failed: {}", role.as_str(), track_id, err);
+        }
+    }
+}
+
+/// Занимает слот трека под загрузку `key`; занять слот, в который уже идет загрузка, нельзя
+pub(crate) async fn claim_slot(
+    app_state: &AppState,
+    target: &UploadTarget,
+    role: TrackRole,
+    key: &str,
+    size: u64,
+) -> Result<(), BadResponseObject> {
+    let record = UploadRecord { key: key.to_string(), size, status: UploadStatus::Uploading, updated_at: Utc::now() };
+    app_state.sessions.claim_upload(&target.session_id, &target.track_id, role, record).await?;
+    Ok(())
+}
+
+pub(crate) async fn record_slot(
+    app_state: &AppState,
+    target: &UploadTarget,
+    role: TrackRole,
+    key: &str,
+    size: u64,
+    status: UploadStatus,
+) -> Result<(), BadResponseObject> {
+    let record = UploadRecord { key: key.to_string(), size, status, updated_at: Utc::now() };
+    app_state.sessions.record_upload(&target.session_id, &target.track_id, role, record).await?;
+    Ok(())
+}
+
+
+/// Файл, целиком загруженный в незавершенную многочастную загрузку: в хранилище
+/// он появится только после `promote_file`, а при сбросе будет отменен
+struct StagedFile {
+    upload: MultipartUploadGuard,
+    /// Учет у координатора остановки, пока загрузка не завершена
+    _tracked: TrackedMultipart,
+    result: FileUploadResult,
+}
+
+/// Ключ объекта по шаблону `upload_key_template`; роль файла - имя поля формы
+pub(crate) fn object_key(
+    app_state: &AppState,
+    target: &UploadTarget,
+    role: &str,
     filename: &str,
-    path: &str,
+) -> Result<String, BadResponseObject> {
+    let params = KeyParams {
+        session_id: &target.session_id,
+        track_id: &target.track_id,
+        role,
+        filename,
+    };
+    app_state.key_template.render(&params).map_err(|err| {
+        ErrorCode::ValidationError.details()
+            .with("reason", "Failed to build object key")
+            .with("error", err.to_string())
+    })
+}
+
+/// Файл формы и слот трека, в который он загружается
+struct TrackFile<'a> {
+    target: &'a UploadTarget,
+    role: TrackRole,
+    /// Исходное имя файла
+    filename: &'a str,
+    limits: UploadLimits,
+}
+
+/// Занимает слот трека и загружает в него файл, не завершая многочастную загрузку
+async fn stage_file(
+    app_state: &AppState,
+    file: TrackFile<'_>,
+    slots: &mut SlotClaims,
+    headers: &HeaderMap,
     mut field: axum::extract::multipart::Field<'_>,
-) -> Result<FileUploadResult, BadResponseObject> {
-    // Формируем путь в S3sdg
-    let path = format!("{path}/{filename}");
+) -> Result<StagedFile, BadResponseObject> {
+    let TrackFile { target, role, filename, limits } = file;
+    let bucket = CONFIG.upload_bucket_name.as_str();
+    let field_name = field.name().unwrap_or_default().to_string();
+    let path = object_key(app_state, target, role.as_str(), filename)?;
+    let progress = &slots.claim(app_state, target, role, &path, upload_id(headers, &field_name)?).await?;
+    let expected = ExpectedDigest::from_headers(headers, &field_name)?;
+    // Формат определяем по содержимому, а не по имени, до создания загрузки
+    let (format, head) = sniff_format(filename, &mut field, limits).await?;
 
+    // S3 проверяет SHA-256 каждой части на своей стороне
+    let options = MultipartUploadOptions {
+        content_type: Some(format.mime_type().to_string()),
+        part_checksums: true,
+        ..Default::default()
+    };
 
+    // Создаем контекст для многочастной загрузки; guard отменит ее, если
+    // мы выйдем с ошибкой или future обработчика будет сброшен
+    let upload_context = MultipartUploadGuard::new(
+        app_state.storage.create_multipart_upload(bucket, &path, Some(options)).await
+            .map_err(|err| {
+                tracing::error!("Failed to create multipart upload context: {}", err);
+                ErrorCode::CoreFileUploadingError.details()
+            })?
+            .into(),
+    );
+    // Если загрузка не завершится к сроку остановки сервера, ее отменит координатор
+    let tracked = app_state.shutdown.track_multipart(bucket, &path, upload_context.upload());
 
-    // Создаем контекст для многочастной загрузки
-    let mut upload_context = s3.create_multipart_upload_context(bucket, &path, None).await
+    let hasher = ObjectHasher::new(CONFIG.upload_crc32c || expected.crc32c.is_some());
+    let probe = AudioProbe::new(format);
+    let (total_size, digest, audio) = match upload_parts(upload_context.upload(), &mut field, head, hasher, probe, progress, limits).await {
+        Ok(result) => result,
+        Err(err) => {
+            upload_context.abort().await;
+            return Err(err);
+        }
+    };
+
+    // Поврежденный файл не должен появиться в хранилище
+    if let Err(err) = expected.verify(&field_name, &digest) {
+        upload_context.abort().await;
+        return Err(err);
+    }
+    progress.set_phase(UploadPhase::Staged);
+
+    Ok(StagedFile {
+        upload: upload_context,
+        _tracked: tracked,
+        result: FileUploadResult {
+            key: path,
+            name: filename.to_string(),
+            size: total_size,
+            sha256: digest.sha256_hex(),
+            crc32c: digest.crc32c_hex(),
+            audio,
+            metadata_stored: None,
+        },
+    })
+}
+
+/// Завершает загрузку (части собираются по порядку номеров) и сохраняет
+/// параметры аудио в метаданных объекта, чтобы обработка не разбирала файл заново
+async fn promote_file(
+    storage: &dyn ObjectStore,
+    bucket: &str,
+    staged: StagedFile,
+) -> Result<FileUploadResult, BadResponseObject> {
+    let StagedFile { upload, mut result, .. } = staged;
+    upload.complete().await
         .map_err(|err| {
-            tracing::error!("Failed to create multipart upload context: {}", err);
+            tracing::error!("Failed to complete multipart upload: {}", err);
             ErrorCode::CoreFileUploadingError.details()
         })?;
 
+    result.metadata_stored = Some(store_object_metadata(storage, bucket, &result.key, &result.name, Some(&result.audio)).await);
+    Ok(result)
+}
+
+/// Сохраняет исходное имя файла и параметры аудио в метаданных объекта.
+/// Объект уже загружен, поэтому ошибка не отменяет загрузку: возвращается
+/// `false`, и клиент узнает об этом из `metadata_stored`
+pub(crate) async fn store_object_metadata(
+    storage: &dyn ObjectStore,
+    bucket: &str,
+    key: &str,
+    filename: &str,
+    audio: Option<&AudioMetadata>,
+) -> bool {
+    let mut metadata = audio.map(AudioMetadata::to_object_metadata).unwrap_or_default();
+    metadata.insert(ORIGINAL_FILENAME_METADATA.to_string(), encode_metadata_value(filename));
+    match storage.replace_metadata(bucket, key, metadata).await {
+        Ok(()) => true,
+        Err(err) => {
+            tracing::error!("Failed to store audio metadata of {}: {}", key, err);
+            false
+        }
+    }
+}
+
+/// Параметры аудио объекта, загруженного в обход сервера (tus, подписанные
+/// ссылки). Читается только начало файла, поэтому пиковый уровень не
+/// считается; если заголовок не разобрать, параметры не сохраняются
+pub(crate) async fn probe_object(
+    storage: &dyn ObjectStore,
+    bucket: &str,
+    key: &str,
+    format: AudioFormat,
+    size: u64,
+) -> Option<AudioMetadata> {
+    if size == 0 {
+        return None;
+    }
+    let range = ByteRange { start: 0, end: size.min(PROBE_HEAD_LEN) - 1 };
+    let head = match storage.get_object_range(bucket, key, range).await {
+        Ok(object) => object.body.try_collect::<Vec<_>>().await.map(|chunks| BBytes::from(chunks.concat())),
+        Err(err) => Err(err),
+    };
+    let result = match head {
+        Ok(head) => AudioProbe::probe_head(format, head, size).await,
+        Err(err) => {
+            tracing::error!("Failed to read the head of {}: {}", key, err);
+            return None;
+        }
+    };
+
+    result
+        .inspect_err(|err| tracing::warn!("Failed to probe audio of {}: {}", key, err))
+        .ok()
+}
+
+/// Завершает обе загрузки пары. Если второй файл завершить не удалось,
+/// первый удаляется: пара сохраняется целиком или не сохраняется вовсе
+async fn promote_pair(
+    storage: &dyn ObjectStore,
+    bucket: &str,
+    vocal: StagedFile,
+    instrumental: StagedFile,
+) -> Result<(FileUploadResult, FileUploadResult), BadResponseObject> {
+    // При ошибке instrumental еще не завершен и отменится при сбросе guard'а
+    let vocal = promote_file(storage, bucket, vocal).await?;
+
+    match promote_file(storage, bucket, instrumental).await {
+        Ok(instrumental) => Ok((vocal, instrumental)),
+        Err(err) => {
+            if let Err(delete_err) = storage.delete_object(bucket, &vocal.key).await {
+                tracing::error!("Failed to delete {} after failed pair upload: {}", vocal.key, delete_err);
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Файл, записанный во временный файл в каталоге трека
+struct DiskStagedFile {
+    file: AtomicFile,
+    result: FileUploadResult,
+}
+
+/// Пишет поле формы во временный файл `path`, проверяя формат, аудио и контрольные суммы
+async fn stage_disk_file(
+    path: PathBuf,
+    key: String,
+    filename: &str,
+    headers: &HeaderMap,
+    mut field: axum::extract::multipart::Field<'_>,
+    progress: &ProgressTracker,
+    limits: UploadLimits,
+) -> Result<DiskStagedFile, BadResponseObject> {
+    let field_name = field.name().unwrap_or_default().to_string();
+    let expected = ExpectedDigest::from_headers(headers, &field_name)?;
+    let (format, head) = sniff_format(filename, &mut field, limits).await?;
+
+    let mut file = AtomicFile::create(path, CONFIG.filesystem_min_free_bytes).await
+        .map_err(filesystem_error)?;
+    let mut hasher = ObjectHasher::new(CONFIG.upload_crc32c || expected.crc32c.is_some());
+    let mut probe = AudioProbe::new(format);
+
+    hasher.update(&head);
+    file.write(&head).await.map_err(filesystem_error)?;
+    progress.received(head.len());
+    let mut total_size = head.len() as u64;
+    probe.feed(head.freeze()).await.map_err(unreadable_audio)?;
+
+    while let Some(chunk) = field.chunk().await.map_err(|err| limits.read_error(err))? {
+        // Временный файл удалится при сбросе
+        total_size += chunk.len() as u64;
+        limits.check_file(&field_name, total_size)?;
+        hasher.update(&chunk);
+        file.write(&chunk).await.map_err(filesystem_error)?;
+        progress.received(chunk.len());
+        probe.feed(chunk).await.map_err(unreadable_audio)?;
+    }
+    progress.set_phase(UploadPhase::Processing);
+
+    let audio = probe.finish().await.map_err(unreadable_audio)?;
+    let digest = hasher.finalize();
+    expected.verify(&field_name, &digest)?;
+    progress.set_phase(UploadPhase::Staged);
+
+    Ok(DiskStagedFile {
+        file,
+        result: FileUploadResult {
+            key,
+            name: filename.to_string(),
+            size: total_size,
+            sha256: digest.sha256_hex(),
+            crc32c: digest.crc32c_hex(),
+            audio,
+            metadata_stored: None,
+        },
+    })
+}
+
+/// Переносит оба файла на место; если второй перенести не удалось, первый удаляется
+async fn commit_disk_pair(
+    vocal: DiskStagedFile,
+    instrumental: DiskStagedFile,
+) -> Result<(FileUploadResult, FileUploadResult), BadResponseObject> {
+    let vocal_path = vocal.file.commit().await.map_err(filesystem_error)?;
+
+    if let Err(err) = instrumental.file.commit().await {
+        if let Err(remove_err) = tokio::fs::remove_file(&vocal_path).await {
+            tracing::error!("Failed to remove {} after failed pair upload: {}", vocal_path.display(), remove_err);
+        }
+        return Err(filesystem_error(err));
+    }
+
+    Ok((vocal.result, instrumental.result))
+}
+
+fn filesystem_error(err: FilesystemError) -> BadResponseObject {
+    match err {
+        FilesystemError::InsufficientSpace { available, required } => {
+            tracing::error!("Not enough disk space for upload: {} of {} bytes available", available, required);
+            ErrorCode::InsufficientStorage.details()
+        }
+        FilesystemError::IoError(err) => {
+            tracing::error!("Failed to write upload to disk: {}", err);
+            ErrorCode::CoreFileUploadingError.details()
+        }
+    }
+}
+
+/// Формат файла по расширению; расширение должно быть в списке разрешенных
+pub(crate) fn allowed_format(filename: &str) -> Result<AudioFormat, BadResponseObject> {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| format!(".{}", extension.to_ascii_lowercase()))
+        .unwrap_or_default();
+
+    ALLOWED_EXTENSIONS
+        .contains(&extension)
+        .then(|| AudioFormat::from_extension(&extension))
+        .flatten()
+        .ok_or_else(|| ErrorCode::WrongFormat.details()
+            .with("reason", "File extension is not allowed")
+            .with("allowed", ALLOWED_EXTENSIONS.as_slice()))
+}
+
+/// Проверяет расширение файла и то, что содержимое ему соответствует;
+/// возвращает формат и уже прочитанное начало файла
+async fn sniff_format(
+    filename: &str,
+    field: &mut axum::extract::multipart::Field<'_>,
+    limits: UploadLimits,
+) -> Result<(AudioFormat, BytesMut), BadResponseObject> {
+    let format = allowed_format(filename)?;
+    let head = read_head(field, limits).await?;
+    let detected = AudioFormat::sniff(&head);
+    if detected != Some(format) {
+        tracing::warn!("Content of {} does not match its extension: {:?}", filename, detected);
+        return Err(ErrorCode::WrongFormat.details()
+            .with("reason", "File content does not match its extension")
+            .with("expected", format.name())
+            .with("detected", detected.map(|format| format.name())));
+    }
+    Ok((format, head))
+}
+
+/// Читает из поля формы начало файла, достаточное для определения формата
+/// (или весь файл, если он короче); тег ID3 читается целиком
+async fn read_head(
+    field: &mut axum::extract::multipart::Field<'_>,
+    limits: UploadLimits,
+) -> Result<BytesMut, BadResponseObject> {
+    let field_name = field.name().unwrap_or_default().to_string();
+    let mut head = BytesMut::new();
+    while head.len() < AudioFormat::sniff_len(&head) {
+        match field.chunk().await.map_err(|err| limits.read_error(err))? {
+            Some(chunk) => head.extend_from_slice(&chunk),
+            None => break,
+        }
+        limits.check_file(&field_name, head.len() as u64)?;
+    }
+    Ok(head)
+}
+
+/// Читает поле формы и загружает его частями, начиная с уже прочитанного
+/// `head`; возвращает размер файла, его контрольные суммы и параметры аудио
+async fn upload_parts(
+    upload_context: &Arc<dyn MultipartUpload>,
+    field: &mut axum::extract::multipart::Field<'_>,
+    head: BytesMut,
+    mut hasher: ObjectHasher,
+    mut probe: AudioProbe,
+    progress: &Arc<ProgressTracker>,
+    limits: UploadLimits,
+) -> Result<(u64, ObjectDigest, AudioMetadata), BadResponseObject> {
+    let field_name = field.name().unwrap_or_default().to_string();
+    // Части загружаются параллельно, пока мы продолжаем читать поле формы
+    let mut pipeline = PartUploadPipeline::new(
+        Arc::clone(upload_context),
+        CONFIG.upload_max_parallel_parts,
+        CONFIG.upload_max_buffered_bytes,
+    ).with_progress(Arc::clone(progress));
+
     // Буфер для чтения данных
-    let mut buffer = BytesMut::new();
+    hasher.update(&head);
+    probe.feed(BBytes::copy_from_slice(&head)).await.map_err(unreadable_audio)?;
+    progress.received(head.len());
+    let mut total_size = head.len() as u64;
+    let mut buffer = head;
     let mut part_number = 1;
-    let mut total_size = 0u64;
 
     // Читаем чанки данных из поля формы
-    while let Some(chunk) = field.chunk().await.map_err(|err| {
-        tracing::error!("Error reading chunk: {}", err);
-        ErrorCode::CoreFileUploadingError.details()
-    })? {
+    while let Some(chunk) = field.chunk().await.map_err(|err| limits.read_error(err))? {
+        // Файл больше лимита не загружается дальше; многочастную загрузку отменит вызывающий
+        total_size += chunk.len() as u64;
+        limits.check_file(&field_name, total_size)?;
+
         // Добавляем данные в буфер
+        hasher.update(&chunk);
         buffer.extend_from_slice(&chunk);
-        total_size += chunk.len() as u64;
+        progress.received(chunk.len());
+        probe.feed(chunk).await.map_err(unreadable_audio)?;
 
         // Если накопили достаточно данных, отправляем часть
         while buffer.len() >= CHUNK_SIZE {
             let chunk_data = buffer.split_to(CHUNK_SIZE).freeze();
-            upload_context.upload_part(part_number, chunk_data).await
-                .map_err(|err| {
-                    tracing::error!("Failed to upload part {}: {}", part_number, err);
-                    ErrorCode::CoreFileUploadingError.details()
-                })?;
+            pipeline.submit(part_number, chunk_data).await
+                .map_err(|_| ErrorCode::CoreFileUploadingError.details())?;
             part_number += 1;
         }
     }
 
+    progress.set_phase(UploadPhase::Processing);
+
     // Отправляем оставшиеся данные, если они есть
     if !buffer.is_empty() {
         let chunk_data = buffer.freeze();
-        upload_context.upload_part(part_number, chunk_data).await
-            .map_err(|err| {
-                tracing::error!("Failed to upload final part {}: {}", part_number, err);
-                ErrorCode::CoreFileUploadingError.details()
-            })?;
+        pipeline.submit(part_number, chunk_data).await
+            .map_err(|_| ErrorCode::CoreFileUploadingError.details())?;
     }
 
-    // Завершаем многочастную загрузку
-    upload_context.complete().await
-        .map_err(|err| {
-            tracing::error!("Failed to complete multipart upload: {}", err);
-            ErrorCode::CoreFileUploadingError.details()
-        })?;
+    // Дожидаемся всех частей: ошибка возвращается для части с наименьшим номером
+    pipeline.finish().await
+        .map_err(|_| ErrorCode::CoreFileUploadingError.details())?;
 
-    // Возвращаем информацию о загруженном файле
-    Ok(FileUploadResult {
-        name: filename.to_string(),
-        size: total_size,
-    })
+    // Нечитаемый файл отклоняется до завершения загрузки
+    let audio = probe.finish().await.map_err(unreadable_audio)?;
+
+    Ok((total_size, hasher.finalize(), audio))
+}
+
+fn unreadable_audio(err: AudioError) -> BadResponseObject {
+    tracing::warn!("Rejected unreadable audio: {}", err);
+    ErrorCode::WrongFormat.details()
+        .with("reason", "Audio stream could not be read")
+        .with("error", err.to_string())
 }
 
 /// Функция для неблокирующей загрузки файла в S3
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::custom_exceptions::RawResponse;
+use services::AppState;
+
+
+const TAG: &str = "Health";
+
+/// Маршруты проб монтируются в корень, вне `api_v1_str`
+pub fn get_router(app_state: Arc<AppState>) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(liveness))
+        .routes(routes!(readiness))
+        .with_state(app_state)
+}
+
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = TAG,
+    description = "Liveness probe: the process is up and serving requests. Dependencies are not checked",
+    responses(
+        (status = 200, description = "Alive", example = json!({"status": "ok"})),
+    ),
+)]
+async fn liveness() -> Json<serde_json::Value> {
+    Json(json!({"status": "ok"}))
+}
+
+
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = TAG,
+    description = "Readiness probe: checks the upload bucket in S3 (`head_bucket`) and Redis, when they are used. \
+        Results are cached for `readiness_cache_ms`. While the server is draining uploads before shutdown \
+        the status is `draining` and no checks are run",
+    responses(
+        (status = 200, description = "Ready", example = json!({
+            "status": "ready",
+            "checks": [
+                {"name": "s3:svaha-mini-input", "status": "ok", "latency_ms": 12},
+                {"name": "redis", "status": "ok", "latency_ms": 1}
+            ],
+            "checked_at": "2025-03-01T12:00:00Z",
+            "cached": false
+        })),
+        (status = 503, description = "A dependency is unavailable or the server is draining", example = json!({
+            "status": "not_ready",
+            "checks": [
+                {"name": "s3:svaha-mini-input", "status": "timeout", "latency_ms": 2000, "error": "No response in 2000ms"},
+                {"name": "redis", "status": "ok", "latency_ms": 1}
+            ],
+            "checked_at": "2025-03-01T12:00:00Z",
+            "cached": false
+        })),
+    ),
+)]
+async fn readiness(State(app_state): State<Arc<AppState>>) -> Response {
+    let report = app_state.health.readiness().await;
+    let status = if report.is_ready() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    // Отчет отдается как есть, глобальный обработчик не должен заменять его ошибкой
+    let mut response = (status, Json(report)).into_response();
+    response.extensions_mut().insert(RawResponse);
+    response
+}
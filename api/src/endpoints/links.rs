@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::auth::{check_scope, AuthUser};
+use crate::custom_exceptions::{BadResponseObject, ErrorCode, JsonResponse};
+use crate::endpoints::files::{validate_ids, UploadTarget};
+use crate::{json_err, json_opt};
+use my_core::config::CONFIG;
+use services::AppState;
+use services::auth::Claims;
+use services::links::{UploadLink, UploadLinks};
+use services::sessions::TrackRole;
+
+
+const TAG: &str = "Upload links";
+
+pub fn get_router(app_state: Arc<AppState>) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(create_upload_link))
+        .with_state(app_state)
+}
+
+/// Подписанная ссылка на загрузку, с которой пришел запрос
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LinkQuery {
+    /// Токен из ссылки, выданной `POST /links`
+    pub token: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreateLinkRequest {
+    session_id: String,
+    track_id: String,
+    /// Разрешенные роли; по умолчанию вокал и минус
+    #[schema(value_type = Option<Vec<String>>, example = json!(["vocal", "instrumental"]))]
+    roles: Option<Vec<TrackRole>>,
+    /// Время жизни ссылки; по умолчанию `upload_link_ttl_secs`
+    ttl_secs: Option<u64>,
+    /// Ссылка действует на одну загрузку
+    #[serde(default)]
+    one_time: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(example = json!({
+    "token": "eyJzaWQiOiJzZXNzaW9uXzEiLCJ0aWQiOiJ0cmFja18xIn0.5Kq0p8wKx3yXk1r2...",
+    "expires_at": "2025-03-01T13:00:00Z",
+    "roles": ["vocal", "instrumental"],
+    "one_time": false,
+    "urls": {
+        "multiple": "http://127.0.0.1:8000/api/v1/upload-ui/upload-ui-multiple/session_1/track_1?token=...",
+        "filesystem": "http://127.0.0.1:8000/api/v1/upload-ui/upload-ui-multiple-filesystem/session_1/track_1?token=..."
+    }
+}))]
+struct CreateLinkResult {
+    token: String,
+    #[schema(value_type = String, format = DateTime)]
+    expires_at: DateTime<Utc>,
+    #[schema(value_type = Vec<String>)]
+    roles: Vec<TrackRole>,
+    one_time: bool,
+    /// Страницы загрузки, открываемые этой ссылкой
+    urls: BTreeMap<String, String>,
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/",
+    tag = TAG,
+    description = "Mint an HMAC-signed, expiring link to the upload pages of a track. \
+        The token is also accepted by the upload endpoints as the `token` query parameter. \
+        Requires a bearer token with the upload scope",
+    request_body = CreateLinkRequest,
+    responses(
+        (status = 200, body = CreateLinkResult, description = "Link created"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn create_upload_link(
+    State(app_state): State<Arc<AppState>>,
+    AuthUser(claims): AuthUser,
+    Json(request): Json<CreateLinkRequest>,
+) -> JsonResponse {
+    // Ссылки выдают только те, кто может загружать сам
+    json_err!(check_scope(&claims, &CONFIG.jwt_upload_scope));
+    let links = json_opt!(
+        app_state.links.as_ref(),
+        ErrorCode::ForbiddenError.details().with("reason", "Upload links are disabled: upload_link_secret is not set")
+    );
+    json_err!(validate_ids(&[("session_id", &request.session_id), ("track_id", &request.track_id)]));
+
+    let mut roles = request.roles.unwrap_or_else(|| TrackRole::ALL.to_vec());
+    roles.sort_by_key(|role| role.as_str());
+    roles.dedup();
+    if roles.is_empty() {
+        return ErrorCode::ValidationError.details()
+            .with("reason", "At least one role is required")
+            .into();
+    }
+
+    let ttl_secs = request.ttl_secs.unwrap_or(CONFIG.upload_link_ttl_secs);
+    if ttl_secs == 0 || ttl_secs > CONFIG.upload_link_max_ttl_secs {
+        return ErrorCode::ValidationError.details()
+            .with("reason", "ttl_secs is out of range")
+            .with("max", CONFIG.upload_link_max_ttl_secs)
+            .into();
+    }
+
+    // Ссылка выдается только на существующий трек
+    json_err!(app_state.sessions.get_track(&request.session_id, &request.track_id).await);
+
+    let expires_at = Utc::now() + Duration::seconds(ttl_secs as i64);
+    let link = UploadLink::new(&request.session_id, &request.track_id, roles, expires_at, request.one_time);
+    let token = links.sign(&link);
+
+    JsonResponse::Ok(json!(CreateLinkResult {
+        urls: page_urls(&link, &token),
+        token,
+        expires_at,
+        roles: link.roles,
+        one_time: link.one_time,
+    }))
+}
+
+/// Страницы загрузки, которые открывает ссылка: одиночная для каждой роли
+/// и страницы пары, если разрешены обе роли
+fn page_urls(link: &UploadLink, token: &str) -> BTreeMap<String, String> {
+    let base = format!(
+        "{}{}upload-ui",
+        CONFIG.upload_public_domain.trim_end_matches('/'),
+        CONFIG.api_v1_str,
+    );
+    let (session_id, track_id) = (&link.session_id, &link.track_id);
+
+    let mut urls: BTreeMap<String, String> = link.roles
+        .iter()
+        .map(|role| {
+            let role = role.as_str();
+            (role.to_string(), format!("{base}/upload-ui/{session_id}/{track_id}/{role}?token={token}"))
+        })
+        .collect();
+    if link.allows(session_id, track_id, &TrackRole::ALL) {
+        urls.insert("multiple".to_string(), format!("{base}/upload-ui-multiple/{session_id}/{track_id}?token={token}"));
+        urls.insert("filesystem".to_string(), format!("{base}/upload-ui-multiple-filesystem/{session_id}/{track_id}?token={token}"));
+    }
+    urls
+}
+
+/// Проверяет доступ перед загрузкой; одноразовая ссылка расходуется здесь,
+/// даже если загрузка затем не удастся
+pub(crate) async fn authorize_upload(
+    app_state: &AppState,
+    user: Option<&Claims>,
+    token: Option<&str>,
+    target: &UploadTarget,
+    roles: &[TrackRole],
+) -> Result<(), BadResponseObject> {
+    if let (Some(links), Some(link)) = (app_state.links.as_ref(), authorize(app_state, user, token, target, roles)?) {
+        links.consume(&link).await?;
+    }
+    Ok(())
+}
+
+/// Загрузку в трек разрешает bearer-токен со scope `jwt_upload_scope` или подписанная
+/// ссылка на этот трек. Если не настроены ни JWT, ни ссылки, загрузка открыта всем.
+/// Одноразовую ссылку не расходует; возвращает ссылку, если доступ дала она.
+/// С пустым `roles` подходит ссылка на любой слот трека - так проверяется чтение
+pub(crate) fn authorize(
+    app_state: &AppState,
+    user: Option<&Claims>,
+    token: Option<&str>,
+    target: &UploadTarget,
+    roles: &[TrackRole],
+) -> Result<Option<UploadLink>, BadResponseObject> {
+    if user.is_some_and(|claims| claims.has_scope(&CONFIG.jwt_upload_scope)) {
+        return Ok(None);
+    }
+
+    let token = token.filter(|token| !token.is_empty());
+    if let (Some(links), Some(token)) = (app_state.links.as_ref(), token) {
+        return verify_link(links, token, target, roles).map(Some);
+    }
+
+    authorize_bearer(app_state, user).map(|_| None)
+}
+
+/// Доступ к данным не одного трека (сессия целиком, ключи вне треков) ссылка не дает:
+/// нужен bearer-токен со scope `jwt_upload_scope`, если настроены JWT или ссылки
+pub(crate) fn authorize_bearer(app_state: &AppState, user: Option<&Claims>) -> Result<(), BadResponseObject> {
+    match user {
+        Some(claims) => check_scope(claims, &CONFIG.jwt_upload_scope),
+        None if app_state.links.is_some() => Err(ErrorCode::Unauthorized.details()
+            .with("reason", "Signed upload link or bearer token is required")),
+        None if app_state.jwt.is_some() => Err(ErrorCode::NotAuthenticated.details()),
+        None => Ok(()),
+    }
+}
+
+fn verify_link(
+    links: &UploadLinks,
+    token: &str,
+    target: &UploadTarget,
+    roles: &[TrackRole],
+) -> Result<UploadLink, BadResponseObject> {
+    let link = links.verify(token)?;
+    if !link.allows(&target.session_id, &target.track_id, roles) {
+        return Err(ErrorCode::AuthorizeError.details()
+            .with("reason", "Upload link does not allow this upload")
+            .with("roles", roles.iter().map(TrackRole::as_str).collect::<Vec<_>>()));
+    }
+    Ok(link)
+}
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::custom_exceptions::{BadResponseObject, ErrorCode, JsonResponse};
+use crate::auth::AuthUser;
+use crate::endpoints::files::{validate_ids, UploadTarget};
+use crate::endpoints::links::{authorize, authorize_bearer, LinkQuery};
+use crate::json_err;
+use my_core::config::CONFIG;
+use services::AppState;
+use services::storage::{ListObjectsQuery, ObjectMeta, MAX_LIST_KEYS};
+
+
+const TAG: &str = "Files";
+
+pub fn get_router(app_state: Arc<AppState>) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(list_session_files))
+        .routes(routes!(list_track_files))
+        .with_state(app_state)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ListFilesQuery {
+    /// Сворачивать вложенные ключи в `prefixes`, например `/` - список треков сессии
+    delimiter: Option<String>,
+    /// `next_continuation_token` предыдущей страницы
+    continuation_token: Option<String>,
+    /// Размер страницы, не больше 1000
+    max_keys: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct StoredFile {
+    key: String,
+    /// Имя относительно запрошенного префикса
+    name: String,
+    size: u64,
+    e_tag: Option<String>,
+    /// RFC 3339
+    last_modified: Option<String>,
+    storage_class: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(example = json!({
+    "prefix": "session_1/",
+    "files": [{
+        "key": "session_1/track_1/vocal.wav",
+        "name": "track_1/vocal.wav",
+        "size": 31457280,
+        "e_tag": "\"9b2cf535f27731c974343645a3985328\"",
+        "last_modified": "2025-03-01T12:00:00+00:00",
+        "storage_class": "STANDARD"
+    }],
+    "prefixes": [],
+    "next_continuation_token": null
+}))]
+struct ListFilesResult {
+    prefix: String,
+    files: Vec<StoredFile>,
+    /// Свернутые по разделителю префиксы
+    prefixes: Vec<String>,
+    /// `null` на последней странице
+    next_continuation_token: Option<String>,
+}
+
+
+#[utoipa::path(
+    get,
+    path = "/{session_id}",
+    tag = TAG,
+    description = "List files already uploaded to a session, page by page. \
+        When JWT or upload links are configured, requires a bearer token with the upload scope",
+    params(
+        ("session_id" = String, Path, description = "Session id"),
+        ListFilesQuery,
+    ),
+    responses(
+        (status = 200, body = ListFilesResult, description = "One page of session files"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn list_session_files(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path(session_id): Path<String>,
+    Query(query): Query<ListFilesQuery>,
+) -> JsonResponse {
+    json_err!(authorize_bearer(&app_state, user.as_ref().map(|AuthUser(claims)| claims)));
+    json_err!(validate_ids(&[("session_id", &session_id)]));
+    list_files(&app_state, format!("{session_id}/"), query).await
+}
+
+
+#[utoipa::path(
+    get,
+    path = "/{session_id}/{track_id}",
+    tag = TAG,
+    description = "List files already uploaded to a track, page by page. \
+        Allowed with the upload scope or an upload link to the track",
+    params(
+        ("session_id" = String, Path, description = "Session id"),
+        ("track_id" = String, Path, description = "Id of track"),
+        ListFilesQuery,
+        LinkQuery,
+    ),
+    responses(
+        (status = 200, body = ListFilesResult, description = "One page of track files"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn list_track_files(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path((session_id, track_id)): Path<(String, String)>,
+    Query(query): Query<ListFilesQuery>,
+    Query(link): Query<LinkQuery>,
+) -> JsonResponse {
+    json_err!(validate_ids(&[("session_id", &session_id), ("track_id", &track_id)]));
+    let target = UploadTarget { session_id, track_id };
+    json_err!(authorize(&app_state, user.as_ref().map(|AuthUser(claims)| claims), link.token.as_deref(), &target, &[]));
+    list_files(&app_state, format!("{}/{}/", target.session_id, target.track_id), query).await
+}
+
+
+async fn list_files(app_state: &AppState, prefix: String, query: ListFilesQuery) -> JsonResponse {
+    if query.max_keys.is_some_and(|max_keys| max_keys == 0 || max_keys > MAX_LIST_KEYS) {
+        return ErrorCode::ValidationError.details()
+            .with("reason", "max_keys is out of range")
+            .with("max", MAX_LIST_KEYS)
+            .into();
+    }
+
+    let list_query = ListObjectsQuery {
+        prefix: Some(prefix.clone()),
+        delimiter: query.delimiter,
+        continuation_token: query.continuation_token,
+        max_keys: query.max_keys,
+    };
+    let page = json_err!(
+        app_state.storage.list_objects_page(&CONFIG.upload_bucket_name, &list_query).await,
+        ErrorCode::InternalError.details().with("reason", "Failed to list files")
+    );
+
+    JsonResponse::Ok(json!(ListFilesResult {
+        files: page.objects.into_iter().map(|meta| stored_file(&prefix, meta)).collect(),
+        prefixes: page.common_prefixes,
+        next_continuation_token: page.next_continuation_token,
+        prefix,
+    }))
+}
+
+fn stored_file(prefix: &str, meta: ObjectMeta) -> StoredFile {
+    StoredFile {
+        name: meta.key.strip_prefix(prefix).unwrap_or(&meta.key).to_string(),
+        last_modified: meta.last_modified.map(|last_modified| last_modified.to_rfc3339()),
+        key: meta.key,
+        size: meta.size,
+        e_tag: meta.e_tag,
+        storage_class: meta.storage_class,
+    }
+}
@@ -1,3 +1,11 @@
+pub mod download;
 pub mod files;
+pub mod health;
+pub mod links;
+pub mod listing;
+pub mod presigned;
+pub mod progress;
+pub mod sessions;
 pub mod tests;
+pub mod tus;
 pub mod webui;
\ No newline at end of file
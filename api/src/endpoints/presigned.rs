@@ -0,0 +1,459 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, Query, State},
+    middleware,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::auth::AuthUser;
+use crate::custom_exceptions::{BadResponseObject, ErrorCode, JsonResponse};
+use crate::endpoints::files::{
+    allowed_format, claim_slot, object_key, probe_object, record_slot, store_object_metadata, validate_ids, UploadTarget,
+};
+use crate::endpoints::links::{authorize, authorize_upload, LinkQuery};
+use crate::shutdown::track_upload;
+use crate::{json_err, json_opt};
+use my_core::config::CONFIG;
+use services::AppState;
+use services::audio::{AudioFormat, AudioMetadata};
+use services::s3::{MultipartUploadOptions, PresignedUrl, S3Error, S3Manager};
+use services::sessions::{TrackRole, UploadRecord, UploadStatus};
+
+
+const TAG: &str = "Presigned upload";
+/// Минимальный размер части S3 (кроме последней)
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+/// Максимальное число частей многочастной загрузки S3
+const MAX_PARTS: u64 = 10_000;
+/// Максимальный размер объекта S3
+const MAX_OBJECT_SIZE: u64 = 5 * 1024 * 1024 * 1024 * 1024;
+
+/// Доступ проверяется в обработчиках, как при загрузке через сервер:
+/// bearer-токен со scope загрузки или подписанная ссылка на трек
+pub fn get_router(app_state: Arc<AppState>) -> OpenApiRouter {
+    OpenApiRouter::new()
+        // Новые загрузки во время остановки не выдаются; начатые можно завершить или отменить
+        .merge(OpenApiRouter::new()
+            .routes(routes!(presign_upload))
+            .route_layer(middleware::from_fn_with_state(Arc::clone(&app_state), track_upload)))
+        .routes(routes!(complete_presigned_upload))
+        .routes(routes!(abort_presigned_upload))
+        .with_state(app_state)
+}
+
+/// Тип загружаемого файла трека
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum TrackFileType {
+    Vocal,
+    Instrumental,
+}
+
+impl TrackFileType {
+    fn role(self) -> TrackRole {
+        match self {
+            Self::Vocal => TrackRole::Vocal,
+            Self::Instrumental => TrackRole::Instrumental,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[schema(example = json!({
+    "file_type": "vocal",
+    "filename": "vocal.wav",
+    "size": 314572800
+}))]
+struct PresignRequest {
+    file_type: TrackFileType,
+    /// Исходное имя файла: по нему проверяется формат и строится ключ
+    filename: String,
+    /// Точный размер файла, он входит в подпись
+    size: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PresignedPart {
+    part_number: i32,
+    url: String,
+}
+
+/// Способ загрузки, выбранный по размеру файла
+#[derive(Serialize, ToSchema)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+enum PresignResult {
+    /// Один PUT на `url` с заголовками `headers`
+    Single {
+        key: String,
+        expires_in: u64,
+        url: String,
+        headers: BTreeMap<String, String>,
+    },
+    /// PUT каждой части на свой `url`, затем вызов `/complete` с ETag частей
+    Multipart {
+        key: String,
+        expires_in: u64,
+        upload_id: String,
+        part_size: u64,
+        parts: Vec<PresignedPart>,
+    },
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CompletedPartRequest {
+    part_number: i32,
+    e_tag: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[schema(example = json!({
+    "file_type": "vocal",
+    "filename": "vocal.wav",
+    "upload_id": "2~6bZ9lH2yG2fP9Qw",
+    "parts": [{"part_number": 1, "e_tag": "\"9b2cf535f27731c974343645a3985328\""}]
+}))]
+struct CompletePresignedRequest {
+    file_type: TrackFileType,
+    /// Исходное имя файла, сохраняется в метаданных объекта
+    filename: String,
+    /// Для одного PUT не передается
+    upload_id: Option<String>,
+    #[serde(default)]
+    parts: Vec<CompletedPartRequest>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct AbortPresignedRequest {
+    file_type: TrackFileType,
+    /// Для одного PUT не передается
+    upload_id: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PresignedUploadResult {
+    key: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CompletedUploadResult {
+    key: String,
+    /// Параметры аудио по заголовку файла, если его удалось разобрать
+    #[schema(value_type = Option<Object>)]
+    audio: Option<AudioMetadata>,
+    /// Сохранены ли имя файла и параметры аудио в метаданных объекта
+    metadata_stored: bool,
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/{session_id}/{track_id}",
+    tag = TAG,
+    description = "Presigned URLs for uploading a track file directly to S3: \
+        a single PUT for small files, a multipart upload with a URL per part for large ones. \
+        The declared size is signed into every URL. The track slot is marked as uploading \
+        until `/complete` or `/abort` is called",
+    params(
+        ("session_id" = String, Path, description = "Session id"),
+        ("track_id" = String, Path, description = "Id of track"),
+        LinkQuery,
+    ),
+    request_body = PresignRequest,
+    responses(
+        (status = 200, body = PresignResult, description = "Presigned upload created"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn presign_upload(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path((session_id, track_id)): Path<(String, String)>,
+    Query(link): Query<LinkQuery>,
+    Json(request): Json<PresignRequest>,
+) -> JsonResponse {
+    let s3 = json_opt!(app_state.s3.as_ref(), s3_required());
+    let target = UploadTarget { session_id, track_id };
+    let role = request.file_type.role();
+    json_err!(validate_ids(&[("session_id", &target.session_id), ("track_id", &target.track_id)]));
+    let format = json_err!(allowed_format(&request.filename));
+    // Файл не может быть больше, чем разрешено при загрузке через сервер
+    let max_size = MAX_OBJECT_SIZE.min(CONFIG.upload_max_file_size);
+    if request.size > max_size {
+        return ErrorCode::PayloadTooLarge.details().with("max_size", max_size).into();
+    }
+    json_err!(authorize_upload(&app_state, user.as_ref().map(|AuthUser(claims)| claims), link.token.as_deref(), &target, &[role]).await);
+
+    // Ключ строится по общему шаблону, а слот трека занимается так же, как
+    // при загрузке через сервер: в финализированный трек загрузка не начнется
+    let key = json_err!(object_key(&app_state, &target, role.as_str(), &request.filename));
+    json_err!(claim_slot(&app_state, &target, role, &key, request.size).await);
+
+    let result = presign(s3, &key, &request, format).await;
+    if result.is_err() {
+        fail_slot(&app_state, &target, role, &key).await;
+    }
+    JsonResponse::Ok(json!(json_err!(result)))
+}
+
+/// Подписывает один PUT или создает многочастную загрузку с подписанными частями
+async fn presign(
+    s3: &S3Manager,
+    key: &str,
+    request: &PresignRequest,
+    format: AudioFormat,
+) -> Result<PresignResult, BadResponseObject> {
+    let bucket = CONFIG.upload_bucket_name.as_str();
+    let expires_in = Duration::from_secs(CONFIG.presign_expires_secs);
+    // Тип содержимого определяется только форматом файла и входит в подпись
+    let content_type = format.mime_type();
+
+    if request.size <= CONFIG.presign_single_max_size {
+        let presigned = s3.presign_put_object(bucket, key, Some(content_type), request.size, expires_in).await
+            .map_err(|err| {
+                tracing::error!("Failed to presign upload of {}: {}", key, err);
+                ErrorCode::InternalError.details().with("reason", "Failed to presign upload")
+            })?;
+        return Ok(single_result(key.to_string(), presigned));
+    }
+
+    let part_size = part_size(request.size);
+    let part_count = request.size.div_ceil(part_size) as i32;
+    let options = MultipartUploadOptions {
+        content_type: Some(content_type.to_string()),
+        ..Default::default()
+    };
+    let upload = s3.create_multipart_upload_context(bucket, key, Some(options)).await
+        .map_err(|err| {
+            tracing::error!("Failed to start multipart upload of {}: {}", key, err);
+            ErrorCode::InternalError.details().with("reason", "Failed to start multipart upload")
+        })?;
+
+    let mut parts = Vec::with_capacity(part_count as usize);
+    for part_number in 1..=part_count {
+        // Последняя часть короче остальных
+        let offset = (part_number as u64 - 1) * part_size;
+        let length = part_size.min(request.size - offset);
+        match s3.presign_upload_part(bucket, key, upload.upload_id(), part_number, length, expires_in).await {
+            Ok(presigned) => parts.push(PresignedPart { part_number, url: presigned.url }),
+            Err(err) => {
+                tracing::error!("Failed to presign part {} of {}: {}", part_number, key, err);
+                abort_upload(s3, bucket, key, upload.upload_id()).await;
+                return Err(ErrorCode::InternalError.details().with("reason", "Failed to presign upload"));
+            }
+        }
+    }
+
+    tracing::info!("Started presigned multipart upload of {} in {} parts", key, part_count);
+    Ok(PresignResult::Multipart {
+        key: key.to_string(),
+        expires_in: CONFIG.presign_expires_secs,
+        upload_id: upload.upload_id().to_string(),
+        part_size,
+        parts,
+    })
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/{session_id}/{track_id}/complete",
+    tag = TAG,
+    description = "Complete a presigned upload: a multipart upload is assembled from the ETags returned by S3 \
+        for each part, a single PUT is only checked. The track slot is then marked as uploaded",
+    params(
+        ("session_id" = String, Path, description = "Session id"),
+        ("track_id" = String, Path, description = "Id of track"),
+        LinkQuery,
+    ),
+    request_body = CompletePresignedRequest,
+    responses(
+        (status = 200, body = CompletedUploadResult, description = "Upload completed"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn complete_presigned_upload(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path((session_id, track_id)): Path<(String, String)>,
+    Query(link): Query<LinkQuery>,
+    Json(request): Json<CompletePresignedRequest>,
+) -> JsonResponse {
+    let s3 = json_opt!(app_state.s3.as_ref(), s3_required());
+    let target = UploadTarget { session_id, track_id };
+    let role = request.file_type.role();
+    json_err!(validate_ids(&[("session_id", &target.session_id), ("track_id", &target.track_id)]));
+    json_err!(authorize(&app_state, user.as_ref().map(|AuthUser(claims)| claims), link.token.as_deref(), &target, &[role]));
+    let format = json_err!(allowed_format(&request.filename));
+    let claimed = json_err!(claimed_slot(&app_state, &target, role).await);
+    let bucket = CONFIG.upload_bucket_name.as_str();
+
+    if let Some(upload_id) = &request.upload_id {
+        let mut seen = HashSet::new();
+        if request.parts.is_empty()
+            || request.parts.iter().any(|part| part.part_number < 1 || !seen.insert(part.part_number))
+        {
+            return ErrorCode::ValidationError.details()
+                .with("reason", "Parts must be a non-empty list with unique positive part numbers")
+                .into();
+        }
+
+        let parts: Vec<(i32, String)> = request.parts
+            .into_iter()
+            .map(|part| (part.part_number, part.e_tag))
+            .collect();
+
+        match s3.complete_multipart_upload(bucket, &claimed.key, upload_id, &parts).await {
+            Ok(()) => tracing::info!("Completed presigned multipart upload of {}", claimed.key),
+            Err(S3Error::ObjectNotFound { .. }) => return ErrorCode::NotFoundError.details()
+                .with("reason", "Multipart upload not found")
+                .into(),
+            Err(err) => {
+                tracing::error!("Failed to complete presigned upload of {}: {}", claimed.key, err);
+                return ErrorCode::CoreFileUploadingError.details().with("reason", err.to_string()).into();
+            }
+        }
+    }
+
+    // Размер подписан в каждом запросе, но объект мог так и не появиться
+    let meta = match app_state.storage.head_object(bucket, &claimed.key).await {
+        Ok(Some(meta)) if meta.size == claimed.size => meta,
+        Ok(Some(meta)) => return ErrorCode::CoreFileUploadingError.details()
+            .with("reason", "Uploaded size does not match the declared size")
+            .with("size", meta.size)
+            .with("expected", claimed.size)
+            .into(),
+        Ok(None) => return ErrorCode::NotFoundError.details()
+            .with("reason", "File has not been uploaded yet")
+            .into(),
+        Err(err) => {
+            tracing::error!("Failed to check presigned upload of {}: {}", claimed.key, err);
+            return ErrorCode::InternalError.details().into();
+        }
+    };
+
+    // Объект загружен в обход сервера: параметры аудио разбираются по его началу
+    let storage = app_state.storage.as_ref();
+    let audio = probe_object(storage, bucket, &claimed.key, format, meta.size).await;
+    let metadata_stored = store_object_metadata(storage, bucket, &claimed.key, &request.filename, audio.as_ref()).await;
+    json_err!(record_slot(&app_state, &target, role, &claimed.key, meta.size, UploadStatus::Uploaded).await);
+
+    JsonResponse::Ok(json!(CompletedUploadResult { key: claimed.key, audio, metadata_stored }))
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/{session_id}/{track_id}/abort",
+    tag = TAG,
+    description = "Abort a presigned upload: the multipart upload and its parts are freed and the track slot is marked failed",
+    params(
+        ("session_id" = String, Path, description = "Session id"),
+        ("track_id" = String, Path, description = "Id of track"),
+        LinkQuery,
+    ),
+    request_body = AbortPresignedRequest,
+    responses(
+        (status = 200, body = PresignedUploadResult, description = "Upload aborted"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn abort_presigned_upload(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path((session_id, track_id)): Path<(String, String)>,
+    Query(link): Query<LinkQuery>,
+    Json(request): Json<AbortPresignedRequest>,
+) -> JsonResponse {
+    let s3 = json_opt!(app_state.s3.as_ref(), s3_required());
+    let target = UploadTarget { session_id, track_id };
+    let role = request.file_type.role();
+    json_err!(validate_ids(&[("session_id", &target.session_id), ("track_id", &target.track_id)]));
+    json_err!(authorize(&app_state, user.as_ref().map(|AuthUser(claims)| claims), link.token.as_deref(), &target, &[role]));
+    let claimed = json_err!(claimed_slot(&app_state, &target, role).await);
+    let bucket = CONFIG.upload_bucket_name.as_str();
+
+    if let Some(upload_id) = &request.upload_id {
+        match s3.abort_multipart_upload(bucket, &claimed.key, upload_id).await {
+            Ok(()) => {}
+            Err(S3Error::ObjectNotFound { .. }) => return ErrorCode::NotFoundError.details()
+                .with("reason", "Multipart upload not found")
+                .into(),
+            Err(err) => {
+                tracing::error!("Failed to abort presigned upload of {}: {}", claimed.key, err);
+                return ErrorCode::InternalError.details().with("reason", err.to_string()).into();
+            }
+        }
+    } else if let Err(err) = app_state.storage.delete_object(bucket, &claimed.key).await {
+        // Подписанный PUT мог успеть выполниться
+        tracing::error!("Failed to delete presigned upload of {}: {}", claimed.key, err);
+    }
+    json_err!(record_slot(&app_state, &target, role, &claimed.key, 0, UploadStatus::Failed).await);
+
+    JsonResponse::Ok(json!(PresignedUploadResult { key: claimed.key }))
+}
+
+
+fn s3_required() -> BadResponseObject {
+    ErrorCode::ValidationError.details().with("reason", "Presigned uploads require the S3 storage backend")
+}
+
+/// Слот трека, занятый подписанной загрузкой: ключ и заявленный размер
+/// берутся из реестра, клиент их не передает
+async fn claimed_slot(
+    app_state: &AppState,
+    target: &UploadTarget,
+    role: TrackRole,
+) -> Result<UploadRecord, BadResponseObject> {
+    let track = app_state.sessions.get_track(&target.session_id, &target.track_id).await?;
+    match track.slot(role) {
+        Some(record) if record.status == UploadStatus::Uploading => Ok(record.clone()),
+        _ => Err(ErrorCode::NotFoundError.details()
+            .with("reason", "No upload in progress for this slot")
+            .with("role", role.as_str())),
+    }
+}
+
+/// Отмечает слот неудачным; ошибка реестра только логируется
+async fn fail_slot(app_state: &AppState, target: &UploadTarget, role: TrackRole, key: &str) {
+    if let Err(err) = record_slot(app_state, target, role, key, 0, UploadStatus::Failed).await {
+        tracing::error!("Failed to mark {} of track {} as failed: {}", role.as_str(), target.track_id, err);
+    }
+}
+
+/// Размер части: настроенный, но не меньше минимума S3 и такой,
+/// чтобы файл уложился в лимит числа частей
+fn part_size(size: u64) -> u64 {
+    CONFIG.presign_part_size
+        .max(MIN_PART_SIZE)
+        .max(size.div_ceil(MAX_PARTS))
+}
+
+fn single_result(key: String, presigned: PresignedUrl) -> PresignResult {
+    PresignResult::Single {
+        key,
+        expires_in: CONFIG.presign_expires_secs,
+        url: presigned.url,
+        // Host и Content-Length клиент подставит сам, остальные подписанные
+        // заголовки нужно повторить
+        headers: presigned.headers
+            .into_iter()
+            .filter(|(name, _)| !name.eq_ignore_ascii_case("host") && !name.eq_ignore_ascii_case("content-length"))
+            .collect(),
+    }
+}
+
+async fn abort_upload(s3: &S3Manager, bucket: &str, key: &str, upload_id: &str) {
+    if let Err(err) = s3.abort_multipart_upload(bucket, key, upload_id).await {
+        tracing::error!("Failed to abort multipart upload {} of {}: {}", upload_id, key, err);
+    }
+}
@@ -0,0 +1,154 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures_util::stream::{self, Stream};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::auth::AuthUser;
+use crate::custom_exceptions::{BadResponseObject, JsonResponse};
+use crate::endpoints::files::{validate_ids, UploadTarget};
+use crate::endpoints::links::{authorize, LinkQuery};
+use services::AppState;
+use services::progress::{ProgressSubscription, ProgressTopic, UploadProgress};
+
+
+const TAG: &str = "Upload progress";
+/// Период комментариев keep-alive, чтобы прокси не закрывали простаивающий поток
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+pub fn get_router(app_state: Arc<AppState>) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(upload_progress))
+        .routes(routes!(track_progress))
+        .with_state(app_state)
+}
+
+
+#[utoipa::path(
+    get,
+    path = "/uploads/{upload_id}",
+    tag = TAG,
+    description = "Server-Sent Events with the progress of one upload. \
+        The upload id comes from the `X-Upload-Id-<field>` request header or from track events. \
+        The stream ends after the `completed` or `failed` event; for an upload that has just finished \
+        it sends only that event. To watch an upload from its very start, subscribe to the track. \
+        Allowed with the upload scope or an upload link to the track of the upload",
+    params(("upload_id" = String, Path, description = "Upload id"), LinkQuery),
+    responses(
+        (status = 200, description = "Event stream; event name is the upload phase, data is the progress", content_type = "text/event-stream"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 403, description = "Access to the track is not allowed", body = BadResponseObject),
+        (status = 404, description = "Upload is neither in progress nor recently finished", body = BadResponseObject),
+    ),
+)]
+async fn upload_progress(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path(upload_id): Path<String>,
+    Query(link): Query<LinkQuery>,
+) -> Response {
+    if let Err(err) = validate_ids(&[("upload_id", &upload_id)]) {
+        return JsonResponse::Err(err).into_response();
+    }
+
+    // Настоящие статусы, чтобы EventSource не переподключался впустую
+    let (snapshot, subscription) = match app_state.progress.subscribe(ProgressTopic::Upload(upload_id)) {
+        Ok(subscribed) => subscribed,
+        Err(err) => return BadResponseObject::from(err).into_response_with_status(StatusCode::NOT_FOUND),
+    };
+    // Снимок подписки на загрузку всегда содержит ее саму
+    if let Some(progress) = snapshot.first() {
+        let target = UploadTarget { session_id: progress.session_id.clone(), track_id: progress.track_id.clone() };
+        let user = user.as_ref().map(|AuthUser(claims)| claims);
+        if let Err(err) = authorize(&app_state, user, link.token.as_deref(), &target, &[]) {
+            return err.into_response_with_status(StatusCode::FORBIDDEN);
+        }
+    }
+
+    progress_stream(snapshot, subscription).into_response()
+}
+
+
+#[utoipa::path(
+    get,
+    path = "/tracks/{session_id}/{track_id}",
+    tag = TAG,
+    description = "Server-Sent Events with the progress of every upload into a track, \
+        starting with the uploads already in progress. \
+        Allowed with the upload scope or an upload link to the track",
+    params(
+        ("session_id" = String, Path, description = "Session id"),
+        ("track_id" = String, Path, description = "Id of track"),
+        LinkQuery,
+    ),
+    responses(
+        (status = 200, description = "Event stream; event name is the upload phase, data is the progress", content_type = "text/event-stream"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 403, description = "Access to the track is not allowed", body = BadResponseObject),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn track_progress(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path((session_id, track_id)): Path<(String, String)>,
+    Query(link): Query<LinkQuery>,
+) -> Response {
+    if let Err(err) = validate_ids(&[("session_id", &session_id), ("track_id", &track_id)]) {
+        return JsonResponse::Err(err).into_response();
+    }
+    let target = UploadTarget { session_id, track_id };
+    let user = user.as_ref().map(|AuthUser(claims)| claims);
+    if let Err(err) = authorize(&app_state, user, link.token.as_deref(), &target, &[]) {
+        return err.into_response_with_status(StatusCode::FORBIDDEN);
+    }
+    if let Err(err) = app_state.sessions.get_track(&target.session_id, &target.track_id).await {
+        return JsonResponse::Err(err.into()).into_response();
+    }
+
+    let topic = ProgressTopic::Track { session_id: target.session_id, track_id: target.track_id };
+    match app_state.progress.subscribe(topic) {
+        Ok((snapshot, subscription)) => progress_stream(snapshot, subscription).into_response(),
+        Err(err) => JsonResponse::Err(err.into()).into_response(),
+    }
+}
+
+
+/// Поток SSE: сначала текущее состояние незавершенных загрузок, затем новые события.
+/// Подписка освобождается, когда клиент отключается
+fn progress_stream(
+    snapshot: Vec<UploadProgress>,
+    subscription: ProgressSubscription,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = stream::unfold(
+        (snapshot.into_iter(), subscription),
+        |(mut snapshot, mut subscription): (std::vec::IntoIter<UploadProgress>, ProgressSubscription)| async move {
+            let progress = match snapshot.next() {
+                Some(progress) => progress,
+                None => subscription.recv().await?,
+            };
+            Some((Ok(progress_event(&progress)), (snapshot, subscription)))
+        },
+    );
+
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(KEEP_ALIVE_INTERVAL))
+}
+
+fn progress_event(progress: &UploadProgress) -> Event {
+    let event = Event::default()
+        .event(progress.phase.as_str())
+        .id(&progress.upload_id);
+    event.json_data(progress).unwrap_or_else(|err| {
+        tracing::error!("Failed to serialize upload progress: {}", err);
+        Event::default().event("error")
+    })
+}
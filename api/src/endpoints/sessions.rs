@@ -0,0 +1,353 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Multipart, Path, Query, State},
+    http::HeaderMap,
+    middleware,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use ulid::Ulid;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::custom_exceptions::{BadResponseObject, ErrorCode, JsonResponse};
+use crate::auth::{check_upload_scope, AuthUser};
+use crate::body_limit::limit_body;
+use crate::endpoints::files::{upload_into_track, validate_ids, UploadLimits, UploadTarget};
+use crate::endpoints::links::{authorize, authorize_bearer, LinkQuery};
+use crate::shutdown::track_upload;
+use crate::{json_err, json_opt};
+use services::AppState;
+use services::sessions::{Session, Track, TrackRole, TrackStatus, UploadRecord};
+
+
+const TAG: &str = "Sessions";
+
+pub fn get_router(app_state: Arc<AppState>) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(create_session))
+        .routes(routes!(get_session, delete_session))
+        .routes(routes!(create_track))
+        .routes(routes!(get_track))
+        .routes(routes!(finalize_track))
+        .merge(OpenApiRouter::new()
+            .routes(routes!(upload_track_file))
+            .route_layer(middleware::from_fn_with_state(UploadLimits::SINGLE.request(), limit_body))
+            .route_layer(middleware::from_fn_with_state(Arc::clone(&app_state), track_upload)))
+        .with_state(app_state)
+}
+
+#[derive(Deserialize, ToSchema, Default)]
+#[serde(default)]
+struct CreateSessionRequest {
+    /// Идентификатор сессии; если не задан, генерируется ULID
+    session_id: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema, Default)]
+#[serde(default)]
+struct CreateTrackRequest {
+    /// Идентификатор трека; если не задан, генерируется ULID
+    track_id: Option<String>,
+}
+
+/// Слот трека: вокал или минус
+#[derive(Serialize, ToSchema)]
+struct SlotInfo {
+    #[schema(value_type = String, example = "uploaded")]
+    status: TrackStatus,
+    /// Ключ объекта в хранилище
+    key: Option<String>,
+    size: Option<u64>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<Option<&UploadRecord>> for SlotInfo {
+    fn from(record: Option<&UploadRecord>) -> Self {
+        Self {
+            status: TrackStatus::from(record),
+            key: record.map(|record| record.key.clone()),
+            size: record.map(|record| record.size),
+            updated_at: record.map(|record| record.updated_at),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(example = json!({
+    "track_id": "track_1",
+    "status": "uploaded",
+    "created_at": "2025-03-01T12:00:00Z",
+    "finalized_at": null,
+    "vocal": {"status": "uploaded", "key": "session_1/track_1/vocal-01jq3v6k2m8e4x0y9z7w5t1r3s.wav", "size": 31457280, "updated_at": "2025-03-01T12:01:00Z"},
+    "instrumental": {"status": "pending", "key": null, "size": null, "updated_at": null}
+}))]
+struct TrackInfo {
+    track_id: String,
+    /// `pending`, `uploading`, `uploaded` (оба файла) или `failed`
+    #[schema(value_type = String)]
+    status: TrackStatus,
+    #[schema(value_type = String, format = DateTime)]
+    created_at: DateTime<Utc>,
+    /// Финализированный трек закрыт для загрузок
+    #[schema(value_type = Option<String>, format = DateTime)]
+    finalized_at: Option<DateTime<Utc>>,
+    vocal: SlotInfo,
+    instrumental: SlotInfo,
+}
+
+impl From<Track> for TrackInfo {
+    fn from(track: Track) -> Self {
+        Self {
+            status: track.status(),
+            vocal: SlotInfo::from(track.slot(TrackRole::Vocal)),
+            instrumental: SlotInfo::from(track.slot(TrackRole::Instrumental)),
+            track_id: track.id,
+            created_at: track.created_at,
+            finalized_at: track.finalized_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct SessionInfo {
+    session_id: String,
+    #[schema(value_type = String, format = DateTime)]
+    created_at: DateTime<Utc>,
+    /// После этого времени сессия и ее треки удаляются
+    #[schema(value_type = String, format = DateTime)]
+    expires_at: DateTime<Utc>,
+    tracks: Vec<TrackInfo>,
+}
+
+impl From<Session> for SessionInfo {
+    fn from(session: Session) -> Self {
+        Self {
+            session_id: session.id,
+            created_at: session.created_at,
+            expires_at: session.expires_at,
+            tracks: session.tracks.into_iter().map(TrackInfo::from).collect(),
+        }
+    }
+}
+
+fn new_id() -> String {
+    Ulid::new().to_string().to_ascii_lowercase()
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/",
+    tag = TAG,
+    description = "Create an upload session. When JWT is configured, requires a bearer token with the upload scope",
+    request_body = CreateSessionRequest,
+    responses(
+        (status = 200, body = SessionInfo, description = "Session created"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn create_session(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Json(request): Json<CreateSessionRequest>,
+) -> JsonResponse {
+    json_err!(check_upload_scope(&app_state, user.as_ref()));
+    let session_id = request.session_id.unwrap_or_else(new_id);
+    json_err!(validate_ids(&[("session_id", &session_id)]));
+
+    let session = json_err!(app_state.sessions.create_session(&session_id).await);
+    JsonResponse::Ok(json!(SessionInfo::from(session)))
+}
+
+
+#[utoipa::path(
+    get,
+    path = "/{session_id}",
+    tag = TAG,
+    description = "Get a session with the status of all its tracks. \
+        When JWT or upload links are configured, requires a bearer token with the upload scope",
+    params(("session_id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, body = SessionInfo, description = "Session"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn get_session(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path(session_id): Path<String>,
+) -> JsonResponse {
+    json_err!(authorize_bearer(&app_state, user.as_ref().map(|AuthUser(claims)| claims)));
+    json_err!(validate_ids(&[("session_id", &session_id)]));
+
+    let session = json_err!(app_state.sessions.get_session(&session_id).await);
+    JsonResponse::Ok(json!(SessionInfo::from(session)))
+}
+
+
+#[utoipa::path(
+    delete,
+    path = "/{session_id}",
+    tag = TAG,
+    description = "Delete a session and its tracks. Uploaded files stay in the storage. \
+        When JWT is configured, requires a bearer token with the upload scope",
+    params(("session_id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, description = "Session deleted"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn delete_session(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path(session_id): Path<String>,
+) -> JsonResponse {
+    json_err!(check_upload_scope(&app_state, user.as_ref()));
+    json_err!(validate_ids(&[("session_id", &session_id)]));
+
+    json_err!(app_state.sessions.delete_session(&session_id).await);
+    JsonResponse::Ok(json!({"session_id": session_id}))
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/{session_id}/tracks",
+    tag = TAG,
+    description = "Create a track with empty vocal and instrumental slots. \
+        When JWT is configured, requires a bearer token with the upload scope",
+    params(("session_id" = String, Path, description = "Session id")),
+    request_body = CreateTrackRequest,
+    responses(
+        (status = 200, body = TrackInfo, description = "Track created"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn create_track(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path(session_id): Path<String>,
+    Json(request): Json<CreateTrackRequest>,
+) -> JsonResponse {
+    json_err!(check_upload_scope(&app_state, user.as_ref()));
+    let track_id = request.track_id.unwrap_or_else(new_id);
+    json_err!(validate_ids(&[("session_id", &session_id), ("track_id", &track_id)]));
+
+    let track = json_err!(app_state.sessions.add_track(&session_id, &track_id).await);
+    JsonResponse::Ok(json!(TrackInfo::from(track)))
+}
+
+
+#[utoipa::path(
+    get,
+    path = "/{session_id}/tracks/{track_id}",
+    tag = TAG,
+    description = "Get the status of a track and its slots: pending, uploading, uploaded or failed. \
+        Allowed with the upload scope or an upload link to the track",
+    params(
+        ("session_id" = String, Path, description = "Session id"),
+        ("track_id" = String, Path, description = "Id of track"),
+        LinkQuery,
+    ),
+    responses(
+        (status = 200, body = TrackInfo, description = "Track"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn get_track(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path((session_id, track_id)): Path<(String, String)>,
+    Query(link): Query<LinkQuery>,
+) -> JsonResponse {
+    json_err!(validate_ids(&[("session_id", &session_id), ("track_id", &track_id)]));
+    let target = UploadTarget { session_id, track_id };
+    json_err!(authorize(&app_state, user.as_ref().map(|AuthUser(claims)| claims), link.token.as_deref(), &target, &[]));
+
+    let track = json_err!(app_state.sessions.get_track(&target.session_id, &target.track_id).await);
+    JsonResponse::Ok(json!(TrackInfo::from(track)))
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/{session_id}/tracks/{track_id}/finalize",
+    tag = TAG,
+    description = "Finalize a track: lock it against further uploads. \
+        Requires at least one uploaded file and no upload in progress. \
+        Allowed with the upload scope or an upload link to both slots",
+    params(
+        ("session_id" = String, Path, description = "Session id"),
+        ("track_id" = String, Path, description = "Id of track"),
+        LinkQuery,
+    ),
+    responses(
+        (status = 200, body = TrackInfo, description = "Track finalized"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn finalize_track(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path((session_id, track_id)): Path<(String, String)>,
+    Query(link): Query<LinkQuery>,
+) -> JsonResponse {
+    json_err!(validate_ids(&[("session_id", &session_id), ("track_id", &track_id)]));
+    // Финализировать трек может и владелец ссылки на оба слота
+    let target = UploadTarget { session_id, track_id };
+    let user = user.as_ref().map(|AuthUser(claims)| claims);
+    json_err!(authorize(&app_state, user, link.token.as_deref(), &target, &TrackRole::ALL));
+    let UploadTarget { session_id, track_id } = target;
+
+    let track = json_err!(app_state.sessions.finalize_track(&session_id, &track_id).await);
+    JsonResponse::Ok(json!(TrackInfo::from(track)))
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/{session_id}/tracks/{track_id}/{role}",
+    tag = TAG,
+    description = "Upload one file into the vocal or instrumental slot of a track (multipart field `track`)",
+    params(
+        ("session_id" = String, Path, description = "Session id"),
+        ("track_id" = String, Path, description = "Id of track"),
+        ("role" = String, Path, description = "`vocal` or `instrumental`"),
+        LinkQuery,
+    ),
+    request_body(content_type = "multipart/form-data", description = "Multipart form with a `track` file field"),
+    responses(
+        (status = 200, description = "Track uploaded successfully!"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+async fn upload_track_file(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path((session_id, track_id, role)): Path<(String, String, String)>,
+    Query(link): Query<LinkQuery>,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> JsonResponse {
+    let role = json_opt!(
+        TrackRole::from_name(&role),
+        ErrorCode::ValidationError.details()
+            .with("reason", "Role must be vocal or instrumental")
+            .with("role", &role)
+    );
+    let target = UploadTarget { session_id, track_id };
+    let user = user.as_ref().map(|AuthUser(claims)| claims);
+    upload_into_track(&app_state, &target, role, user, link.token.as_deref(), &headers, multipart).await
+}
@@ -0,0 +1,579 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use chrono::Utc;
+
+use crate::auth::AuthUser;
+use crate::custom_exceptions::{BadResponseObject, ErrorCode};
+use crate::endpoints::files::{allowed_format, object_key, probe_object, store_object_metadata, validate_ids, UploadTarget};
+use crate::endpoints::links::{authorize, authorize_upload, LinkQuery};
+use crate::shutdown::track_upload;
+use my_core::config::CONFIG;
+use services::AppState;
+use services::sessions::{SessionError, TrackRole, UploadRecord, UploadStatus};
+use services::auth::Claims;
+use services::tus::{TusChecksum, TusError, TusUpload, TUS_CHECKSUM_ALGORITHMS};
+
+
+const TAG: &str = "Tus";
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination,checksum";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+const TUS_VERSION_HEADER: HeaderName = HeaderName::from_static("tus-version");
+const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+const TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+const TUS_CHECKSUM_ALGORITHM: HeaderName = HeaderName::from_static("tus-checksum-algorithm");
+const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+const UPLOAD_CHECKSUM: HeaderName = HeaderName::from_static("upload-checksum");
+
+/// Нестандартный статус расширения `checksum`
+const CHECKSUM_MISMATCH: u16 = 460;
+
+pub fn get_router(app_state: Arc<AppState>) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(routes!(tus_options, tus_create))
+        .routes(routes!(tus_head, tus_patch, tus_delete))
+        // Во время остановки отклоняются и PATCH: клиент продолжит загрузку с сохраненного смещения
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&app_state), track_upload))
+        .with_state(app_state)
+}
+
+
+#[utoipa::path(
+    options,
+    path = "/uploads",
+    tag = TAG,
+    description = "tus server capabilities",
+    responses(
+        (status = 204, description = "Supported version, extensions and limits in Tus-* headers"),
+    ),
+)]
+pub async fn tus_options() -> Response {
+    let mut headers = tus_headers();
+    headers.insert(TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION));
+    headers.insert(TUS_EXTENSION, HeaderValue::from_static(TUS_EXTENSIONS));
+    headers.insert(TUS_MAX_SIZE, HeaderValue::from(max_size()));
+    headers.insert(TUS_CHECKSUM_ALGORITHM, HeaderValue::from_str(&TUS_CHECKSUM_ALGORITHMS.join(",")).unwrap());
+
+    (StatusCode::NO_CONTENT, headers).into_response()
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/uploads",
+    tag = TAG,
+    description = "Create a resumable upload into a track slot (tus creation extension). \
+        The slot is marked as uploading until the last chunk arrives or the upload is terminated",
+    params(
+        ("Tus-Resumable" = String, Header, description = "Protocol version, must be 1.0.0"),
+        ("Upload-Length" = u64, Header, description = "Size of the whole file in bytes"),
+        ("Upload-Metadata" = String, Header, description = "Comma separated `key base64(value)` pairs: \
+            `session_id`, `track_id`, `role` (`vocal` or `instrumental`) and `filename` are required. \
+            The stored Content-Type is taken from the file extension, `filetype` is ignored"),
+        LinkQuery,
+    ),
+    responses(
+        (status = 201, description = "Upload created, its URL is in the Location header"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 403, description = "Upload into this track is not allowed", body = BadResponseObject),
+        (status = 404, description = "Session or track not found", body = BadResponseObject),
+        (status = 409, description = "Track is finalized or the slot is busy", body = BadResponseObject),
+        (status = 413, description = "Upload-Length exceeds Tus-Max-Size", body = BadResponseObject),
+        (status = 415, description = "File extension is not allowed", body = BadResponseObject),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    ),
+)]
+pub async fn tus_create(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Query(link): Query<LinkQuery>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = check_version(&headers) {
+        return response;
+    }
+
+    let Some(length) = header_u64(&headers, &UPLOAD_LENGTH) else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::ValidationError.details().with("reason", "Missing or invalid Upload-Length header"),
+        );
+    };
+    if length > max_size() {
+        return error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::PayloadTooLarge.details().with("max_size", max_size()),
+        );
+    }
+
+    let metadata = match headers.get(&UPLOAD_METADATA) {
+        Some(value) => match parse_metadata(value) {
+            Some(metadata) => metadata,
+            None => return error_response(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::ValidationError.details().with("reason", "Invalid Upload-Metadata header"),
+            ),
+        },
+        None => BTreeMap::new(),
+    };
+
+    let (target, role) = match upload_target(&metadata) {
+        Ok(target) => target,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+    let user = user.as_ref().map(|AuthUser(claims)| claims);
+    if let Err(err) = authorize_upload(&app_state, user, link.token.as_deref(), &target, &[role]).await {
+        return error_response(StatusCode::FORBIDDEN, err);
+    }
+
+    // Формат проверяется по расширению, как при загрузке формой, а начало
+    // файла сверяется с ним при первых PATCH-запросах
+    let Some(filename) = metadata.get("filename").filter(|filename| !filename.is_empty()) else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::ValidationError.details().with("reason", "Upload-Metadata must contain filename"),
+        );
+    };
+    let format = match allowed_format(filename) {
+        Ok(format) => format,
+        Err(err) => return error_response(StatusCode::UNSUPPORTED_MEDIA_TYPE, err),
+    };
+
+    // Слот трека занимается так же, как при загрузке формой: в финализированный
+    // трек или в слот с идущей загрузкой tus-загрузка не начнется
+    let key = match object_key(&app_state, &target, role.as_str(), filename) {
+        Ok(key) => key,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+    if let Err(response) = record_track(&app_state, &target, role, &key, length, UploadStatus::Uploading).await {
+        return response;
+    }
+
+    let upload = match app_state.tus.create(key.clone(), length, Some(format), metadata).await {
+        Ok(upload) => upload,
+        Err(err) => {
+            let _ = record_track(&app_state, &target, role, &key, 0, UploadStatus::Failed).await;
+            return tus_error(err);
+        }
+    };
+    // Загрузка нулевой длины завершена сразу
+    if upload.completed {
+        if let Err(response) = complete_track(&app_state, &upload).await {
+            return response;
+        }
+    }
+
+    // Ссылка нужна и для следующих запросов, поэтому остается в адресе загрузки
+    let mut location = format!(
+        "{}{}tus/uploads/{}",
+        CONFIG.upload_public_domain, CONFIG.api_v1_str, upload.id
+    );
+    if let Some(token) = link.token.as_deref().filter(|token| !token.is_empty()) {
+        location.push_str(&format!("?token={token}"));
+    }
+    let mut headers = tus_headers();
+    headers.insert(header::LOCATION, HeaderValue::from_str(&location).unwrap());
+    headers.insert(UPLOAD_OFFSET, HeaderValue::from(upload.offset));
+
+    (StatusCode::CREATED, headers).into_response()
+}
+
+
+#[utoipa::path(
+    head,
+    path = "/uploads/{upload_id}",
+    tag = TAG,
+    description = "Current offset of the upload, used to resume it",
+    params(
+        ("upload_id" = String, Path, description = "Upload id from the Location header"),
+        ("Tus-Resumable" = String, Header, description = "Protocol version, must be 1.0.0"),
+        LinkQuery,
+    ),
+    responses(
+        (status = 200, description = "Upload-Offset and Upload-Length headers"),
+        (status = 403, description = "Access to the upload track is not allowed", body = BadResponseObject),
+        (status = 404, description = "Upload not found"),
+    ),
+)]
+pub async fn tus_head(
+    State(app_state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+    user: Option<AuthUser>,
+    Query(link): Query<LinkQuery>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = check_version(&headers) {
+        return response;
+    }
+
+    let user = user.as_ref().map(|AuthUser(claims)| claims);
+    let upload = match authorized_upload(&app_state, user, link.token.as_deref(), &upload_id).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+
+    let mut headers = upload_headers(&upload);
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    if !upload.metadata.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&encode_metadata(&upload.metadata)) {
+            headers.insert(UPLOAD_METADATA, value);
+        }
+    }
+
+    (StatusCode::OK, headers).into_response()
+}
+
+
+#[utoipa::path(
+    patch,
+    path = "/uploads/{upload_id}",
+    tag = TAG,
+    description = "Append a chunk to the upload starting at Upload-Offset",
+    params(
+        ("upload_id" = String, Path, description = "Upload id from the Location header"),
+        ("Tus-Resumable" = String, Header, description = "Protocol version, must be 1.0.0"),
+        ("Upload-Offset" = u64, Header, description = "Offset the chunk starts at, must equal the current offset"),
+        ("Upload-Checksum" = Option<String>, Header, description = "`algorithm base64(digest)` of the chunk"),
+        LinkQuery,
+    ),
+    request_body(content = Vec<u8>, content_type = "application/offset+octet-stream", description = "Chunk of the file"),
+    responses(
+        (status = 204, description = "Chunk stored, new offset in the Upload-Offset header"),
+        (status = 400, description = "Bad request", body = BadResponseObject),
+        (status = 403, description = "Access to the upload track is not allowed", body = BadResponseObject),
+        (status = 404, description = "Upload not found", body = BadResponseObject),
+        (status = 409, description = "Upload-Offset does not match the current offset", body = BadResponseObject),
+        (status = 415, description = "Wrong Content-Type", body = BadResponseObject),
+        (status = 415, description = "File content does not match its extension, the upload is terminated", body = BadResponseObject),
+        (status = 423, description = "Upload is being written by another request", body = BadResponseObject),
+        (status = 460, description = "Checksum mismatch", body = BadResponseObject),
+    ),
+)]
+pub async fn tus_patch(
+    State(app_state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+    user: Option<AuthUser>,
+    Query(link): Query<LinkQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    if let Some(response) = check_version(&headers) {
+        return response;
+    }
+
+    let user = user.as_ref().map(|AuthUser(claims)| claims);
+    if let Err(response) = authorized_upload(&app_state, user, link.token.as_deref(), &upload_id).await {
+        return response;
+    }
+
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    if content_type != Some(OFFSET_CONTENT_TYPE) {
+        return error_response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::WrongFormat.details().with("reason", format!("Content-Type must be {OFFSET_CONTENT_TYPE}")),
+        );
+    }
+
+    let Some(offset) = header_u64(&headers, &UPLOAD_OFFSET) else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::ValidationError.details().with("reason", "Missing or invalid Upload-Offset header"),
+        );
+    };
+
+    let checksum = match headers.get(&UPLOAD_CHECKSUM).map(parse_checksum) {
+        Some(Ok(checksum)) => Some(checksum),
+        Some(Err(err)) => return tus_error(err),
+        None => None,
+    };
+
+    let upload = match app_state.tus.append(&upload_id, offset, body.into_data_stream(), checksum).await {
+        Ok(upload) => upload,
+        Err(err @ TusError::WrongFormat { .. }) => {
+            // Загрузку с чужим содержимым не продолжить: данные удаляются, слот освобождается
+            if let Err(response) = discard_upload(&app_state, &upload_id).await {
+                return response;
+            }
+            return tus_error(err);
+        }
+        Err(err) => return tus_error(err),
+    };
+    if upload.completed {
+        if let Err(response) = complete_track(&app_state, &upload).await {
+            return response;
+        }
+    }
+
+    (StatusCode::NO_CONTENT, upload_headers(&upload)).into_response()
+}
+
+
+#[utoipa::path(
+    delete,
+    path = "/uploads/{upload_id}",
+    tag = TAG,
+    description = "Terminate the upload and delete its data (tus termination extension)",
+    params(
+        ("upload_id" = String, Path, description = "Upload id from the Location header"),
+        ("Tus-Resumable" = String, Header, description = "Protocol version, must be 1.0.0"),
+        LinkQuery,
+    ),
+    responses(
+        (status = 204, description = "Upload terminated"),
+        (status = 403, description = "Access to the upload track is not allowed", body = BadResponseObject),
+        (status = 404, description = "Upload not found", body = BadResponseObject),
+        (status = 423, description = "Upload is being written by another request", body = BadResponseObject),
+    ),
+)]
+pub async fn tus_delete(
+    State(app_state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+    user: Option<AuthUser>,
+    Query(link): Query<LinkQuery>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = check_version(&headers) {
+        return response;
+    }
+
+    let user = user.as_ref().map(|AuthUser(claims)| claims);
+    if let Err(response) = authorized_upload(&app_state, user, link.token.as_deref(), &upload_id).await {
+        return response;
+    }
+
+    match discard_upload(&app_state, &upload_id).await {
+        Ok(()) => (StatusCode::NO_CONTENT, tus_headers()).into_response(),
+        Err(response) => response,
+    }
+}
+
+
+/// Наибольший размер tus-загрузки: не больше, чем разрешено при загрузке через сервер
+fn max_size() -> u64 {
+    CONFIG.tus_max_size.min(CONFIG.upload_max_file_size)
+}
+
+/// Трек и слот загрузки из `Upload-Metadata`
+fn upload_target(metadata: &BTreeMap<String, String>) -> Result<(UploadTarget, TrackRole), BadResponseObject> {
+    let field = |name: &str| {
+        metadata.get(name).cloned().ok_or_else(|| ErrorCode::ValidationError.details()
+            .with("reason", format!("Upload-Metadata must contain {name}")))
+    };
+    let target = UploadTarget { session_id: field("session_id")?, track_id: field("track_id")? };
+    validate_ids(&[("session_id", &target.session_id), ("track_id", &target.track_id)])?;
+    let role = TrackRole::from_name(&field("role")?).ok_or_else(|| ErrorCode::ValidationError.details()
+        .with("reason", "role must be vocal or instrumental"))?;
+    Ok((target, role))
+}
+
+/// Загрузка, если запрос имеет доступ к треку и слоту из ее метаданных, как при
+/// создании; одноразовая ссылка, израсходованная при создании, продолжает действовать
+async fn authorized_upload(
+    app_state: &AppState,
+    user: Option<&Claims>,
+    token: Option<&str>,
+    upload_id: &str,
+) -> Result<TusUpload, Response> {
+    let upload = app_state.tus.get(upload_id).await.map_err(tus_error)?;
+    let (target, role) = upload_target(&upload.metadata).map_err(|err| error_response(StatusCode::BAD_REQUEST, err))?;
+    authorize(app_state, user, token, &target, &[role]).map_err(|err| error_response(StatusCode::FORBIDDEN, err))?;
+    Ok(upload)
+}
+
+/// Освобождает слот трека и удаляет загрузку вместе с данными. Слот
+/// освобождается первым: в финализированном треке загрузку не отменить
+async fn discard_upload(app_state: &AppState, upload_id: &str) -> Result<(), Response> {
+    let upload = app_state.tus.get(upload_id).await.map_err(tus_error)?;
+    if let Ok((target, role)) = upload_target(&upload.metadata) {
+        let track = match app_state.sessions.get_track(&target.session_id, &target.track_id).await {
+            Ok(track) => Some(track),
+            Err(SessionError::SessionNotFound(_) | SessionError::TrackNotFound { .. }) => None,
+            Err(err) => return Err(session_error(err)),
+        };
+        if track.as_ref().and_then(|track| track.slot(role)).is_some_and(|record| record.key == upload.key) {
+            record_track(app_state, &target, role, &upload.key, 0, UploadStatus::Failed).await?;
+        }
+    }
+
+    app_state.tus.terminate(upload_id).await.map_err(tus_error)
+}
+
+/// Записывает состояние слота трека; занять слот, в который уже идет загрузка, нельзя
+async fn record_track(
+    app_state: &AppState,
+    target: &UploadTarget,
+    role: TrackRole,
+    key: &str,
+    size: u64,
+    status: UploadStatus,
+) -> Result<(), Response> {
+    let record = UploadRecord { key: key.to_string(), size, status, updated_at: Utc::now() };
+    let result = match status {
+        UploadStatus::Uploading => app_state.sessions.claim_upload(&target.session_id, &target.track_id, role, record).await,
+        _ => app_state.sessions.record_upload(&target.session_id, &target.track_id, role, record).await,
+    };
+    result.map(|_| ()).map_err(session_error)
+}
+
+/// Сохраняет исходное имя файла и параметры аудио в метаданных объекта
+/// и отмечает слот загруженным
+async fn complete_track(app_state: &AppState, upload: &TusUpload) -> Result<(), Response> {
+    let Ok((target, role)) = upload_target(&upload.metadata) else {
+        return Ok(());
+    };
+
+    let storage = app_state.storage.as_ref();
+    let bucket = app_state.tus.bucket();
+    let audio = match upload.format {
+        Some(format) => probe_object(storage, bucket, &upload.key, format, upload.length).await,
+        None => None,
+    };
+    let filename = upload.metadata.get("filename").map(String::as_str).unwrap_or_default();
+    store_object_metadata(storage, bucket, &upload.key, filename, audio.as_ref()).await;
+    record_track(app_state, &target, role, &upload.key, upload.length, UploadStatus::Uploaded).await
+}
+
+fn session_error(err: SessionError) -> Response {
+    let status = match &err {
+        SessionError::SessionNotFound(_) | SessionError::TrackNotFound { .. } => StatusCode::NOT_FOUND,
+        SessionError::Contended { .. } => StatusCode::TOO_MANY_REQUESTS,
+        SessionError::Redis(_) | SessionError::Serialization(_) => {
+            tracing::error!("Session registry error: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        _ => StatusCode::CONFLICT,
+    };
+    error_response(status, err.into())
+}
+
+fn tus_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    headers
+}
+
+fn upload_headers(upload: &TusUpload) -> HeaderMap {
+    let mut headers = tus_headers();
+    headers.insert(UPLOAD_OFFSET, HeaderValue::from(upload.offset));
+    headers.insert(UPLOAD_LENGTH, HeaderValue::from(upload.length));
+    headers
+}
+
+/// Ответ с ошибкой и реальным HTTP-статусом, который нужен tus-клиентам
+fn error_response(status: StatusCode, error: BadResponseObject) -> Response {
+    let mut response = error.into_response_with_status(status);
+    response.headers_mut().extend(tus_headers());
+    response
+}
+
+fn tus_error(err: TusError) -> Response {
+    match err {
+        TusError::NotFound(_) => error_response(
+            StatusCode::NOT_FOUND,
+            ErrorCode::NotFoundError.details().with("reason", err.to_string()),
+        ),
+        TusError::OffsetMismatch { expected, .. } => error_response(
+            StatusCode::CONFLICT,
+            ErrorCode::ValidationError.details()
+                .with("reason", err.to_string())
+                .with("offset", expected),
+        ),
+        TusError::Locked(_) => error_response(
+            StatusCode::LOCKED,
+            ErrorCode::ValidationError.details().with("reason", err.to_string()),
+        ),
+        TusError::ChecksumMismatch => error_response(
+            StatusCode::from_u16(CHECKSUM_MISMATCH).unwrap(),
+            ErrorCode::ChecksumMismatch.details().with("reason", err.to_string()),
+        ),
+        TusError::ExceedsLength(_) => error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::PayloadTooLarge.details().with("reason", err.to_string()),
+        ),
+        TusError::WrongFormat { expected, detected } => error_response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::WrongFormat.details()
+                .with("reason", "File content does not match its extension")
+                .with("expected", expected.name())
+                .with("detected", detected.map(|format| format.name())),
+        ),
+        TusError::UnsupportedChecksum(_) | TusError::Body(_) => error_response(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::ValidationError.details().with("reason", err.to_string()),
+        ),
+        TusError::State(_) | TusError::Storage(_) => {
+            tracing::error!("Tus upload failed: {}", err);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError.details())
+        }
+    }
+}
+
+/// Все запросы, кроме OPTIONS, обязаны указывать поддерживаемую версию протокола
+fn check_version(headers: &HeaderMap) -> Option<Response> {
+    if headers.get(&TUS_RESUMABLE).is_some_and(|value| value == TUS_VERSION) {
+        return None;
+    }
+
+    let mut response = error_response(
+        StatusCode::PRECONDITION_FAILED,
+        ErrorCode::ValidationError.details().with("reason", format!("Tus-Resumable must be {TUS_VERSION}")),
+    );
+    response.headers_mut().insert(TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION));
+    Some(response)
+}
+
+fn header_u64(headers: &HeaderMap, name: &HeaderName) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+/// Разбирает `Upload-Metadata`: пары `ключ base64(значение)` через запятую,
+/// значение может отсутствовать
+fn parse_metadata(value: &HeaderValue) -> Option<BTreeMap<String, String>> {
+    let mut metadata = BTreeMap::new();
+
+    for pair in value.to_str().ok()?.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let mut parts = pair.split(' ');
+        let key = parts.next()?;
+        let value = match parts.next() {
+            Some(encoded) => String::from_utf8(BASE64.decode(encoded).ok()?).ok()?,
+            None => String::new(),
+        };
+        if parts.next().is_some() || metadata.insert(key.to_string(), value).is_some() {
+            return None;
+        }
+    }
+
+    Some(metadata)
+}
+
+fn encode_metadata(metadata: &BTreeMap<String, String>) -> String {
+    metadata
+        .iter()
+        .map(|(key, value)| format!("{key} {}", BASE64.encode(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Разбирает `Upload-Checksum: <алгоритм> <base64(дайджест)>`
+fn parse_checksum(value: &HeaderValue) -> Result<TusChecksum, TusError> {
+    let invalid = || TusError::UnsupportedChecksum(String::from_utf8_lossy(value.as_bytes()).into_owned());
+
+    let (algorithm, digest) = value.to_str().ok().and_then(|value| value.split_once(' ')).ok_or_else(invalid)?;
+    let digest = BASE64.decode(digest.trim()).map_err(|_| invalid())?;
+    TusChecksum::new(algorithm, digest)
+}
@@ -1,17 +1,23 @@
-use axum::extract::Path;
+use axum::extract::{Path, Query, State};
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_axum::routes;
 use my_core::config::CONFIG;
+use crate::auth::AuthUser;
 use crate::custom_exceptions::{ErrorCode, BadResponseObject, HtmlResponse};
 use axum::response::Html as AxumHtml;
+use crate::endpoints::files::{validate_ids, UploadTarget};
+use crate::endpoints::links::{authorize, LinkQuery};
 use services::AppState;
+use services::sessions::TrackRole;
 use std::sync::Arc;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 
 const TAG: &str = "WebUI";
 pub fn get_router(app_state: Arc<AppState>) -> OpenApiRouter {
     OpenApiRouter::new()
         .routes(routes!(upload_ui))
         .routes(routes!(upload_ui_multiple))
+        .routes(routes!(upload_ui_multiple_filesystem))
         .with_state(app_state)
 }
 
@@ -23,6 +29,7 @@ pub fn get_router(app_state: Arc<AppState>) -> OpenApiRouter {
         ("session_id" = String, Path, description = "Session id"),
         ("track_id" = String, Path, description = "Id of track"),
         ("file_type" = String, Path, description = "Type: **vocal** or **instrumental**"),
+        LinkQuery,
     ),
     responses(
         (status = 200, description = "Number returned successfully", body = String, content_type = "text/html"),
@@ -30,14 +37,23 @@ pub fn get_router(app_state: Arc<AppState>) -> OpenApiRouter {
         (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
     )
 )]
-pub async fn upload_ui(Path((session_id, track_id, file_type)): Path<(String, String, String)>) -> HtmlResponse {
-    if session_id == "ses" {
-        return HtmlResponse::from(ErrorCode::AuthorizeError.details()
-            .with("reason", "You have already taken access to this endpoint."));
-    }
-
-    tracing::info!("Hello from tracing!");
-    let html_code = file_upload_html(&file_type);
+pub async fn upload_ui(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path((session_id, track_id, file_type)): Path<(String, String, String)>,
+    Query(link): Query<LinkQuery>,
+) -> HtmlResponse {
+    let Some(role) = TrackRole::from_name(&file_type) else {
+        return HtmlResponse::from(ErrorCode::ValidationError.details()
+            .with("reason", "File type must be vocal or instrumental"));
+    };
+    let target = UploadTarget { session_id, track_id };
+    let token = match open_track(&app_state, &target, &[role], user.as_ref(), link.token.as_deref()).await {
+        Ok(token) => token,
+        Err(err) => return HtmlResponse::from(err),
+    };
+
+    let html_code = file_upload_html(&url_param(&target.session_id), &url_param(&target.track_id), role.as_str(), &token);
 
     HtmlResponse::Ok(html_code)
 }
@@ -50,6 +66,7 @@ pub async fn upload_ui(Path((session_id, track_id, file_type)): Path<(String, St
     params(
         ("session_id" = String, Path, description = "Session id"),
         ("track_id" = String, Path, description = "Id of track"),
+        LinkQuery,
     ),
     responses(
         (status = 200, description = "Number returned successfully", body = String, content_type = "text/html"),
@@ -57,16 +74,83 @@ pub async fn upload_ui(Path((session_id, track_id, file_type)): Path<(String, St
         (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
     )
 )]
-pub async fn upload_ui_multiple(Path((session_id, track_id)): Path<(String, String)>) -> HtmlResponse {
-    let html_code = file_upload_multiple_html(&session_id, &track_id);
+pub async fn upload_ui_multiple(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path((session_id, track_id)): Path<(String, String)>,
+    Query(link): Query<LinkQuery>,
+) -> HtmlResponse {
+    let target = UploadTarget { session_id, track_id };
+    let token = match open_track(&app_state, &target, &TrackRole::ALL, user.as_ref(), link.token.as_deref()).await {
+        Ok(token) => token,
+        Err(err) => return HtmlResponse::from(err),
+    };
+    let html_code = file_upload_multiple_html(&url_param(&target.session_id), &url_param(&target.track_id), &token);
 
     tracing::info!("Hello from tracing!");
 
     HtmlResponse::Ok(html_code)
 }
 
+#[utoipa::path(
+    get,
+    tag=TAG,
+    path = "/upload-ui-multiple-filesystem/{session_id}/{track_id}",
+    params(
+        ("session_id" = String, Path, description = "Session id"),
+        ("track_id" = String, Path, description = "Id of track"),
+        LinkQuery,
+    ),
+    responses(
+        (status = 200, description = "Upload page returned successfully", body = String, content_type = "text/html"),
+        (status = 400, description = "Bad request", body = BadResponseObject, example = json!(BadResponseObject::default_400())),
+        (status = 500, description = "Internal server error", body = BadResponseObject, example = json!(BadResponseObject::default_500())),
+    )
+)]
+pub async fn upload_ui_multiple_filesystem(
+    State(app_state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path((session_id, track_id)): Path<(String, String)>,
+    Query(link): Query<LinkQuery>,
+) -> HtmlResponse {
+    let target = UploadTarget { session_id, track_id };
+    let token = match open_track(&app_state, &target, &TrackRole::ALL, user.as_ref(), link.token.as_deref()).await {
+        Ok(token) => token,
+        Err(err) => return HtmlResponse::from(err),
+    };
+    HtmlResponse::Ok(file_upload_multiple_filesystem_html(&url_param(&target.session_id), &url_param(&target.track_id), &token))
+}
+
+/// Страница загрузки открывается только с правом загрузки в эти роли
+/// и только для существующего и не финализированного трека. Возвращает токен
+/// для запросов страницы: только проверенный и уже закодированный для URL
+async fn open_track(
+    app_state: &AppState,
+    target: &UploadTarget,
+    roles: &[TrackRole],
+    user: Option<&AuthUser>,
+    token: Option<&str>,
+) -> Result<String, BadResponseObject> {
+    validate_ids(&[("session_id", &target.session_id), ("track_id", &target.track_id)])?;
+    let link = authorize(app_state, user.map(|AuthUser(claims)| claims), token, target, roles)?;
+
+    let track = app_state.sessions.get_track(&target.session_id, &target.track_id).await?;
+    if track.finalized_at.is_some() {
+        return Err(ErrorCode::ProjectLocked.details()
+            .with("reason", "Track is finalized")
+            .with("track_id", &target.track_id));
+    }
+    Ok(link.and(token).map(url_param).unwrap_or_default())
+}
+
+/// Значение для подстановки в URL внутри JS-строки страницы: после кодирования
+/// в нем остаются только буквы, цифры и `%`
+fn url_param(value: &str) -> String {
+    utf8_percent_encode(value, NON_ALPHANUMERIC).to_string()
+}
+
 
-fn file_upload_html(file_type: &str) -> String {
+fn file_upload_html(session_id: &str, track_id: &str, file_type: &str, token: &str) -> String {
     let html = format!(r#"
 <!DOCTYPE html>
 <html lang="en">
@@ -136,7 +220,6 @@ fn file_upload_html(file_type: &str) -> String {
             }}
 
             formdata.append("track", file);
-            formdata.append("path", "test");
 
             var request = new XMLHttpRequest();
 
@@ -157,13 +240,13 @@ fn file_upload_html(file_type: &str) -> String {
                 }}
             }};
 
-            request.open('post', "/api/v1/upload/upload-track-single");
+            request.open('post', "/api/v1/upload/upload-track-single?session_id={session_id}&track_id={track_id}&role={file_type}&token={token}");
             request.send(formdata);
         }}
     </script>
 </body>
 </html>
-    "#, file_type = file_type);
+    "#, session_id = session_id, track_id = track_id, file_type = file_type, token = token);
 
     html
 }
@@ -174,7 +257,7 @@ fn file_upload_html(file_type: &str) -> String {
 
 
 
-fn file_upload_multiple_html(session_id: &str, track_id: &str) -> String {
+fn file_upload_multiple_html(session_id: &str, track_id: &str, token: &str) -> String {
     let html = format!(r#"
 <!DOCTYPE html>
 <html lang="en">
@@ -293,18 +376,18 @@ fn file_upload_multiple_html(session_id: &str, track_id: &str) -> String {
                 }}
             }};
 
-            request.open('post', `/api/v1/upload/upload-tracks`);
+            request.open('post', `/api/v1/upload/upload-tracks?session_id={session_id}&track_id={track_id}&token={token}`);
             request.send(formdata);
         }}
     </script>
 </body>
 </html>
-    "#, session_id = session_id, track_id = track_id);
+    "#, session_id = session_id, track_id = track_id, token = token);
 
     html
 }
 
-fn file_upload_multiple_filesystem_html(session_id: &str, track_id: &str,) -> String {
+fn file_upload_multiple_filesystem_html(session_id: &str, track_id: &str, token: &str) -> String {
     let p0 = r#"
 <!DOCTYPE html>
 <head>
@@ -415,9 +498,9 @@ fn file_upload_multiple_filesystem_html(session_id: &str, track_id: &str,) -> St
     "#;
 
     let p2 = format!(r#"
-            request.open('post', '{}/api/v1/upload_filesystem_multiple/{}/{}');
+            request.open('post', '{}/api/v1/upload_filesystem_multiple/{}/{}?token={}');
             request.send(formdata);
-    "#, CONFIG.upload_public_domain, session_id, track_id);
+    "#, CONFIG.upload_public_domain, session_id, track_id, token);
 
     let p3 = r#"
         }
@@ -1,18 +1,22 @@
 use std::sync::Arc;
 use utoipa::openapi::{Info, OpenApi};
+pub mod auth;
+pub mod body_limit;
 pub mod custom_tracing;
 mod endpoints;
 pub mod exceptions;
 pub mod custom_exceptions;
+pub mod rate_limit;
+pub mod shutdown;
 
 use my_core::config::CONFIG;
 use utoipa_axum::router::OpenApiRouter;
-use axum::Router;
+use axum::{middleware, Router};
 
 use utoipa_swagger_ui::SwaggerUi;
 
 use endpoints::{
-    files, tests, webui
+    download, files, health, links, listing, presigned, progress, sessions, tests, tus, webui
 };
 use services::AppState;
 
@@ -20,10 +24,29 @@ pub fn get_api(app_state: Arc<AppState>) -> Router {
 
     let (mut router, mut api) = OpenApiRouter::new()
         .nest(&format!("{}upload", CONFIG.api_v1_str.as_str()), files::get_router(Arc::clone(&app_state)))
+        .nest(&format!("{}upload_filesystem_multiple", CONFIG.api_v1_str.as_str()), files::get_filesystem_router(Arc::clone(&app_state)))
+        .nest(&format!("{}sessions", CONFIG.api_v1_str.as_str()), sessions::get_router(Arc::clone(&app_state)))
+        .nest(&format!("{}progress", CONFIG.api_v1_str.as_str()), progress::get_router(Arc::clone(&app_state)))
+        .nest(&format!("{}links", CONFIG.api_v1_str.as_str()), links::get_router(Arc::clone(&app_state)))
+        .nest(&format!("{}download", CONFIG.api_v1_str.as_str()), download::get_router(Arc::clone(&app_state)))
+        .nest(&format!("{}files", CONFIG.api_v1_str.as_str()), listing::get_router(Arc::clone(&app_state)))
+        .nest(&format!("{}presigned", CONFIG.api_v1_str.as_str()), presigned::get_router(Arc::clone(&app_state)))
+        .nest(&format!("{}tus", CONFIG.api_v1_str.as_str()), tus::get_router(Arc::clone(&app_state)))
         .nest(&format!("{}test", CONFIG.api_v1_str.as_str()), tests::get_router(Arc::clone(&app_state)))
         .nest(&format!("{}upload-ui", CONFIG.api_v1_str.as_str()), webui::get_router(Arc::clone(&app_state)))
         .split_for_parts();
 
+    // Лимиты считаются после роутинга (нужен `session_id` из пути) и после проверки
+    // bearer-токена; требования к токену - в самих маршрутах
+    router = router
+        .route_layer(middleware::from_fn_with_state(Arc::clone(&app_state), rate_limit::rate_limit))
+        .layer(middleware::from_fn_with_state(Arc::clone(&app_state), auth::authenticate));
+
+    // Пробы не проходят через лимиты и проверку токена
+    let (health_router, health_api) = health::get_router(Arc::clone(&app_state)).split_for_parts();
+    router = router.merge(health_router);
+    api.merge(health_api);
+
     api.info = Info::new("Svaha-Mini Uploader", "1.0.0");
     api.info.description = Some("This is world best uploader, writed on RUST!".to_string());
 
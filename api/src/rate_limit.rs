@@ -0,0 +1,168 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{FromRequestParts, Query, RawPathParams, Request, State},
+    http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use futures_util::StreamExt;
+use serde::Deserialize;
+
+use crate::custom_exceptions::ErrorCode;
+use crate::custom_tracing::get_real_ip;
+use my_core::config::{RateLimitKey, CONFIG};
+use services::AppState;
+use services::auth::Claims;
+use services::rate_limit::{token_fingerprint, LimitKind, RateLimitDecision, RateLimiter};
+
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+const RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+/// Параметры запроса, которыми дополняется ключ лимита
+#[derive(Deserialize)]
+struct KeyQuery {
+    session_id: Option<String>,
+    token: Option<String>,
+}
+
+/// Ограничивает число запросов и байт тел на клиента. Запрос с `Content-Length`
+/// больше остатка отклоняется до чтения тела; тело без `Content-Length`
+/// учитывается по мере чтения и ограничивает следующие запросы.
+/// Работает после роутинга, чтобы видеть `session_id` из пути
+pub async fn rate_limit(
+    State(app_state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(limiter) = app_state.rate_limiter.clone() else {
+        return next.run(request).await;
+    };
+    if request.method() == Method::OPTIONS {
+        return next.run(request).await;
+    }
+
+    let ip = get_real_ip(&request)
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let (mut parts, mut body) = request.into_parts();
+    let keys = client_keys(&mut parts, ip).await;
+
+    let content_length = parts.headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    // Отказ по любому лимиту не учитывается ни в одном счетчике
+    let costs = [(LimitKind::Requests, 1), (LimitKind::Bytes, content_length.unwrap_or(0))];
+    let decisions = match limiter.acquire_all(&keys, &costs).await {
+        Ok(decisions) => decisions,
+        Err(decision) => return too_many_requests(&limiter, decision),
+    };
+
+    if content_length.is_none() && limiter.limit(LimitKind::Bytes) > 0 {
+        let mut counter = ByteCounter { limiter: Arc::clone(&limiter), keys, bytes: 0 };
+        body = Body::from_stream(body.into_data_stream().inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                counter.add(chunk.len());
+            }
+        }));
+    }
+
+    let mut response = next.run(Request::from_parts(parts, body)).await;
+    // Клиенту показываем лимит, который ближе к исчерпанию
+    let tightest = decisions.into_iter().min_by(|left, right| {
+        (left.remaining as u128 * right.limit as u128).cmp(&(right.remaining as u128 * left.limit as u128))
+    });
+    if let Some(decision) = tightest {
+        set_headers(response.headers_mut(), &limiter, &decision);
+    }
+    response
+}
+
+/// Ключи счетчиков клиента: IP учитывается всегда, а по `rate_limit_key`
+/// дополнительно сессия или токен, если они есть в запросе. Их задает сам
+/// клиент, поэтому они только добавляют счетчики и не заменяют лимит по IP
+async fn client_keys(parts: &mut Parts, ip: String) -> Vec<String> {
+    let query = Query::<KeyQuery>::try_from_uri(&parts.uri).ok().map(|Query(query)| query);
+
+    let extra = match CONFIG.rate_limit_key {
+        RateLimitKey::Ip => None,
+        RateLimitKey::Session => {
+            let from_path = RawPathParams::from_request_parts(parts, &()).await.ok().and_then(|params| {
+                params.iter().find(|(name, _)| *name == "session_id").map(|(_, value)| value.to_string())
+            });
+            from_path
+                .or_else(|| query.and_then(|query| query.session_id))
+                .map(|session_id| format!("session:{session_id}"))
+        }
+        RateLimitKey::Token => {
+            // Пользователь из bearer-токена, иначе ссылка загрузки
+            parts.extensions
+                .get::<Claims>()
+                .map(|claims| format!("sub:{}", claims.sub))
+                .or_else(|| query.and_then(|query| query.token).filter(|token| !token.is_empty()))
+                .map(|token| format!("token:{}", token_fingerprint(&token)))
+        }
+    };
+
+    let mut keys = vec![ip];
+    keys.extend(extra);
+    keys
+}
+
+fn too_many_requests(limiter: &RateLimiter, decision: RateLimitDecision) -> Response {
+    tracing::info!("Rate limit of {} exceeded, retry in {}s", decision.kind.as_str(), decision.reset_secs);
+    let mut response = ErrorCode::TooManyRequestsError.details()
+        .with("limit_kind", decision.kind.as_str())
+        .with("limit", decision.limit)
+        .with("window_secs", limiter.window().as_secs())
+        .with("retry_after", decision.reset_secs)
+        .into_response_with_status(StatusCode::TOO_MANY_REQUESTS);
+    set_headers(response.headers_mut(), limiter, &decision);
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(decision.reset_secs));
+    response
+}
+
+fn set_headers(headers: &mut HeaderMap, limiter: &RateLimiter, decision: &RateLimitDecision) {
+    headers.insert(RATELIMIT_LIMIT, HeaderValue::from(decision.limit));
+    headers.insert(RATELIMIT_REMAINING, HeaderValue::from(decision.remaining));
+    headers.insert(RATELIMIT_RESET, HeaderValue::from(decision.reset_secs));
+    let policy = format!("{};w={}", decision.limit, limiter.window().as_secs());
+    if let Ok(policy) = HeaderValue::from_str(&policy) {
+        headers.insert(RATELIMIT_POLICY, policy);
+    }
+}
+
+/// Байты тела без `Content-Length`; учитываются во всех счетчиках клиента,
+/// когда тело прочитано или брошено
+struct ByteCounter {
+    limiter: Arc<RateLimiter>,
+    keys: Vec<String>,
+    bytes: u64,
+}
+
+impl ByteCounter {
+    fn add(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+}
+
+impl Drop for ByteCounter {
+    fn drop(&mut self) {
+        if self.bytes == 0 {
+            return;
+        }
+        let limiter = Arc::clone(&self.limiter);
+        let keys = std::mem::take(&mut self.keys);
+        let bytes = self.bytes;
+        tokio::spawn(async move {
+            for key in keys {
+                limiter.charge(LimitKind::Bytes, &key, bytes).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{OriginalUri, Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::custom_exceptions::ErrorCode;
+use my_core::config::CONFIG;
+use services::AppState;
+
+
+/// Учитывает запросы загрузки (POST, PUT, PATCH) для остановки сервера.
+/// Во время остановки новые загрузки получают 503 с `Retry-After`; загрузки,
+/// не успевшие завершиться к сроку, прерываются. Остальные методы проходят как есть
+pub async fn track_upload(
+    State(app_state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !matches!(*request.method(), Method::POST | Method::PUT | Method::PATCH) {
+        return next.run(request).await;
+    }
+
+    // Вложенные роутеры отрезают префикс, в логе нужен полный путь
+    let path = request.extensions()
+        .get::<OriginalUri>()
+        .map(|OriginalUri(uri)| uri.path())
+        .unwrap_or(request.uri().path());
+    let description = format!("{} {}", request.method(), path);
+    let Some(ticket) = app_state.shutdown.begin_upload(description.as_str()) else {
+        tracing::info!("Rejected {}: server is shutting down", description);
+        return unavailable("Server is shutting down, retry the upload later");
+    };
+
+    tokio::select! {
+        response = next.run(request) => response,
+        _ = ticket.cancelled() => unavailable("Upload was cancelled because the server is shutting down"),
+    }
+}
+
+fn unavailable(reason: &str) -> Response {
+    let mut response = ErrorCode::ShuttingDown.details()
+        .with("reason", reason)
+        .with("retry_after", CONFIG.shutdown_retry_after_secs)
+        .into_response_with_status(StatusCode::SERVICE_UNAVAILABLE);
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(CONFIG.shutdown_retry_after_secs));
+    response
+}
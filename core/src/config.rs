@@ -1,12 +1,13 @@
 use std::env;
 use axum::http::HeaderValue;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr};
+use ipnet::IpNet;
 
 use clap::builder::Str;
 use clap::builder::styling::Reset;
 use tracing;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use dotenv::dotenv;
 
 use std::path::{Path, PathBuf};
@@ -15,6 +16,48 @@ use once_cell::sync::Lazy;
 
 pub static CONFIG: Lazy<Config> = Lazy::new(|| {Config::new().unwrap()});
 
+/// Бэкенд хранилища загружаемых файлов
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StorageBackend {
+    /// S3 или совместимое хранилище (MinIO)
+    S3,
+    /// Локальная файловая система в `base_upload_dir`
+    Local,
+    /// Память процесса (данные теряются при перезапуске)
+    Memory,
+}
+
+/// Бэкенд реестра сессий загрузки
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SessionBackend {
+    Redis,
+    /// Память процесса (сессии теряются при перезапуске)
+    Memory,
+}
+
+/// Стратегия повторов запросов к S3
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum S3RetryMode {
+    Standard,
+    /// Повторы с клиентским ограничением скорости при троттлинге
+    Adaptive,
+}
+
+/// Подсети доверенных прокси
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies(pub Vec<IpNet>);
+
+/// Какой лимит действует вместе с лимитом на IP клиента
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RateLimitKey {
+    /// Только общий лимит на IP
+    Ip,
+    /// Еще и лимит на каждую сессию загрузки со всех IP
+    Session,
+    /// Еще и лимит на каждый bearer-токен или ссылку загрузки со всех IP
+    Token,
+}
+
 #[derive(Parser)]
 pub struct Config {
 
@@ -33,6 +76,13 @@ pub struct Config {
     pub redis_login: String,
     #[arg(long, env)]
     pub redis_password: String,
+    #[arg(long, env, default_value = "3000")]
+    pub redis_connect_timeout_ms: u64,
+    #[arg(long, env, value_enum, default_value = "redis")]
+    pub session_backend: SessionBackend,
+    /// Время жизни сессии загрузки вместе с ее треками
+    #[arg(long, env, default_value = "86400")]
+    pub session_ttl_secs: u64,
 
 
     #[arg(long, env)]
@@ -45,18 +95,177 @@ pub struct Config {
     pub s3_bucket_name: String,
     #[arg(long, env)]
     pub s3_region_name: String,
+    #[arg(long, env, default_value = "3000")]
+    pub s3_connect_timeout_ms: u64,
+    #[arg(long, env, default_value = "30000")]
+    pub s3_read_timeout_ms: u64,
+    #[arg(long, env, default_value = "300000")]
+    pub s3_operation_timeout_ms: u64,
+    #[arg(long, env, default_value = "120000")]
+    pub s3_operation_attempt_timeout_ms: u64,
+    #[arg(long, env, default_value = "3")]
+    pub s3_max_attempts: u32,
+    #[arg(long, env, default_value = "100")]
+    pub s3_retry_initial_backoff_ms: u64,
+    #[arg(long, env, value_enum, default_value = "standard")]
+    pub s3_retry_mode: S3RetryMode,
     #[arg(long, env, default_value = "")]
     pub upload_public_domain: String,
+    /// Бакет для загружаемых треков
+    #[arg(long, env, default_value = "svaha-mini-input")]
+    pub upload_bucket_name: String,
 
     #[arg(long, env, default_value = "./")]
     pub base_upload_dir: String,
+    /// Сколько места на диске должно оставаться свободным при загрузке в `base_upload_dir`
+    #[arg(long, env, default_value = "1073741824")]
+    pub filesystem_min_free_bytes: u64,
+    #[arg(long, env, value_enum, default_value = "s3")]
+    pub storage_backend: StorageBackend,
 
     #[arg(long, env, default_value = "false")]
     pub production: bool,
 
 
+    /// Лимит тела запроса для маршрутов без своего лимита (JSON и формы)
     #[arg(long, env, default_value = "104857600")]
     pub body_size_limit: usize,
+    /// Лимит одного файла в multipart-загрузке; лимит всего запроса маршрута
+    /// считается из него по числу файлов формы. Ограничивает и tus и прямую загрузку в S3
+    #[arg(long, env, default_value = "1073741824")]
+    pub upload_max_file_size: u64,
+
+    /// Сколько частей одного файла загружается в хранилище одновременно
+    #[arg(long, env, default_value = "4")]
+    pub upload_max_parallel_parts: usize,
+    /// Лимит байтов одного файла, буферизованных в ожидании загрузки частей
+    #[arg(long, env, default_value = "104857600")]
+    pub upload_max_buffered_bytes: usize,
+    /// Считать CRC32C загружаемых файлов в дополнение к SHA-256
+    #[arg(long, env, default_value = "false")]
+    pub upload_crc32c: bool,
+    /// Сколько событий прогресса загрузки копится для отстающего подписчика SSE
+    #[arg(long, env, default_value = "64")]
+    pub upload_progress_buffer: usize,
+    /// Как часто публиковать прогресс приема файла, мс
+    #[arg(long, env, default_value = "250")]
+    pub upload_progress_interval_ms: u64,
+    /// Разрешенные расширения загружаемых аудиофайлов (через запятую)
+    #[arg(long, env, value_delimiter = ',', default_value = "ogg,mp3,wav,flac,m4a")]
+    pub upload_allowed_extensions: Vec<String>,
+    /// Шаблон ключа загружаемого файла; подстановки: `{session_id}`, `{track_id}`,
+    /// `{role}`, `{ulid}`, `{ext}`, `{filename}`, `{stem}`. Должен начинаться
+    /// с `{session_id}/{track_id}/` и содержать `{ulid}`
+    #[arg(long, env, default_value = "{session_id}/{track_id}/{role}-{ulid}.{ext}")]
+    pub upload_key_template: String,
+    /// Максимальная длина ключа в байтах (в S3 не больше 1024)
+    #[arg(long, env, default_value = "512")]
+    pub upload_key_max_length: usize,
+
+    /// Допустимая разница длительностей вокала и минуса (секунды)
+    #[arg(long, env, default_value = "1.0")]
+    pub pair_duration_tolerance_secs: f64,
+    /// Трек с пиковым уровнем ниже порога считается тишиной (dBFS)
+    #[arg(long, env, default_value = "-60.0", allow_hyphen_values = true)]
+    pub pair_silence_threshold_dbfs: f64,
+    /// Удалять оба файла пары, не прошедшей проверку
+    #[arg(long, env, default_value = "false")]
+    pub pair_delete_invalid: bool,
+    /// Префикс ключей (и каталог на диске), под которым хранится пара, не прошедшая
+    /// проверку, если ее не удалять. Точка в начале не дает совпасть с id сессии
+    #[arg(long, env, default_value = ".quarantine")]
+    pub pair_quarantine_prefix: String,
+
+    /// Период проверки незавершенных многочастных загрузок (0 - отключить)
+    #[arg(long, env, default_value = "3600")]
+    pub multipart_janitor_interval_secs: u64,
+    /// Возраст, после которого незавершенная загрузка отменяется
+    #[arg(long, env, default_value = "86400")]
+    pub multipart_max_age_secs: u64,
+
+    /// Сколько после SIGTERM ждать начатые загрузки, прежде чем отменить их;
+    /// должно быть меньше `terminationGracePeriodSeconds` (в Kubernetes 30 с)
+    #[arg(long, env, default_value = "25")]
+    pub shutdown_drain_timeout_secs: u64,
+    /// `Retry-After` для загрузок, отклоненных во время остановки
+    #[arg(long, env, default_value = "30")]
+    pub shutdown_retry_after_secs: u64,
+    /// Файл, в который дописываются (JSON Lines) многочастные загрузки,
+    /// отмененные при остановке; пусто - только в лог
+    #[arg(long, env, default_value = "")]
+    pub shutdown_aborted_uploads_path: String,
+
+    /// Сколько результат проверки готовности (`/ready`) отдается из кеша,
+    /// чтобы частые пробы не нагружали S3 и Redis
+    #[arg(long, env, default_value = "2000")]
+    pub readiness_cache_ms: u64,
+    /// Таймаут проверки одной зависимости
+    #[arg(long, env, default_value = "2000")]
+    pub readiness_check_timeout_ms: u64,
+
+    /// Максимальный размер файла, загружаемого по протоколу tus (байты);
+    /// больше `upload_max_file_size` он все равно не будет
+    #[arg(long, env, default_value = "2147483648")]
+    pub tus_max_size: u64,
+
+    /// Время жизни подписанных ссылок на прямую загрузку в S3
+    #[arg(long, env, default_value = "3600")]
+    pub presign_expires_secs: u64,
+    /// Файлы до этого размера загружаются одним подписанным PUT, больше - по частям
+    #[arg(long, env, default_value = "104857600")]
+    pub presign_single_max_size: u64,
+    /// Размер части при прямой многочастной загрузке
+    #[arg(long, env, default_value = "20971520")]
+    pub presign_part_size: u64,
+
+    /// Секрет HMAC для подписи ссылок на загрузку; пустой - ссылки не выдаются,
+    /// а страницы и эндпоинты загрузки открыты без подписи. Требует ключей JWT
+    #[arg(long, env, default_value = "")]
+    pub upload_link_secret: String,
+    /// Время жизни ссылки на загрузку по умолчанию
+    #[arg(long, env, default_value = "3600")]
+    pub upload_link_ttl_secs: u64,
+    /// Максимальное время жизни, которое можно запросить при выдаче ссылки
+    #[arg(long, env, default_value = "604800")]
+    pub upload_link_max_ttl_secs: u64,
+
+    /// Секрет HS256 для проверки bearer-токенов; пустой - HS256 не принимается
+    #[arg(long, env, default_value = "")]
+    pub jwt_hs256_secret: String,
+    /// Файл с публичным ключом RS256 в PEM
+    #[arg(long, env, default_value = "")]
+    pub jwt_rs256_public_key_path: String,
+    /// Локальный JWKS с ключами RS256, выбираются по `kid` токена
+    #[arg(long, env, default_value = "")]
+    pub jwt_jwks_path: String,
+    /// Ожидаемый `iss`; пустой - не проверяется
+    #[arg(long, env, default_value = "")]
+    pub jwt_issuer: String,
+    /// Ожидаемый `aud`; пустой - не проверяется
+    #[arg(long, env, default_value = "")]
+    pub jwt_audience: String,
+    /// Допуск расхождения часов при проверке `exp` и `nbf`
+    #[arg(long, env, default_value = "30")]
+    pub jwt_leeway_secs: u64,
+    /// Scope, без которого токен не дает права на загрузку
+    #[arg(long, env, default_value = "upload")]
+    pub jwt_upload_scope: String,
+
+    /// Сколько запросов разрешено одному клиенту за окно; 0 - без ограничения
+    #[arg(long, env, default_value = "0")]
+    pub rate_limit_requests: u64,
+    /// Сколько байт тел запросов разрешено одному клиенту за окно; 0 - без ограничения
+    #[arg(long, env, default_value = "0")]
+    pub rate_limit_bytes: u64,
+    #[arg(long, env, default_value = "60")]
+    pub rate_limit_window_secs: u64,
+    #[arg(long, env, value_enum, default_value = "ip")]
+    pub rate_limit_key: RateLimitKey,
+
+    /// Подсети прокси через запятую (`10.0.0.0/8,127.0.0.1`), от которых принимаются
+    /// `Forwarded`, `X-Forwarded-For` и `X-Real-IP`; пусто - заголовки игнорируются
+    #[arg(long, env, default_value = "", value_parser = parse_trusted_proxies)]
+    pub trusted_proxies: TrustedProxies,
 }
 
 
@@ -112,6 +321,28 @@ impl Config {
     }
 }
 
+impl Config {
+    /// Адрес принадлежит доверенному прокси
+    pub fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.0.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// Подсети в нотации CIDR или отдельные адреса через запятую; пустая строка - пустой список
+fn parse_trusted_proxies(value: &str) -> Result<TrustedProxies, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            item.parse::<IpNet>()
+                .or_else(|_| item.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("Invalid CIDR or IP address: {item}"))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(TrustedProxies)
+}
+
 fn bin_path() -> Result<PathBuf, String> {
     env::current_exe().map_err(|e| format!("Failed to get current executable path: {}", e))
 }
\ No newline at end of file
@@ -971,6 +971,7 @@ pub fn init_logger(service_name: &str) {
                 "{}=debug,\
                 tower_http=debug,\
                 api=trace,\
+                services=info,\
                 response_trace=info,\
                 http_response=info,\
                 core=info",
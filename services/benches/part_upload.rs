@@ -0,0 +1,90 @@
+//! Замер пропускной способности `upload_part` на реальном S3/MinIO из `.env`.
+//!
+//! Сравнивает два режима:
+//! - `per-part` — новый клиент на каждую часть (как было с `client_factory`);
+//! - `shared` — один общий клиент с пулом соединений.
+//!
+//! Оба режима грузят части в одну и ту же многочастную загрузку,
+//! различается только клиент, а с ним и соединение.
+//!
+//! Запуск: `cargo bench -p services --bench part_upload`
+//! Параметры: `BENCH_BUCKET`, `BENCH_PARTS` (8), `BENCH_PART_SIZE_MB` (5).
+
+use std::env;
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use my_core::config::CONFIG;
+use services::s3::{S3ClientOptions, S3Manager};
+
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+async fn create_manager() -> S3Manager {
+    let credentials = aws_sdk_s3::config::Credentials::new(
+        CONFIG.s3_svaha_writer_login.clone(),
+        CONFIG.s3_svaha_writer_password.clone(),
+        None,
+        None,
+        "env",
+    );
+    let endpoint = CONFIG.s3_endpoint.to_str().expect("Invalid S3 endpoint").to_string();
+
+    S3Manager::new(
+        CONFIG.s3_region_name.clone(),
+        Some(endpoint),
+        credentials,
+        S3ClientOptions::from_config(&CONFIG),
+    )
+        .await
+        .expect("Failed to create S3Manager")
+}
+
+/// Загружает `parts` частей и возвращает затраченное время
+async fn run(mode: &str, bucket: &str, parts: i32, body: &Bytes) -> Duration {
+    let key = format!("bench/part_upload_{mode}_{}", ulid::Ulid::new());
+    let shared = create_manager().await;
+    let context = shared
+        .create_multipart_upload_context(bucket, &key, None)
+        .await
+        .expect("Failed to create multipart upload");
+
+    let started = Instant::now();
+    for part_number in 1..=parts {
+        let result = if mode == "per-part" {
+            // Прежнее поведение: новый клиент и новый пул соединений на каждую часть
+            let manager = create_manager().await;
+            manager
+                .attach_multipart_upload_context(bucket, &key, context.upload_id())
+                .upload_part(part_number, body.clone())
+                .await
+        } else {
+            context.upload_part(part_number, body.clone()).await
+        };
+        result.expect("Failed to upload part");
+    }
+    let elapsed = started.elapsed();
+
+    let _ = context.abort().await;
+    elapsed
+}
+
+#[tokio::main]
+async fn main() {
+    let bucket: String = env_or("BENCH_BUCKET", CONFIG.s3_bucket_name.clone());
+    let parts: i32 = env_or("BENCH_PARTS", 8);
+    let part_size_mb: usize = env_or("BENCH_PART_SIZE_MB", 5);
+    let body = Bytes::from(vec![0u8; part_size_mb * 1024 * 1024]);
+    let total_mb = (parts as usize * part_size_mb) as f64;
+
+    println!("bucket={bucket} parts={parts} part_size={part_size_mb}MB");
+    for mode in ["per-part", "shared"] {
+        let elapsed = run(mode, &bucket, parts, &body).await;
+        println!(
+            "{mode:>8}: {:>8.2?} total, {:>7.2} MB/s",
+            elapsed,
+            total_mb / elapsed.as_secs_f64(),
+        );
+    }
+}
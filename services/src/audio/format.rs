@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+
+/// Сколько первых байт файла нужно, чтобы определить формат
+pub const SNIFF_LEN: usize = 12;
+/// Больше этого за тегом ID3 не читаем: за слишком большим тегом формат не проверяется
+pub const MAX_SNIFF_LEN: usize = 16 * 1024 * 1024;
+
+/// Аудиоформаты, которые принимает загрузчик
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    Ogg,
+    Mp3,
+    Wav,
+    Flac,
+    /// Контейнер MP4 (M4A/M4B)
+    M4a,
+}
+
+impl AudioFormat {
+    /// Формат по расширению файла (с точкой или без, без учета регистра)
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.trim_start_matches('.').to_ascii_lowercase().as_str() {
+            "ogg" | "oga" | "opus" => Some(Self::Ogg),
+            "mp3" => Some(Self::Mp3),
+            "wav" | "wave" => Some(Self::Wav),
+            "flac" => Some(Self::Flac),
+            "m4a" | "m4b" | "mp4" => Some(Self::M4a),
+            _ => None,
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Ogg => "audio/ogg",
+            Self::Mp3 => "audio/mpeg",
+            Self::Wav => "audio/wav",
+            Self::Flac => "audio/flac",
+            Self::M4a => "audio/mp4",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ogg => "ogg",
+            Self::Mp3 => "mp3",
+            Self::Wav => "wav",
+            Self::Flac => "flac",
+            Self::M4a => "m4a",
+        }
+    }
+
+    /// Сколько байт начала файла нужно `sniff`: обычно `SNIFF_LEN`, а за тегом
+    /// ID3 - весь тег и сигнатура после него, но не больше `MAX_SNIFF_LEN`
+    pub fn sniff_len(head: &[u8]) -> usize {
+        match id3_len(head) {
+            Some(tag_len) => (tag_len + SNIFF_LEN).min(MAX_SNIFF_LEN),
+            None => SNIFF_LEN,
+        }
+    }
+
+    /// Определяет формат по сигнатуре в начале файла. `head` должен быть
+    /// не короче `sniff_len(head)` байт (или содержать весь файл)
+    pub fn sniff(head: &[u8]) -> Option<Self> {
+        match head {
+            [b'O', b'g', b'g', b'S', ..] => Some(Self::Ogg),
+            [b'f', b'L', b'a', b'C', ..] => Some(Self::Flac),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..]
+            | [b'R', b'F', b'6', b'4', _, _, _, _, b'W', b'A', b'V', b'E', ..]
+            | [b'B', b'W', b'6', b'4', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some(Self::Wav),
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some(Self::M4a),
+            [b'I', b'D', b'3', ..] => Some(Self::after_id3(head)),
+            _ if is_mpeg_frame(head) => Some(Self::Mp3),
+            _ => None,
+        }
+    }
+
+    /// ID3v2 обычно у MP3, но встречается и перед FLAC и WAV
+    fn after_id3(head: &[u8]) -> Self {
+        let Some(tag_len) = id3_len(head) else { return Self::Mp3 };
+        match head.get(tag_len..).and_then(Self::sniff) {
+            Some(format @ (Self::Flac | Self::Wav)) => format,
+            _ => Self::Mp3,
+        }
+    }
+}
+
+/// Длина тега ID3v2 вместе с заголовком и футером, если `head` с него начинается
+fn id3_len(head: &[u8]) -> Option<usize> {
+    let [b'I', b'D', b'3', _, _, flags, size @ ..] = head.get(..10)? else { return None };
+    // Размер тега записан в syncsafe-формате: по 7 бит в байте
+    let size = size.iter().fold(0usize, |size, byte| (size << 7) | (*byte & 0x7f) as usize);
+    let footer = if flags & 0x10 != 0 { 10 } else { 0 };
+    Some(10 + size + footer)
+}
+
+/// Заголовок кадра MPEG audio: 11 бит синхронизации, допустимые версия и слой.
+/// Слой `00` отсекает AAC ADTS с той же синхронизацией
+fn is_mpeg_frame(head: &[u8]) -> bool {
+    let [0xff, second, ..] = head else { return false };
+    let version = (second >> 3) & 0b11;
+    let layer = (second >> 1) & 0b11;
+    second & 0xe0 == 0xe0 && version != 0b01 && layer != 0b00
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Тег ID3v2 с телом из `size` нулевых байт
+    fn id3(size: usize) -> Vec<u8> {
+        let syncsafe = [(size >> 21) & 0x7f, (size >> 14) & 0x7f, (size >> 7) & 0x7f, size & 0x7f];
+        let mut tag = b"ID3\x04\x00\x00".to_vec();
+        tag.extend(syncsafe.iter().map(|byte| *byte as u8));
+        tag.resize(10 + size, 0);
+        tag
+    }
+
+    #[test]
+    fn signatures_are_detected() {
+        assert_eq!(AudioFormat::sniff(b"OggS\0\x02\0\0\0\0\0\0"), Some(AudioFormat::Ogg));
+        assert_eq!(AudioFormat::sniff(b"fLaC\0\0\0\x22\0\0\0\0"), Some(AudioFormat::Flac));
+        assert_eq!(AudioFormat::sniff(b"RIFF\x24\0\0\0WAVE"), Some(AudioFormat::Wav));
+        assert_eq!(AudioFormat::sniff(b"RF64\xff\xff\xff\xffWAVE"), Some(AudioFormat::Wav));
+        assert_eq!(AudioFormat::sniff(b"\0\0\0\x20ftypM4A "), Some(AudioFormat::M4a));
+        // MPEG-1 Layer III
+        assert_eq!(AudioFormat::sniff(&[0xff, 0xfb, 0x90, 0x64]), Some(AudioFormat::Mp3));
+    }
+
+    #[test]
+    fn foreign_content_is_not_detected() {
+        for head in [&b"<html><body>"[..], b"RIFF\x24\0\0\0AVI ", b"\x89PNG\r\n\x1a\n\0\0\0\0", b"", b"Og"] {
+            assert_eq!(AudioFormat::sniff(head), None, "{head:?}");
+        }
+        // AAC ADTS: та же синхронизация, но слой 00
+        assert_eq!(AudioFormat::sniff(&[0xff, 0xf1, 0x50, 0x80]), None);
+    }
+
+    #[test]
+    fn id3_tag_is_skipped() {
+        let mut flac = id3(100);
+        assert_eq!(AudioFormat::sniff_len(&flac), 110 + SNIFF_LEN);
+        flac.extend_from_slice(b"fLaC\0\0\0\x22\0\0\0\0");
+        assert_eq!(AudioFormat::sniff(&flac), Some(AudioFormat::Flac));
+
+        let mut mp3 = id3(100);
+        mp3.extend_from_slice(&[0xff, 0xfb, 0x90, 0x64]);
+        assert_eq!(AudioFormat::sniff(&mp3), Some(AudioFormat::Mp3));
+
+        // Огромный тег читается только до MAX_SNIFF_LEN
+        assert_eq!(AudioFormat::sniff_len(&id3(0)), 10 + SNIFF_LEN);
+        let mut huge = b"ID3\x04\x00\x00\x7f\x7f\x7f\x7f".to_vec();
+        assert_eq!(AudioFormat::sniff_len(&huge), MAX_SNIFF_LEN);
+        huge.truncate(4);
+        assert_eq!(AudioFormat::sniff_len(&huge), SNIFF_LEN);
+    }
+
+    #[test]
+    fn extensions_map_to_formats() {
+        assert_eq!(AudioFormat::from_extension(".WAV"), Some(AudioFormat::Wav));
+        assert_eq!(AudioFormat::from_extension("opus"), Some(AudioFormat::Ogg));
+        assert_eq!(AudioFormat::from_extension(".m4b"), Some(AudioFormat::M4a));
+        assert_eq!(AudioFormat::from_extension(".html"), None);
+        assert_eq!(AudioFormat::Mp3.mime_type(), "audio/mpeg");
+    }
+}
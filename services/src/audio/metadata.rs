@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom};
+use bytes::{Buf, Bytes};
+use serde::{Deserialize, Serialize};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CodecParameters, DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::{MediaSource, MediaSourceStream, MediaSourceStreamOptions};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use super::AudioFormat;
+
+
+/// Сколько чанков может ждать разбора, прежде чем загрузка притормозит
+const PROBE_QUEUE_CHUNKS: usize = 32;
+/// Пиковый уровень полностью беззвучного файла
+pub const SILENCE_FLOOR_DBFS: f64 = -120.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AudioError {
+    #[error("Unreadable audio: {0}")]
+    Unreadable(String),
+    #[error("No audio track found")]
+    NoTrack,
+    #[error("Audio probe task failed: {0}")]
+    Task(String),
+}
+
+/// Параметры аудиопотока, известные после разбора контейнера
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AudioMetadata {
+    /// Короткое имя кодека (`mp3`, `flac`, `pcm_s16le`, ...)
+    pub codec: String,
+    pub duration_secs: Option<f64>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u32>,
+    /// Разрядность для PCM и lossless-кодеков
+    pub bits_per_sample: Option<u32>,
+    /// Средний битрейт файла, бит/с
+    pub bitrate: Option<u64>,
+    /// Пиковый уровень сигнала в dBFS, не ниже `SILENCE_FLOOR_DBFS`;
+    /// `None`, если кодек не поддерживается декодером
+    pub peak_dbfs: Option<f64>,
+}
+
+impl AudioMetadata {
+    /// Пользовательские метаданные объекта (`x-amz-meta-*` в S3)
+    pub fn to_object_metadata(&self) -> HashMap<String, String> {
+        let fields = [
+            ("audio-codec", Some(self.codec.clone())),
+            ("audio-duration", self.duration_secs.map(|duration| format!("{duration:.3}"))),
+            ("audio-sample-rate", self.sample_rate.map(|rate| rate.to_string())),
+            ("audio-channels", self.channels.map(|channels| channels.to_string())),
+            ("audio-bits-per-sample", self.bits_per_sample.map(|bits| bits.to_string())),
+            ("audio-bitrate", self.bitrate.map(|bitrate| bitrate.to_string())),
+            ("audio-peak-dbfs", self.peak_dbfs.map(|peak| format!("{peak:.1}"))),
+        ];
+
+        fields
+            .into_iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value?)))
+            .collect()
+    }
+}
+
+/// Разбирает аудиофайл по мере загрузки, не дожидаясь его целиком.
+/// Декодирование идет в блокирующем потоке, чтобы найти пиковый уровень
+pub struct AudioProbe {
+    sender: Option<mpsc::Sender<Bytes>>,
+    total_size: u64,
+    task: JoinHandle<ProbeResult>,
+    /// Результат разбора, закончившегося раньше конца файла
+    result: Option<ProbeResult>,
+}
+
+type ProbeResult = Result<AudioMetadata, AudioError>;
+
+impl AudioProbe {
+    pub fn new(format: AudioFormat) -> Self {
+        let (sender, receiver) = mpsc::channel(PROBE_QUEUE_CHUNKS);
+        let source = ChannelSource { receiver, current: Bytes::new() };
+        let task = tokio::task::spawn_blocking(move || probe(source, format));
+
+        Self { sender: Some(sender), total_size: 0, task, result: None }
+    }
+
+    /// Передает очередной чанк файла. Если разбор уже закончен, чанк пропускается;
+    /// ошибка разбора возвращается сразу, чтобы не дочитывать негодный файл
+    pub async fn feed(&mut self, chunk: Bytes) -> Result<(), AudioError> {
+        self.total_size += chunk.len() as u64;
+        let Some(sender) = self.sender.as_ref() else { return Ok(()) };
+        if sender.send(chunk).await.is_ok() {
+            return Ok(());
+        }
+
+        self.sender = None;
+        let result = (&mut self.task).await.map_err(|err| AudioError::Task(err.to_string()))?;
+        match result {
+            Ok(result) => {
+                self.result = Some(Ok(result));
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Закрывает поток и возвращает параметры файла
+    pub async fn finish(mut self) -> Result<AudioMetadata, AudioError> {
+        self.sender = None;
+        let result = match self.result.take() {
+            Some(result) => result,
+            None => self.task.await.map_err(|err| AudioError::Task(err.to_string()))?,
+        };
+        let mut metadata = result?;
+        metadata.bitrate = bitrate(self.total_size, metadata.duration_secs);
+        Ok(metadata)
+    }
+
+    /// Разбирает только заголовок контейнера по началу уже сохраненного
+    /// файла размером `total_size`. Длительность берется из заголовка,
+    /// пиковый уровень не считается
+    pub async fn probe_head(format: AudioFormat, head: Bytes, total_size: u64) -> Result<AudioMetadata, AudioError> {
+        // Канал без отправителя: после `head` источник сообщает о конце файла
+        let (_, receiver) = mpsc::channel(1);
+        let source = ChannelSource { receiver, current: head };
+        let (_, _, params) = tokio::task::spawn_blocking(move || open_track(source, format))
+            .await
+            .map_err(|err| AudioError::Task(err.to_string()))??;
+
+        let mut metadata = describe(&params);
+        metadata.bitrate = bitrate(total_size, metadata.duration_secs);
+        Ok(metadata)
+    }
+}
+
+/// Средний битрейт по размеру файла и длительности
+fn bitrate(total_size: u64, duration_secs: Option<f64>) -> Option<u64> {
+    duration_secs
+        .filter(|duration| *duration > 0.0)
+        .map(|duration| (total_size as f64 * 8.0 / duration).round() as u64)
+}
+
+/// Разбирает контейнер и находит аудиодорожку
+fn open_track(source: ChannelSource, format: AudioFormat) -> Result<(Box<dyn FormatReader>, u32, CodecParameters), AudioError> {
+    let stream = MediaSourceStream::new(Box::new(source), MediaSourceStreamOptions::default());
+    let mut hint = Hint::new();
+    hint.with_extension(format.name());
+
+    let probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|err| AudioError::Unreadable(err.to_string()))?;
+    let reader = probed.format;
+
+    let track = reader.default_track()
+        .filter(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or(AudioError::NoTrack)?;
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+    Ok((reader, track_id, params))
+}
+
+/// Разбирает контейнер и декодирует дорожку до конца потока; длительность
+/// берется из заголовка, а если ее там нет - досчитывается по пакетам
+fn probe(source: ChannelSource, format: AudioFormat) -> ProbeResult {
+    let (mut reader, track_id, params) = open_track(source, format)?;
+    let mut metadata = describe(&params);
+
+    // Кодеки без декодера (например, Opus) проверяются только по контейнеру
+    let mut decoder = symphonia::default::get_codecs().make(&params, &DecoderOptions::default()).ok();
+    let mut samples: Option<SampleBuffer<f32>> = None;
+    let mut peak = 0f32;
+    let mut timestamps = 0u64;
+
+    loop {
+        let packet = match reader.next_packet() {
+            Ok(packet) if packet.track_id() == track_id => packet,
+            Ok(_) => continue,
+            Err(SymphoniaError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(err) => return Err(AudioError::Unreadable(err.to_string())),
+        };
+        timestamps += packet.dur;
+
+        let Some(decoder) = decoder.as_mut() else { continue };
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Поврежденный пакет пропускаем, как это делают плееры
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(err) => return Err(AudioError::Unreadable(err.to_string())),
+        };
+
+        if samples.as_ref().is_none_or(|buffer| buffer.capacity() < decoded.capacity()) {
+            samples = Some(SampleBuffer::new(decoded.capacity() as u64, *decoded.spec()));
+        }
+        if let Some(buffer) = samples.as_mut() {
+            buffer.copy_interleaved_ref(decoded);
+            peak = buffer.samples().iter().fold(peak, |peak, sample| peak.max(sample.abs()));
+        }
+    }
+
+    // Длительность из заголовка есть не у всех форматов (MP3 без Xing, OGG)
+    if metadata.duration_secs.is_none() {
+        metadata.duration_secs = duration_secs(&params, timestamps);
+    }
+    if decoder.is_some() {
+        metadata.peak_dbfs = Some((20.0 * (peak as f64).log10()).max(SILENCE_FLOOR_DBFS));
+    }
+    Ok(metadata)
+}
+
+/// Переводит длительность в единицах `time_base` дорожки в секунды
+fn duration_secs(params: &CodecParameters, timestamps: u64) -> Option<f64> {
+    match (params.time_base, params.sample_rate) {
+        (Some(time_base), _) => {
+            let time = time_base.calc_time(timestamps);
+            Some(time.seconds as f64 + time.frac)
+        }
+        (None, Some(sample_rate)) => Some(timestamps as f64 / sample_rate as f64),
+        _ => None,
+    }
+}
+
+fn describe(params: &CodecParameters) -> AudioMetadata {
+    let codec = symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .map(|descriptor| descriptor.short_name)
+        .or((params.codec == CODEC_TYPE_OPUS).then_some("opus"))
+        .unwrap_or("unknown");
+
+    AudioMetadata {
+        codec: codec.to_string(),
+        duration_secs: params.n_frames.and_then(|frames| duration_secs(params, frames)),
+        sample_rate: params.sample_rate,
+        channels: params.channels.map(|channels| channels.count() as u32),
+        bits_per_sample: params.bits_per_sample,
+        bitrate: None,
+        peak_dbfs: None,
+    }
+}
+
+/// Источник для symphonia поверх канала чанков: только последовательное чтение
+struct ChannelSource {
+    receiver: mpsc::Receiver<Bytes>,
+    current: Bytes,
+}
+
+impl Read for ChannelSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.current.is_empty() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => self.current = chunk,
+                None => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.current.len());
+        self.current.copy_to_slice(&mut buf[..len]);
+        Ok(len)
+    }
+}
+
+impl Seek for ChannelSource {
+    fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Upload stream is not seekable"))
+    }
+}
+
+impl MediaSource for ChannelSource {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    /// PCM WAV 16 бит, моно: секунда синусоиды с амплитудой в половину шкалы
+    fn wav() -> Vec<u8> {
+        let samples: Vec<i16> = (0..SAMPLE_RATE)
+            .map(|index| ((index as f64 * 0.05).sin() * i16::MAX as f64 / 2.0) as i16)
+            .collect();
+        let data_len = samples.len() as u32 * 2;
+
+        let mut file = Vec::new();
+        file.extend_from_slice(b"RIFF");
+        file.extend_from_slice(&(36 + data_len).to_le_bytes());
+        file.extend_from_slice(b"WAVEfmt ");
+        file.extend_from_slice(&16u32.to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        file.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+        file.extend_from_slice(&2u16.to_le_bytes());
+        file.extend_from_slice(&16u16.to_le_bytes());
+        file.extend_from_slice(b"data");
+        file.extend_from_slice(&data_len.to_le_bytes());
+        file.extend(samples.iter().flat_map(|sample| sample.to_le_bytes()));
+        file
+    }
+
+    #[tokio::test]
+    async fn streamed_file_is_described_and_decoded() {
+        let file = wav();
+        let mut probe = AudioProbe::new(AudioFormat::Wav);
+        for chunk in file.chunks(1000) {
+            probe.feed(Bytes::copy_from_slice(chunk)).await.unwrap();
+        }
+        let metadata = probe.finish().await.unwrap();
+
+        assert_eq!(metadata.codec, "pcm_s16le");
+        assert_eq!(metadata.sample_rate, Some(SAMPLE_RATE));
+        assert_eq!(metadata.channels, Some(1));
+        assert_eq!(metadata.bits_per_sample, Some(16));
+        assert!((metadata.duration_secs.unwrap() - 1.0).abs() < 1e-3);
+        assert_eq!(metadata.bitrate, Some(file.len() as u64 * 8));
+        // Половина шкалы - около -6 dBFS
+        assert!((metadata.peak_dbfs.unwrap() + 6.0).abs() < 0.1, "{:?}", metadata.peak_dbfs);
+    }
+
+    #[tokio::test]
+    async fn head_probe_reads_only_the_header() {
+        let file = wav();
+        let head = Bytes::copy_from_slice(&file[..512]);
+        let metadata = AudioProbe::probe_head(AudioFormat::Wav, head, file.len() as u64).await.unwrap();
+
+        assert_eq!(metadata.codec, "pcm_s16le");
+        assert!((metadata.duration_secs.unwrap() - 1.0).abs() < 1e-3);
+        assert_eq!(metadata.bitrate, Some(file.len() as u64 * 8));
+        assert_eq!(metadata.peak_dbfs, None);
+    }
+
+    #[tokio::test]
+    async fn garbage_is_unreadable() {
+        let mut probe = AudioProbe::new(AudioFormat::Flac);
+        probe.feed(Bytes::from_static(b"fLaC but not really a flac stream")).await.ok();
+        assert!(matches!(probe.finish().await, Err(AudioError::Unreadable(_))));
+
+        let head = Bytes::from_static(b"<html></html>");
+        assert!(AudioProbe::probe_head(AudioFormat::Wav, head, 13).await.is_err());
+    }
+
+    #[test]
+    fn object_metadata_skips_unknown_fields() {
+        let metadata = AudioMetadata {
+            codec: "flac".to_string(),
+            duration_secs: Some(1.23456),
+            sample_rate: Some(48000),
+            peak_dbfs: Some(-0.04),
+            ..Default::default()
+        };
+
+        let object = metadata.to_object_metadata();
+        assert_eq!(object.len(), 4);
+        assert_eq!(object["audio-codec"], "flac");
+        assert_eq!(object["audio-duration"], "1.235");
+        assert_eq!(object["audio-sample-rate"], "48000");
+        assert_eq!(object["audio-peak-dbfs"], "-0.0");
+    }
+}
@@ -0,0 +1,7 @@
+mod format;
+mod metadata;
+mod pair;
+
+pub use format::{AudioFormat, SNIFF_LEN};
+pub use metadata::{AudioError, AudioMetadata, AudioProbe, SILENCE_FLOOR_DBFS};
+pub use pair::{validate_pair, PairIssue, PairRules};
@@ -0,0 +1,120 @@
+use serde::Serialize;
+use super::AudioMetadata;
+
+
+/// Допуски проверки пары вокал/минус
+#[derive(Debug, Clone, Copy)]
+pub struct PairRules {
+    /// Допустимая разница длительностей, секунды
+    pub duration_tolerance_secs: f64,
+    /// Трек с пиком ниже порога считается тишиной, dBFS
+    pub silence_threshold_dbfs: f64,
+}
+
+/// Несоответствие в паре вокал/минус
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "issue", rename_all = "snake_case")]
+pub enum PairIssue {
+    DurationMismatch {
+        vocal_secs: f64,
+        instrumental_secs: f64,
+        tolerance_secs: f64,
+    },
+    SampleRateMismatch {
+        vocal: u32,
+        instrumental: u32,
+    },
+    Silent {
+        track: &'static str,
+        peak_dbfs: f64,
+        threshold_dbfs: f64,
+    },
+}
+
+/// Проверяет, что вокал и минус относятся к одной песне: длительности совпадают
+/// в пределах допуска, частоты дискретизации равны и ни один трек не тишина.
+/// Неизвестные параметры не проверяются
+pub fn validate_pair(vocal: &AudioMetadata, instrumental: &AudioMetadata, rules: &PairRules) -> Vec<PairIssue> {
+    let mut issues = Vec::new();
+
+    if let (Some(vocal_secs), Some(instrumental_secs)) = (vocal.duration_secs, instrumental.duration_secs) {
+        if (vocal_secs - instrumental_secs).abs() > rules.duration_tolerance_secs {
+            issues.push(PairIssue::DurationMismatch {
+                vocal_secs,
+                instrumental_secs,
+                tolerance_secs: rules.duration_tolerance_secs,
+            });
+        }
+    }
+
+    if let (Some(vocal), Some(instrumental)) = (vocal.sample_rate, instrumental.sample_rate) {
+        if vocal != instrumental {
+            issues.push(PairIssue::SampleRateMismatch { vocal, instrumental });
+        }
+    }
+
+    for (track, metadata) in [("vocal", vocal), ("instrumental", instrumental)] {
+        let Some(peak_dbfs) = metadata.peak_dbfs else { continue };
+        if peak_dbfs < rules.silence_threshold_dbfs {
+            issues.push(PairIssue::Silent {
+                track,
+                peak_dbfs,
+                threshold_dbfs: rules.silence_threshold_dbfs,
+            });
+        }
+    }
+
+    issues
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: PairRules = PairRules { duration_tolerance_secs: 1.0, silence_threshold_dbfs: -60.0 };
+
+    fn track(duration_secs: f64, sample_rate: u32, peak_dbfs: f64) -> AudioMetadata {
+        AudioMetadata {
+            codec: "pcm_s16le".to_string(),
+            duration_secs: Some(duration_secs),
+            sample_rate: Some(sample_rate),
+            peak_dbfs: Some(peak_dbfs),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matching_pair_has_no_issues() {
+        assert!(validate_pair(&track(180.0, 44100, -3.0), &track(180.9, 44100, -1.0), &RULES).is_empty());
+    }
+
+    #[test]
+    fn every_mismatch_is_reported() {
+        let issues = validate_pair(&track(180.0, 44100, -80.0), &track(200.0, 48000, -1.0), &RULES);
+        assert_eq!(issues, [
+            PairIssue::DurationMismatch { vocal_secs: 180.0, instrumental_secs: 200.0, tolerance_secs: 1.0 },
+            PairIssue::SampleRateMismatch { vocal: 44100, instrumental: 48000 },
+            PairIssue::Silent { track: "vocal", peak_dbfs: -80.0, threshold_dbfs: -60.0 },
+        ]);
+    }
+
+    #[test]
+    fn unknown_parameters_are_not_checked() {
+        // Например, Opus: декодера нет, пик и длительность могут быть неизвестны
+        let unknown = AudioMetadata { codec: "opus".to_string(), ..Default::default() };
+        assert!(validate_pair(&unknown, &track(10.0, 48000, -90.0), &RULES)
+            .iter()
+            .all(|issue| matches!(issue, PairIssue::Silent { track: "instrumental", .. })));
+        assert!(validate_pair(&unknown, &unknown, &RULES).is_empty());
+    }
+
+    #[test]
+    fn issues_serialize_with_a_tag() {
+        let issue = PairIssue::SampleRateMismatch { vocal: 44100, instrumental: 48000 };
+        assert_eq!(
+            serde_json::to_value(issue).unwrap(),
+            serde_json::json!({"issue": "sample_rate_mismatch", "vocal": 44100, "instrumental": 48000}),
+        );
+    }
+}
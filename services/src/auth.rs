@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use jsonwebtoken::errors::ErrorKind;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use my_core::config::Config;
+
+
+/// Результат проверки токенов
+pub type Result<T> = std::result::Result<T, AuthError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Token has expired")]
+    Expired,
+
+    #[error("Invalid token: {0}")]
+    InvalidToken(String),
+
+    #[error("No key to verify the token: {0}")]
+    KeyNotFound(String),
+
+    #[error("User is inactive")]
+    Inactive,
+
+    #[error("Invalid JWT configuration: {0}")]
+    Config(String),
+}
+
+/// Утверждения проверенного JWT
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Claims {
+    /// Идентификатор пользователя
+    pub sub: String,
+    pub exp: i64,
+    /// Scope через пробел (RFC 8693)
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// Scope списком, как его выдают некоторые провайдеры
+    #[serde(default)]
+    pub scp: Option<Vec<String>>,
+    /// `false` - пользователь заблокирован
+    #[serde(default)]
+    pub active: Option<bool>,
+    /// Остальные утверждения
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl Claims {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scope.as_deref().is_some_and(|scopes| scopes.split_whitespace().any(|item| item == scope))
+            || self.scp.as_ref().is_some_and(|scopes| scopes.iter().any(|item| item == scope))
+    }
+}
+
+/// Проверка bearer-токенов HS256 (общий секрет) и RS256 (PEM-ключ или
+/// локальный JWKS с выбором ключа по `kid`)
+pub struct JwtVerifier {
+    hs256: Option<DecodingKey>,
+    rs256: Option<DecodingKey>,
+    jwks: HashMap<String, DecodingKey>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    leeway_secs: u64,
+}
+
+impl JwtVerifier {
+    /// `None`, если в конфигурации не задано ни одного ключа
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let hs256 = Some(&config.jwt_hs256_secret)
+            .filter(|secret| !secret.is_empty())
+            .map(|secret| DecodingKey::from_secret(secret.as_bytes()));
+
+        let rs256 = match config.jwt_rs256_public_key_path.as_str() {
+            "" => None,
+            path => {
+                let pem = read_file(path)?;
+                Some(DecodingKey::from_rsa_pem(&pem).map_err(|err| AuthError::Config(format!("{path}: {err}")))?)
+            }
+        };
+
+        let jwks = match config.jwt_jwks_path.as_str() {
+            "" => HashMap::new(),
+            path => load_jwks(path)?,
+        };
+
+        if hs256.is_none() && rs256.is_none() && jwks.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            hs256,
+            rs256,
+            jwks,
+            issuer: Some(config.jwt_issuer.clone()).filter(|issuer| !issuer.is_empty()),
+            audience: Some(config.jwt_audience.clone()).filter(|audience| !audience.is_empty()),
+            leeway_secs: config.jwt_leeway_secs,
+        }))
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Claims> {
+        let header = decode_header(token).map_err(|err| AuthError::InvalidToken(err.to_string()))?;
+
+        // Алгоритм берется из заголовка, но только из разрешенных и только с ключом своего типа
+        let key = match header.alg {
+            Algorithm::HS256 => self.hs256.as_ref().ok_or_else(|| AuthError::KeyNotFound("HS256".to_string()))?,
+            Algorithm::RS256 => match header.kid.as_deref().and_then(|kid| self.jwks.get(kid)) {
+                Some(key) => key,
+                None => self.rs256.as_ref().ok_or_else(|| {
+                    AuthError::KeyNotFound(header.kid.clone().unwrap_or_else(|| "RS256".to_string()))
+                })?,
+            },
+            alg => return Err(AuthError::InvalidToken(format!("Unsupported algorithm {alg:?}"))),
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.leeway = self.leeway_secs;
+        match &self.issuer {
+            Some(issuer) => validation.set_issuer(&[issuer]),
+            None => validation.iss = None,
+        }
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let claims = decode::<Claims>(token, key, &validation)
+            .map_err(|err| match err.kind() {
+                ErrorKind::ExpiredSignature => AuthError::Expired,
+                _ => AuthError::InvalidToken(err.to_string()),
+            })?
+            .claims;
+
+        if claims.active == Some(false) {
+            return Err(AuthError::Inactive);
+        }
+        Ok(claims)
+    }
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|err| AuthError::Config(format!("{path}: {err}")))
+}
+
+/// Ключи JWKS с `kid`; ключи без `kid` выбрать нельзя, они пропускаются
+fn load_jwks(path: &str) -> Result<HashMap<String, DecodingKey>> {
+    let jwks: JwkSet = serde_json::from_slice(&read_file(path)?)
+        .map_err(|err| AuthError::Config(format!("{path}: {err}")))?;
+
+    let mut keys = HashMap::new();
+    for jwk in &jwks.keys {
+        let Some(kid) = jwk.common.key_id.clone() else {
+            tracing::warn!("Skipping JWKS key without kid in {}", path);
+            continue;
+        };
+        let key = DecodingKey::from_jwk(jwk).map_err(|err| AuthError::Config(format!("{path}, key {kid}: {err}")))?;
+        keys.insert(kid, key);
+    }
+    Ok(keys)
+}
@@ -0,0 +1,232 @@
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use ulid::Ulid;
+use crate::storage::key::sanitize_filename;
+
+
+/// Через сколько записанных байт заново проверять свободное место
+const SPACE_CHECK_INTERVAL: u64 = 16 * 1024 * 1024;
+
+/// Результат операций с файлами загрузок на диске
+pub type Result<T> = std::result::Result<T, FilesystemError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FilesystemError {
+    #[error("Not enough disk space: {available} bytes available, {required} bytes required")]
+    InsufficientSpace { available: u64, required: u64 },
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+/// Создает каталог загрузки и проверяет, что после записи `expected_size` байт
+/// на диске останется не меньше `min_free_bytes`
+pub async fn prepare_dir(dir: &Path, expected_size: u64, min_free_bytes: u64) -> Result<()> {
+    fs::create_dir_all(dir).await?;
+    ensure_free_space(dir, expected_size.saturating_add(min_free_bytes))
+}
+
+/// Имя файла трека на диске: `{role}-{ulid}.{ext}`, расширение берется из очищенного имени
+pub fn track_file_name(role: &str, filename: &str) -> String {
+    let (_, ext) = sanitize_filename(filename);
+    let ulid = Ulid::new().to_string().to_ascii_lowercase();
+    if ext.is_empty() {
+        format!("{role}-{ulid}")
+    } else {
+        format!("{role}-{ulid}.{ext}")
+    }
+}
+
+fn ensure_free_space(dir: &Path, required: u64) -> Result<()> {
+    let available = fs2::available_space(dir)?;
+    if available < required {
+        return Err(FilesystemError::InsufficientSpace { available, required });
+    }
+    Ok(())
+}
+
+/// Файл, который пишется во временный файл рядом с целевым и появляется под
+/// своим именем только в `commit`: fsync данных, атомарный rename и fsync каталога.
+/// Незакоммиченный временный файл удаляется при сбросе
+pub struct AtomicFile {
+    file: Option<fs::File>,
+    temp_path: PathBuf,
+    path: PathBuf,
+    min_free_bytes: u64,
+    written: u64,
+    next_space_check: u64,
+}
+
+impl AtomicFile {
+    /// Открывает временный файл для `path`; каталог должен существовать
+    pub async fn create(path: PathBuf, min_free_bytes: u64) -> Result<Self> {
+        let dir = parent_dir(&path);
+        ensure_free_space(dir, min_free_bytes)?;
+
+        let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        let temp_path = dir.join(format!(".{file_name}.{}.tmp", Ulid::new()));
+        let file = fs::OpenOptions::new().write(true).create_new(true).open(&temp_path).await?;
+
+        Ok(Self {
+            file: Some(file),
+            temp_path,
+            path,
+            min_free_bytes,
+            written: 0,
+            next_space_check: SPACE_CHECK_INTERVAL,
+        })
+    }
+
+    /// Путь, под которым файл появится после `commit`
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Дописывает данные; каждые `SPACE_CHECK_INTERVAL` байт проверяет, что диск
+    /// не заполняется сверх `min_free_bytes`
+    pub async fn write(&mut self, data: &[u8]) -> Result<()> {
+        let Some(file) = self.file.as_mut() else {
+            return Err(std::io::Error::other("File is already committed").into());
+        };
+        file.write_all(data).await?;
+
+        self.written += data.len() as u64;
+        if self.written >= self.next_space_check {
+            ensure_free_space(parent_dir(&self.path), self.min_free_bytes)?;
+            self.next_space_check = self.written + SPACE_CHECK_INTERVAL;
+        }
+        Ok(())
+    }
+
+    /// Меняет путь, под которым файл появится после `commit`. Новый каталог
+    /// должен существовать и быть на той же файловой системе
+    pub fn retarget(mut self, path: PathBuf) -> Self {
+        self.path = path;
+        self
+    }
+
+    /// Сбрасывает данные на диск и переносит файл на место
+    pub async fn commit(mut self) -> Result<PathBuf> {
+        if let Some(mut file) = self.file.take() {
+            file.flush().await?;
+            file.sync_all().await?;
+        }
+        fs::rename(&self.temp_path, &self.path).await?;
+        // После rename временного файла больше нет, удалять в drop нечего
+        self.temp_path = PathBuf::new();
+        sync_dir(parent_dir(&self.path)).await?;
+        Ok(std::mem::take(&mut self.path))
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.temp_path.as_os_str().is_empty() {
+            return;
+        }
+        drop(self.file.take());
+        if let Err(err) = std::fs::remove_file(&self.temp_path) {
+            tracing::warn!("Failed to remove temporary file {}: {}", self.temp_path.display(), err);
+        }
+    }
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    path.parent().unwrap_or(Path::new("."))
+}
+
+/// Запись о переименовании попадает на диск только после fsync каталога
+#[cfg(unix)]
+async fn sync_dir(dir: &Path) -> Result<()> {
+    fs::File::open(dir).await?.sync_all().await?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn sync_dir(_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("svaha-fs-{}", Ulid::new()));
+        fs::create_dir_all(&dir).await.unwrap();
+        dir
+    }
+
+    async fn file_names(dir: &Path) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut entries = fs::read_dir(dir).await.unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn file_appears_only_after_commit() {
+        let dir = temp_dir().await;
+        let path = dir.join("vocal.wav");
+
+        let mut file = AtomicFile::create(path.clone(), 0).await.unwrap();
+        file.write(b"abc").await.unwrap();
+        file.write(b"def").await.unwrap();
+        assert!(!path.exists());
+        assert_eq!(file_names(&dir).await.len(), 1);
+
+        assert_eq!(file.commit().await.unwrap(), path);
+        assert_eq!(fs::read(&path).await.unwrap(), b"abcdef");
+        assert_eq!(file_names(&dir).await, ["vocal.wav"]);
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn dropped_file_leaves_nothing() {
+        let dir = temp_dir().await;
+        let mut file = AtomicFile::create(dir.join("vocal.wav"), 0).await.unwrap();
+        file.write(b"partial").await.unwrap();
+        drop(file);
+
+        assert!(file_names(&dir).await.is_empty());
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn retargeted_file_is_committed_to_the_new_path() {
+        let dir = temp_dir().await;
+        fs::create_dir_all(dir.join("other")).await.unwrap();
+        let mut file = AtomicFile::create(dir.join("vocal.wav"), 0).await.unwrap();
+        file.write(b"abc").await.unwrap();
+
+        let file = file.retarget(dir.join("other/vocal.wav"));
+        assert_eq!(file.path(), dir.join("other/vocal.wav"));
+        file.commit().await.unwrap();
+        assert_eq!(file_names(&dir).await, ["other"]);
+        assert_eq!(fs::read(dir.join("other/vocal.wav")).await.unwrap(), b"abc");
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn free_space_is_checked() {
+        let dir = temp_dir().await;
+        let err = AtomicFile::create(dir.join("vocal.wav"), u64::MAX).await.err().unwrap();
+        assert!(matches!(err, FilesystemError::InsufficientSpace { required: u64::MAX, .. }));
+        assert!(matches!(prepare_dir(&dir, u64::MAX, 1).await, Err(FilesystemError::InsufficientSpace { .. })));
+        prepare_dir(&dir.join("a/b"), 0, 0).await.unwrap();
+        assert!(dir.join("a/b").is_dir());
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[test]
+    fn track_file_names_keep_only_the_extension() {
+        let name = track_file_name("vocal", "../../My Song.WAV");
+        assert!(name.starts_with("vocal-") && name.ends_with(".wav"), "{name}");
+        assert!(!track_file_name("instrumental", "noext").contains('.'));
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use futures::future::{join_all, BoxFuture, FutureExt};
+use redis::aio::ConnectionManager;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use crate::s3::S3Manager;
+use crate::shutdown::ShutdownCoordinator;
+
+
+/// Готовность сервиса принимать запросы
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Readiness {
+    Ready,
+    /// Одна из зависимостей недоступна
+    NotReady,
+    /// Сервер останавливается и ждет начатые загрузки
+    Draining,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Error,
+    Timeout,
+}
+
+/// Результат проверки одной зависимости
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    /// `s3:<бакет>` или `redis`
+    pub name: String,
+    pub status: CheckStatus,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessReport {
+    pub status: Readiness,
+    pub checks: Vec<CheckResult>,
+    pub checked_at: DateTime<Utc>,
+    /// Результат взят из кеша
+    pub cached: bool,
+}
+
+impl ReadinessReport {
+    pub fn is_ready(&self) -> bool {
+        self.status == Readiness::Ready
+    }
+}
+
+/// Проверка готовности: доступность бакетов S3 и Redis. Проверки идут
+/// параллельно, каждая со своим таймаутом; результат кешируется на `cache_ttl`,
+/// а одновременные пробы ждут одну проверку
+pub struct HealthChecker {
+    s3: Option<S3Manager>,
+    buckets: Vec<String>,
+    redis: Option<ConnectionManager>,
+    shutdown: Arc<ShutdownCoordinator>,
+    timeout: Duration,
+    cache_ttl: Duration,
+    cache: Mutex<Option<ReadinessReport>>,
+}
+
+impl HealthChecker {
+    /// Бакеты проверяются, только если выбран S3-бэкенд (`s3` задан),
+    /// Redis - если он используется. Повторяющиеся бакеты проверяются один раз
+    pub fn new(
+        s3: Option<S3Manager>,
+        mut buckets: Vec<String>,
+        redis: Option<ConnectionManager>,
+        shutdown: Arc<ShutdownCoordinator>,
+        timeout: Duration,
+        cache_ttl: Duration,
+    ) -> Self {
+        let mut seen = HashSet::new();
+        buckets.retain(|bucket| seen.insert(bucket.clone()));
+        Self { s3, buckets, redis, shutdown, timeout, cache_ttl, cache: Mutex::default() }
+    }
+
+    pub async fn readiness(&self) -> ReadinessReport {
+        // Во время остановки балансировщик должен сразу перестать слать запросы
+        if self.shutdown.is_draining() {
+            return ReadinessReport {
+                status: Readiness::Draining,
+                checks: Vec::new(),
+                checked_at: Utc::now(),
+                cached: false,
+            };
+        }
+
+        let mut cache = self.cache.lock().await;
+        if let Some(report) = cache.as_ref() {
+            let age = (Utc::now() - report.checked_at).to_std().unwrap_or_default();
+            if age < self.cache_ttl {
+                return ReadinessReport { cached: true, ..report.clone() };
+            }
+        }
+
+        let report = self.check().await;
+        if !report.is_ready() {
+            let failed: Vec<_> = report.checks
+                .iter()
+                .filter(|check| check.status != CheckStatus::Ok)
+                .map(|check| check.name.as_str())
+                .collect();
+            tracing::warn!("Service is not ready, failed checks: {}", failed.join(", "));
+        }
+        *cache = Some(report.clone());
+        report
+    }
+
+    async fn check(&self) -> ReadinessReport {
+        let mut checks: Vec<BoxFuture<'_, CheckResult>> = Vec::new();
+        if let Some(s3) = &self.s3 {
+            for bucket in &self.buckets {
+                checks.push(self.run(format!("s3:{bucket}"), async move {
+                    s3.head_bucket(bucket).await.map_err(|err| err.to_string())
+                }).boxed());
+            }
+        }
+        if let Some(redis) = &self.redis {
+            let mut redis = redis.clone();
+            checks.push(self.run("redis".to_string(), async move {
+                redis::cmd("PING").query_async::<()>(&mut redis).await.map_err(|err| err.to_string())
+            }).boxed());
+        }
+        let checks = join_all(checks).await;
+
+        let ready = checks.iter().all(|check| check.status == CheckStatus::Ok);
+        ReadinessReport {
+            status: if ready { Readiness::Ready } else { Readiness::NotReady },
+            checks,
+            checked_at: Utc::now(),
+            cached: false,
+        }
+    }
+
+    /// Выполняет проверку с таймаутом и замеряет время
+    async fn run(
+        &self,
+        name: String,
+        check: impl Future<Output = Result<(), String>> + Send,
+    ) -> CheckResult {
+        let started = Instant::now();
+        let (status, error) = match tokio::time::timeout(self.timeout, check).await {
+            Ok(Ok(())) => (CheckStatus::Ok, None),
+            Ok(Err(err)) => (CheckStatus::Error, Some(err)),
+            Err(_) => (CheckStatus::Timeout, Some(format!("No response in {}ms", self.timeout.as_millis()))),
+        };
+        CheckResult { name, status, latency_ms: started.elapsed().as_millis() as u64, error }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_dependencies(shutdown: &Arc<ShutdownCoordinator>, cache_ttl: Duration) -> HealthChecker {
+        HealthChecker::new(None, Vec::new(), None, Arc::clone(shutdown), Duration::from_millis(50), cache_ttl)
+    }
+
+    #[tokio::test]
+    async fn report_is_cached_for_its_ttl() {
+        let shutdown = Arc::new(ShutdownCoordinator::new());
+
+        let checker = no_dependencies(&shutdown, Duration::from_secs(60));
+        let first = checker.readiness().await;
+        assert!(first.is_ready() && !first.cached);
+        let second = checker.readiness().await;
+        assert!(second.cached);
+        assert_eq!(second.checked_at, first.checked_at);
+
+        let checker = no_dependencies(&shutdown, Duration::ZERO);
+        checker.readiness().await;
+        assert!(!checker.readiness().await.cached);
+    }
+
+    #[tokio::test]
+    async fn concurrent_probes_share_one_check() {
+        let shutdown = Arc::new(ShutdownCoordinator::new());
+        let checker = no_dependencies(&shutdown, Duration::from_secs(60));
+
+        let reports = join_all((0..8).map(|_| checker.readiness())).await;
+        assert_eq!(reports.iter().filter(|report| !report.cached).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn draining_overrides_the_cached_report() {
+        let shutdown = Arc::new(ShutdownCoordinator::new());
+        let checker = no_dependencies(&shutdown, Duration::from_secs(60));
+        assert!(checker.readiness().await.is_ready());
+
+        shutdown.drain(Duration::ZERO, "").await;
+        let report = checker.readiness().await;
+        assert_eq!(report.status, Readiness::Draining);
+        assert!(!report.cached && report.checks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_and_hanging_checks_are_reported() {
+        let shutdown = Arc::new(ShutdownCoordinator::new());
+        let checker = no_dependencies(&shutdown, Duration::from_secs(60));
+
+        let failed = checker.run("redis".to_string(), async { Err("refused".to_string()) }).await;
+        assert_eq!(failed.status, CheckStatus::Error);
+        assert_eq!(failed.error.as_deref(), Some("refused"));
+
+        let hanging = checker.run("s3:bucket".to_string(), std::future::pending()).await;
+        assert_eq!(hanging.status, CheckStatus::Timeout);
+        assert!(hanging.latency_ms >= 50);
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::Utc;
+use tokio::task::JoinHandle;
+use crate::storage::ObjectStore;
+
+
+/// Фоновая задача, отменяющая многочастные загрузки старше `max_age`
+pub fn spawn_multipart_janitor(
+    storage: Arc<dyn ObjectStore>,
+    buckets: Vec<String>,
+    max_age: Duration,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            for bucket in &buckets {
+                sweep_bucket(storage.as_ref(), bucket, max_age).await;
+            }
+        }
+    })
+}
+
+/// Один проход по бакету: находит устаревшие загрузки и отменяет их
+pub async fn sweep_bucket(storage: &dyn ObjectStore, bucket: &str, max_age: Duration) {
+    let uploads = match storage.list_multipart_uploads(bucket).await {
+        Ok(uploads) => uploads,
+        Err(err) => {
+            tracing::error!("Janitor failed to list multipart uploads in {}: {}", bucket, err);
+            return;
+        }
+    };
+
+    let max_age = chrono::Duration::from_std(max_age).unwrap_or(chrono::Duration::MAX);
+    let now = Utc::now();
+    let found = uploads.len();
+    let mut aborted = 0;
+    let mut failed = 0;
+
+    for upload in uploads {
+        // Загрузки без даты начала не трогаем: мы не знаем их возраст
+        let Some(initiated) = upload.initiated else { continue };
+        if now - initiated < max_age {
+            continue;
+        }
+
+        match storage.abort_multipart_upload(bucket, &upload.key, &upload.upload_id).await {
+            Ok(()) => aborted += 1,
+            Err(err) => {
+                failed += 1;
+                tracing::warn!(
+                    "Janitor failed to abort upload {} of {}/{}: {}",
+                    upload.upload_id, bucket, upload.key, err
+                );
+            }
+        }
+    }
+
+    tracing::info!(
+        "Janitor swept {}: {} incomplete uploads, {} aborted, {} failed",
+        bucket, found, aborted, failed
+    );
+}
@@ -1,19 +1,176 @@
 pub mod s3_old;
 
+pub mod audio;
+pub mod auth;
+pub mod filesystem;
+pub mod health;
+pub mod janitor;
+pub mod links;
+pub mod progress;
+pub mod rate_limit;
 pub mod s3;
+pub mod sessions;
+pub mod shutdown;
+pub mod storage;
+pub mod tus;
 
 
-use s3::{S3Manager};
+use std::sync::Arc;
+use std::time::Duration;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use auth::JwtVerifier;
+use health::HealthChecker;
+use links::UploadLinks;
+use progress::ProgressHub;
+use rate_limit::RateLimiter;
+use s3::{S3Manager, S3ClientOptions};
+use sessions::{MemorySessionRegistry, RedisSessionRegistry, SessionBackend, SessionRegistry};
+use shutdown::ShutdownCoordinator;
+use storage::{KeyTemplate, LocalObjectStore, MemoryObjectStore, ObjectStore, StorageBackend};
+use tus::TusStore;
 use anyhow::Result;
 use my_core::config::CONFIG;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub s3: S3Manager,
+    /// Хранилище загружаемых файлов, выбранное в `Config::storage_backend`
+    pub storage: Arc<dyn ObjectStore>,
+    /// Клиент S3, если выбран S3-бэкенд
+    pub s3: Option<S3Manager>,
+    /// Состояние возобновляемых загрузок по протоколу tus
+    pub tus: TusStore,
+    /// Шаблон ключей загружаемых файлов
+    pub key_template: KeyTemplate,
+    /// Подключение к Redis, если реестр сессий хранится в Redis
+    pub redis: Option<ConnectionManager>,
+    /// Реестр сессий загрузки, выбранный в `Config::session_backend`
+    pub sessions: Arc<dyn SessionRegistry>,
+    /// Рассылка прогресса загрузок подписчикам SSE
+    pub progress: Arc<ProgressHub>,
+    /// Подписанные ссылки на загрузку; `None`, если не задан `upload_link_secret`
+    pub links: Option<Arc<UploadLinks>>,
+    /// Проверка bearer-токенов; `None`, если не задан ни один ключ JWT
+    pub jwt: Option<Arc<JwtVerifier>>,
+    /// Ограничение запросов и байт на клиента; `None`, если лимиты не заданы
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// Учет загрузок для остановки без их потери
+    pub shutdown: Arc<ShutdownCoordinator>,
+    /// Проверка готовности для `/ready`
+    pub health: Arc<HealthChecker>,
 }
 
 impl AppState {
     pub async fn new() -> Result<Self> {
+        let (storage, s3): (Arc<dyn ObjectStore>, Option<S3Manager>) = match CONFIG.storage_backend {
+            StorageBackend::S3 => {
+                let s3 = Self::create_s3_manager().await?;
+                (Arc::new(s3.clone()), Some(s3))
+            }
+            StorageBackend::Local => {
+                tracing::info!("Using local filesystem storage in {}", CONFIG.base_upload_dir);
+                (Arc::new(LocalObjectStore::new(&CONFIG.base_upload_dir).await?), None)
+            }
+            StorageBackend::Memory => {
+                tracing::warn!("Using in-memory storage, uploaded files will be lost on restart");
+                (Arc::new(MemoryObjectStore::new()), None)
+            }
+        };
+
+        let tus = TusStore::new(Arc::clone(&storage), CONFIG.upload_bucket_name.clone());
+        let key_template = KeyTemplate::new(&CONFIG.upload_key_template, CONFIG.upload_key_max_length)?;
+
+        let session_ttl = Duration::from_secs(CONFIG.session_ttl_secs);
+        let (sessions, redis): (Arc<dyn SessionRegistry>, Option<ConnectionManager>) = match CONFIG.session_backend {
+            SessionBackend::Redis => {
+                let redis = Self::create_redis_manager().await?;
+                (Arc::new(RedisSessionRegistry::new(redis.clone(), session_ttl)), Some(redis))
+            }
+            SessionBackend::Memory => {
+                tracing::warn!("Using in-memory session registry, sessions will be lost on restart");
+                (Arc::new(MemorySessionRegistry::new(session_ttl)), None)
+            }
+        };
+
+        let progress = Arc::new(ProgressHub::new(
+            CONFIG.upload_progress_buffer,
+            Duration::from_millis(CONFIG.upload_progress_interval_ms),
+        ));
+
+        let links = if CONFIG.upload_link_secret.is_empty() {
+            tracing::warn!("upload_link_secret is not set, upload pages and endpoints are not protected by signed links");
+            None
+        } else {
+            Some(Arc::new(UploadLinks::new(&CONFIG.upload_link_secret, redis.clone())))
+        };
+
+        let jwt = JwtVerifier::from_config(&CONFIG)?.map(Arc::new);
+        if jwt.is_none() {
+            // Ссылки выдает только пользователь с bearer-токеном: без JWT их выдал бы кто угодно
+            if links.is_some() {
+                anyhow::bail!("upload_link_secret requires JWT keys to authenticate who issues upload links");
+            }
+            tracing::warn!("No JWT keys configured, bearer tokens are not accepted");
+        }
+
+        let rate_limiter = RateLimiter::new(
+            redis.clone(),
+            Duration::from_secs(CONFIG.rate_limit_window_secs),
+            CONFIG.rate_limit_requests,
+            CONFIG.rate_limit_bytes,
+        ).map(Arc::new);
+
+        let shutdown = Arc::new(ShutdownCoordinator::new());
+
+        let health = Arc::new(HealthChecker::new(
+            s3.clone(),
+            vec![CONFIG.upload_bucket_name.clone(), CONFIG.s3_bucket_name.clone()],
+            redis.clone(),
+            Arc::clone(&shutdown),
+            Duration::from_millis(CONFIG.readiness_check_timeout_ms),
+            Duration::from_millis(CONFIG.readiness_cache_ms),
+        ));
+
+        Ok(Self { storage, s3, tus, key_template, redis, sessions, progress, links, jwt, rate_limiter, shutdown, health })
+    }
+
+    /// Запускает фоновую отмену заброшенных многочастных загрузок
+    pub fn spawn_multipart_janitor(&self) -> Option<tokio::task::JoinHandle<()>> {
+        if CONFIG.multipart_janitor_interval_secs == 0 {
+            return None;
+        }
+
+        Some(janitor::spawn_multipart_janitor(
+            Arc::clone(&self.storage),
+            vec![CONFIG.upload_bucket_name.clone(), CONFIG.s3_bucket_name.clone()],
+            Duration::from_secs(CONFIG.multipart_max_age_secs),
+            Duration::from_secs(CONFIG.multipart_janitor_interval_secs),
+        ))
+    }
+
+    async fn create_redis_manager() -> Result<ConnectionManager> {
+        let host = CONFIG.redis_host.to_str()?.to_string();
+        let connection_info = redis::ConnectionInfo {
+            addr: redis::ConnectionAddr::Tcp(host, CONFIG.redis_port),
+            redis: redis::RedisConnectionInfo {
+                username: Some(CONFIG.redis_login.clone()).filter(|login| !login.is_empty()),
+                password: Some(CONFIG.redis_password.clone()).filter(|password| !password.is_empty()),
+                ..Default::default()
+            },
+        };
+        let client = redis::Client::open(connection_info)?;
+        // По умолчанию задержка между попытками растет в 100 раз, и недоступный
+        // Redis задерживает старт на минуты; ограничиваем ее секундой
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(Duration::from_millis(CONFIG.redis_connect_timeout_ms))
+            .set_factor(2)
+            .set_max_delay(1000)
+            .set_number_of_retries(3);
+
+        tracing::info!("Connecting to Redis at {}:{}", CONFIG.redis_host.to_str()?, CONFIG.redis_port);
+        Ok(ConnectionManager::new_with_config(client, config).await?)
+    }
+
+    async fn create_s3_manager() -> Result<S3Manager> {
         // Create s3_old manager
         let region = CONFIG.s3_region_name.clone();
         let endpoint = CONFIG.s3_endpoint.to_str().expect("Invalid S3 endpoint").to_string();
@@ -28,8 +185,6 @@ impl AppState {
             "env",
         );
 
-        let s3 = S3Manager::new(region, Some(endpoint), credentials).await?;
-
-        Ok(Self { s3 })
+        Ok(S3Manager::new(region, Some(endpoint), credentials, S3ClientOptions::from_config(&CONFIG)).await?)
     }
-}
\ No newline at end of file
+}
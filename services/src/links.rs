@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::Mutex;
+use ulid::Ulid;
+use crate::sessions::TrackRole;
+
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Префикс ключей использованных одноразовых ссылок в Redis
+const USED_KEY_PREFIX: &str = "upload:link";
+
+/// Результат проверки ссылок загрузки
+pub type Result<T> = std::result::Result<T, LinkError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LinkError {
+    #[error("Malformed upload link")]
+    Malformed,
+
+    #[error("Invalid upload link signature")]
+    InvalidSignature,
+
+    #[error("Upload link has expired")]
+    Expired,
+
+    #[error("Upload link has already been used")]
+    AlreadyUsed,
+
+    #[error("Upload link does not allow this upload")]
+    NotAllowed,
+
+    #[error("Redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+}
+
+/// Что разрешает ссылка: загрузку в трек в заданных ролях до `expires_at`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadLink {
+    #[serde(rename = "sid")]
+    pub session_id: String,
+    #[serde(rename = "tid")]
+    pub track_id: String,
+    pub roles: Vec<TrackRole>,
+    /// Unix-время истечения
+    #[serde(rename = "exp")]
+    pub expires_at: i64,
+    /// Ссылка действует на одну загрузку
+    #[serde(default)]
+    pub one_time: bool,
+    /// Уникальный идентификатор ссылки, по нему учитываются одноразовые
+    pub nonce: String,
+}
+
+impl UploadLink {
+    pub fn new(
+        session_id: &str,
+        track_id: &str,
+        roles: Vec<TrackRole>,
+        expires_at: DateTime<Utc>,
+        one_time: bool,
+    ) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            track_id: track_id.to_string(),
+            roles,
+            expires_at: expires_at.timestamp(),
+            one_time,
+            nonce: Ulid::new().to_string().to_ascii_lowercase(),
+        }
+    }
+
+    /// Ссылка выдана на этот трек и разрешает все роли из `roles`
+    pub fn allows(&self, session_id: &str, track_id: &str, roles: &[TrackRole]) -> bool {
+        self.session_id == session_id
+            && self.track_id == track_id
+            && roles.iter().all(|role| self.roles.contains(role))
+    }
+}
+
+/// Подпись и проверка ссылок загрузки: `base64url(json).base64url(hmac-sha256)`
+pub struct UploadLinks {
+    key: Vec<u8>,
+    used: UsedLinks,
+}
+
+impl UploadLinks {
+    /// Использованные одноразовые ссылки хранятся в Redis, если он есть, иначе в памяти процесса
+    pub fn new(secret: &str, redis: Option<ConnectionManager>) -> Self {
+        let used = match redis {
+            Some(redis) => UsedLinks::Redis(redis),
+            None => UsedLinks::Memory(Mutex::default()),
+        };
+        Self { key: secret.as_bytes().to_vec(), used }
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length")
+    }
+
+    pub fn sign(&self, link: &UploadLink) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(link).expect("Upload link is serializable"));
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        format!("{payload}.{signature}")
+    }
+
+    /// Проверяет подпись и срок действия; одноразовую ссылку не расходует
+    pub fn verify(&self, token: &str) -> Result<UploadLink> {
+        let (payload, signature) = token.split_once('.').ok_or(LinkError::Malformed)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| LinkError::Malformed)?;
+
+        // Сравнение подписи за постоянное время
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature).map_err(|_| LinkError::InvalidSignature)?;
+
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| LinkError::Malformed)?;
+        let link: UploadLink = serde_json::from_slice(&payload).map_err(|_| LinkError::Malformed)?;
+        if link.expires_at <= Utc::now().timestamp() {
+            return Err(LinkError::Expired);
+        }
+        Ok(link)
+    }
+
+    /// Отмечает одноразовую ссылку использованной; повторное использование - ошибка
+    pub async fn consume(&self, link: &UploadLink) -> Result<()> {
+        if !link.one_time {
+            return Ok(());
+        }
+        if !self.used.mark(link).await? {
+            return Err(LinkError::AlreadyUsed);
+        }
+        Ok(())
+    }
+}
+
+/// Использованные одноразовые ссылки; запись живет до истечения ссылки
+enum UsedLinks {
+    Redis(ConnectionManager),
+    Memory(Mutex<HashMap<String, i64>>),
+}
+
+impl UsedLinks {
+    /// `false`, если ссылка уже была использована
+    async fn mark(&self, link: &UploadLink) -> Result<bool> {
+        let ttl = (link.expires_at - Utc::now().timestamp()).max(1);
+        match self {
+            Self::Redis(redis) => {
+                let mut redis = redis.clone();
+                let marked: Option<String> = redis::cmd("SET")
+                    .arg(format!("{USED_KEY_PREFIX}:{}", link.nonce))
+                    .arg(1)
+                    .arg("NX")
+                    .arg("EX")
+                    .arg(ttl)
+                    .query_async(&mut redis)
+                    .await?;
+                Ok(marked.is_some())
+            }
+            Self::Memory(used) => {
+                let mut used = used.lock().await;
+                let now = Utc::now().timestamp();
+                used.retain(|_, expires_at| *expires_at > now);
+                Ok(used.insert(link.nonce.clone(), link.expires_at).is_none())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use super::*;
+
+    fn link(expires_in: Duration, one_time: bool) -> UploadLink {
+        UploadLink::new("s", "t", vec![TrackRole::Vocal], Utc::now() + expires_in, one_time)
+    }
+
+    #[test]
+    fn signed_link_verifies_and_keeps_its_grants() {
+        let links = UploadLinks::new("secret", None);
+        let link = link(Duration::minutes(5), false);
+
+        let verified = links.verify(&links.sign(&link)).unwrap();
+        assert_eq!(verified, link);
+        assert!(verified.allows("s", "t", &[TrackRole::Vocal]));
+        assert!(!verified.allows("s", "t", &TrackRole::ALL));
+        assert!(!verified.allows("s", "other", &[TrackRole::Vocal]));
+    }
+
+    #[test]
+    fn tampered_or_foreign_links_are_rejected() {
+        let links = UploadLinks::new("secret", None);
+        let token = links.sign(&link(Duration::minutes(5), false));
+        let (_, signature) = token.split_once('.').unwrap();
+
+        // Чужой секрет
+        let foreign = UploadLinks::new("other", None);
+        assert!(matches!(foreign.verify(&token), Err(LinkError::InvalidSignature)));
+
+        // Подмененные роли со старой подписью
+        let mut widened = link(Duration::minutes(5), false);
+        widened.roles = TrackRole::ALL.to_vec();
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&widened).unwrap());
+        assert!(matches!(links.verify(&format!("{payload}.{signature}")), Err(LinkError::InvalidSignature)));
+
+        for malformed in ["", "no-dot", "a.!!!"] {
+            assert!(matches!(links.verify(malformed), Err(LinkError::Malformed)), "{malformed:?}");
+        }
+    }
+
+    #[test]
+    fn expired_link_is_rejected() {
+        let links = UploadLinks::new("secret", None);
+        let token = links.sign(&link(Duration::seconds(-1), false));
+        assert!(matches!(links.verify(&token), Err(LinkError::Expired)));
+    }
+
+    #[tokio::test]
+    async fn one_time_link_is_consumed_once() {
+        let links = UploadLinks::new("secret", None);
+
+        let one_time = links.verify(&links.sign(&link(Duration::minutes(5), true))).unwrap();
+        links.consume(&one_time).await.unwrap();
+        assert!(matches!(links.consume(&one_time).await, Err(LinkError::AlreadyUsed)));
+        // Проверка подписи ссылку не расходует и после использования
+        assert!(links.verify(&links.sign(&one_time)).is_ok());
+
+        let reusable = link(Duration::minutes(5), false);
+        links.consume(&reusable).await.unwrap();
+        links.consume(&reusable).await.unwrap();
+    }
+}
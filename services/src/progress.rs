@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::broadcast;
+use crate::sessions::TrackRole;
+
+
+/// Сколько помнить последнее состояние завершенной загрузки для поздних подписчиков
+const FINISHED_RETENTION: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Error)]
+pub enum ProgressError {
+    #[error("Upload {0} not found")]
+    UnknownUpload(String),
+    #[error("Upload id {0} is already in use")]
+    UploadIdInUse(String),
+}
+
+/// Этап загрузки файла
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadPhase {
+    /// Файл принимается от клиента
+    Receiving,
+    /// Файл принят, дозагружаются части и проверяется аудио
+    Processing,
+    /// Файл загружен и ждет второй файл пары
+    Staged,
+    Completed,
+    Failed,
+}
+
+impl UploadPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Receiving => "receiving",
+            Self::Processing => "processing",
+            Self::Staged => "staged",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Completed | Self::Failed)
+    }
+}
+
+/// Состояние загрузки одного файла
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadProgress {
+    pub upload_id: String,
+    pub session_id: String,
+    pub track_id: String,
+    pub role: TrackRole,
+    pub phase: UploadPhase,
+    /// Сколько байт файла получено от клиента
+    pub bytes_received: u64,
+    /// Сколько частей отправлено в хранилище
+    pub parts_submitted: u32,
+    /// Сколько частей хранилище подтвердило
+    pub parts_completed: u32,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// На что подписывается клиент: одна загрузка или все загрузки трека
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ProgressTopic {
+    Upload(String),
+    Track { session_id: String, track_id: String },
+}
+
+#[derive(Default)]
+struct HubState {
+    channels: HashMap<ProgressTopic, broadcast::Sender<UploadProgress>>,
+    /// Последнее состояние незавершенных загрузок, для новых подписчиков
+    active: HashMap<String, UploadProgress>,
+    /// Итог недавно завершенных загрузок и время завершения
+    finished: HashMap<String, (UploadProgress, Instant)>,
+}
+
+impl HubState {
+    fn forget_finished(&mut self) {
+        self.finished.retain(|_, (_, finished_at)| finished_at.elapsed() < FINISHED_RETENTION);
+    }
+}
+
+/// Рассылка прогресса загрузок подписчикам внутри процесса. Каналы создаются
+/// подписчиками; канал загрузки закрывается, когда она завершается, канал
+/// трека - когда отписывается последний подписчик. Подписаться можно только
+/// на идущую или недавно завершенную загрузку
+pub struct ProgressHub {
+    state: Mutex<HubState>,
+    capacity: usize,
+    interval: Duration,
+}
+
+impl ProgressHub {
+    /// `capacity` - сколько событий копится для отстающего подписчика,
+    /// `interval` - как часто публиковать число принятых байт
+    pub fn new(capacity: usize, interval: Duration) -> Self {
+        Self { state: Mutex::default(), capacity: capacity.max(1), interval }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HubState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Начинает отслеживать загрузку; если трекер сброшен до завершения,
+    /// загрузка публикуется как неудачная. Идентификатор идущей или недавно
+    /// завершенной загрузки повторно использовать нельзя
+    pub fn track(
+        self: &Arc<Self>,
+        upload_id: String,
+        session_id: &str,
+        track_id: &str,
+        role: TrackRole,
+    ) -> Result<Arc<ProgressTracker>, ProgressError> {
+        let progress = UploadProgress {
+            upload_id,
+            session_id: session_id.to_string(),
+            track_id: track_id.to_string(),
+            role,
+            phase: UploadPhase::Receiving,
+            bytes_received: 0,
+            parts_submitted: 0,
+            parts_completed: 0,
+            updated_at: Utc::now(),
+        };
+        {
+            let mut state = self.lock();
+            state.forget_finished();
+            if state.active.contains_key(&progress.upload_id) || state.finished.contains_key(&progress.upload_id) {
+                return Err(ProgressError::UploadIdInUse(progress.upload_id));
+            }
+            // Занимаем идентификатор сразу, чтобы его не занял параллельный запрос
+            Self::publish_locked(&mut state, &progress);
+        }
+
+        Ok(Arc::new(ProgressTracker {
+            hub: Arc::clone(self),
+            state: Mutex::new(TrackerState { progress, last_published: Instant::now() }),
+        }))
+    }
+
+    /// Подписывает на тему; сначала возвращает текущее состояние ее незавершенных
+    /// загрузок. На завершенную загрузку подписка сразу отдает ее итог и закрывается
+    pub fn subscribe(self: &Arc<Self>, topic: ProgressTopic) -> Result<(Vec<UploadProgress>, ProgressSubscription), ProgressError> {
+        let mut state = self.lock();
+        state.forget_finished();
+
+        if let ProgressTopic::Upload(upload_id) = &topic {
+            if !state.active.contains_key(upload_id) {
+                let Some((progress, _)) = state.finished.get(upload_id) else {
+                    return Err(ProgressError::UnknownUpload(upload_id.clone()));
+                };
+                let snapshot = vec![progress.clone()];
+                return Ok((snapshot, ProgressSubscription { hub: Arc::clone(self), topic, receiver: None }));
+            }
+        }
+
+        let receiver = state.channels
+            .entry(topic.clone())
+            .or_insert_with(|| broadcast::channel(self.capacity).0)
+            .subscribe();
+        let snapshot = state.active
+            .values()
+            .filter(|progress| topics(progress).contains(&topic))
+            .cloned()
+            .collect();
+
+        Ok((snapshot, ProgressSubscription { hub: Arc::clone(self), topic, receiver: Some(receiver) }))
+    }
+
+    fn publish(&self, progress: &UploadProgress) {
+        Self::publish_locked(&mut self.lock(), progress);
+    }
+
+    fn publish_locked(state: &mut HubState, progress: &UploadProgress) {
+        let [upload_topic, track_topic] = topics(progress);
+
+        for topic in [&upload_topic, &track_topic] {
+            if let Some(sender) = state.channels.get(topic) {
+                // Ошибка означает только отсутствие подписчиков
+                let _ = sender.send(progress.clone());
+            }
+        }
+
+        if progress.phase.is_finished() {
+            state.active.remove(&progress.upload_id);
+            state.finished.insert(progress.upload_id.clone(), (progress.clone(), Instant::now()));
+            // Подписчики дочитают накопленные события и получат закрытие канала
+            state.channels.remove(&upload_topic);
+        } else {
+            state.active.insert(progress.upload_id.clone(), progress.clone());
+        }
+    }
+
+    fn release(&self, topic: &ProgressTopic) {
+        let mut state = self.lock();
+        if state.channels.get(topic).is_some_and(|sender| sender.receiver_count() == 0) {
+            state.channels.remove(topic);
+        }
+    }
+}
+
+fn topics(progress: &UploadProgress) -> [ProgressTopic; 2] {
+    [
+        ProgressTopic::Upload(progress.upload_id.clone()),
+        ProgressTopic::Track {
+            session_id: progress.session_id.clone(),
+            track_id: progress.track_id.clone(),
+        },
+    ]
+}
+
+struct TrackerState {
+    progress: UploadProgress,
+    last_published: Instant,
+}
+
+/// Прогресс одной загрузки; общий для обработчика и задач загрузки частей
+pub struct ProgressTracker {
+    hub: Arc<ProgressHub>,
+    state: Mutex<TrackerState>,
+}
+
+impl ProgressTracker {
+    pub fn upload_id(&self) -> String {
+        self.lock().progress.upload_id.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, TrackerState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Обновляет состояние и публикует его; завершенная загрузка больше не меняется.
+    /// Без `force` публикует не чаще `interval`
+    fn update(&self, force: bool, change: impl FnOnce(&mut UploadProgress)) {
+        let mut state = self.lock();
+        if state.progress.phase.is_finished() {
+            return;
+        }
+        change(&mut state.progress);
+
+        if force || state.last_published.elapsed() >= self.hub.interval {
+            state.progress.updated_at = Utc::now();
+            state.last_published = Instant::now();
+            self.hub.publish(&state.progress);
+        }
+    }
+
+    /// Получены очередные байты файла
+    pub fn received(&self, bytes: usize) {
+        self.update(false, |progress| progress.bytes_received += bytes as u64);
+    }
+
+    pub fn part_submitted(&self) {
+        self.update(false, |progress| progress.parts_submitted += 1);
+    }
+
+    pub fn part_completed(&self) {
+        self.update(true, |progress| progress.parts_completed += 1);
+    }
+
+    pub fn set_phase(&self, phase: UploadPhase) {
+        self.update(true, |progress| progress.phase = phase);
+    }
+}
+
+impl Drop for ProgressTracker {
+    fn drop(&mut self) {
+        self.set_phase(UploadPhase::Failed);
+    }
+}
+
+/// Подписка на тему; при сбросе освобождает канал, если подписчиков не осталось
+pub struct ProgressSubscription {
+    hub: Arc<ProgressHub>,
+    topic: ProgressTopic,
+    receiver: Option<broadcast::Receiver<UploadProgress>>,
+}
+
+impl ProgressSubscription {
+    /// Следующее событие; `None` - канал закрыт. Пропущенные отстающим
+    /// подписчиком события не возвращаются: каждое событие - полное состояние
+    pub async fn recv(&mut self) -> Option<UploadProgress> {
+        let receiver = self.receiver.as_mut()?;
+        loop {
+            match receiver.recv().await {
+                Ok(progress) => return Some(progress),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::debug!("Progress subscriber lagged behind by {} events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Drop for ProgressSubscription {
+    fn drop(&mut self) {
+        drop(self.receiver.take());
+        self.hub.release(&self.topic);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hub() -> Arc<ProgressHub> {
+        Arc::new(ProgressHub::new(16, Duration::ZERO))
+    }
+
+    fn track_topic() -> ProgressTopic {
+        ProgressTopic::Track { session_id: "s".to_string(), track_id: "t".to_string() }
+    }
+
+    #[tokio::test]
+    async fn every_subscriber_sees_the_upload_until_it_finishes() {
+        let hub = hub();
+        let tracker = hub.track("u".to_string(), "s", "t", TrackRole::Vocal).unwrap();
+
+        let (snapshot, mut first) = hub.subscribe(ProgressTopic::Upload("u".to_string())).unwrap();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].phase, UploadPhase::Receiving);
+        let (_, mut second) = hub.subscribe(track_topic()).unwrap();
+
+        tracker.received(10);
+        tracker.set_phase(UploadPhase::Completed);
+        for subscription in [&mut first, &mut second] {
+            assert_eq!(subscription.recv().await.unwrap().bytes_received, 10);
+            assert_eq!(subscription.recv().await.unwrap().phase, UploadPhase::Completed);
+        }
+        // Канал загрузки закрывается вместе с ней, канал трека остается
+        assert!(first.recv().await.is_none());
+        assert!(hub.lock().channels.contains_key(&track_topic()));
+
+        drop(second);
+        assert!(hub.lock().channels.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unknown_upload_cannot_be_subscribed() {
+        let hub = hub();
+        assert!(matches!(
+            hub.subscribe(ProgressTopic::Upload("missing".to_string())),
+            Err(ProgressError::UnknownUpload(_))
+        ));
+        assert!(hub.lock().channels.is_empty());
+    }
+
+    #[tokio::test]
+    async fn finished_upload_returns_its_outcome_and_closes() {
+        let hub = hub();
+        drop(hub.track("u".to_string(), "s", "t", TrackRole::Vocal).unwrap());
+
+        let (snapshot, mut subscription) = hub.subscribe(ProgressTopic::Upload("u".to_string())).unwrap();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].phase, UploadPhase::Failed);
+        assert!(subscription.recv().await.is_none());
+    }
+
+    #[test]
+    fn upload_id_in_use_is_rejected() {
+        let hub = hub();
+        let tracker = hub.track("u".to_string(), "s", "t", TrackRole::Vocal).unwrap();
+        assert!(matches!(
+            hub.track("u".to_string(), "s", "t", TrackRole::Instrumental),
+            Err(ProgressError::UploadIdInUse(_))
+        ));
+
+        tracker.set_phase(UploadPhase::Completed);
+        assert!(matches!(
+            hub.track("u".to_string(), "s", "t", TrackRole::Vocal),
+            Err(ProgressError::UploadIdInUse(_))
+        ));
+        // Отклоненная попытка не меняет состояние исходной загрузки
+        assert_eq!(hub.lock().finished["u"].0.phase, UploadPhase::Completed);
+    }
+}
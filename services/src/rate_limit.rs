@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use chrono::Utc;
+use redis::aio::ConnectionManager;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+
+/// Префикс ключей счетчиков в Redis
+const COUNTER_KEY_PREFIX: &str = "ratelimit";
+
+/// Что ограничивается
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitKind {
+    /// Число запросов
+    Requests,
+    /// Байты тел запросов
+    Bytes,
+}
+
+impl LimitKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Requests => "requests",
+            Self::Bytes => "bytes",
+        }
+    }
+}
+
+/// Результат проверки лимита
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitDecision {
+    pub kind: LimitKind,
+    pub allowed: bool,
+    pub limit: u64,
+    /// Сколько еще осталось в текущем окне
+    pub remaining: u64,
+    /// Через сколько секунд начнется новое окно
+    pub reset_secs: u64,
+}
+
+/// Ограничение числа запросов и байт на клиента в фиксированном окне.
+/// Счетчики общие для всех экземпляров через Redis; без Redis или при его
+/// ошибках считаются в памяти процесса
+pub struct RateLimiter {
+    redis: Option<ConnectionManager>,
+    memory: Mutex<HashMap<String, u64>>,
+    window_secs: u64,
+    requests: u64,
+    bytes: u64,
+}
+
+impl RateLimiter {
+    /// `None`, если оба лимита выключены (равны 0)
+    pub fn new(redis: Option<ConnectionManager>, window: Duration, requests: u64, bytes: u64) -> Option<Self> {
+        if requests == 0 && bytes == 0 {
+            return None;
+        }
+        Some(Self {
+            redis,
+            memory: Mutex::default(),
+            window_secs: window.as_secs().max(1),
+            requests,
+            bytes,
+        })
+    }
+
+    /// Лимит на окно; 0 - не ограничивается
+    pub fn limit(&self, kind: LimitKind) -> u64 {
+        match kind {
+            LimitKind::Requests => self.requests,
+            LimitKind::Bytes => self.bytes,
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    /// Учитывает `cost` и проверяет лимит; отказ в счетчик не попадает.
+    /// С нулевой ценой только проверяет, не исчерпан ли лимит.
+    /// `None`, если этот лимит выключен
+    pub async fn acquire(&self, kind: LimitKind, key: &str, cost: u64) -> Option<RateLimitDecision> {
+        let limit = self.limit(kind);
+        if limit == 0 {
+            return None;
+        }
+
+        let (counter_key, reset_secs) = self.counter_key(kind, key);
+        let mut count = self.add(&counter_key, cost, reset_secs).await;
+        let allowed = if cost == 0 { count < limit } else { count <= limit };
+        if !allowed && cost > 0 {
+            count = self.sub(&counter_key, cost).await;
+        }
+
+        Some(RateLimitDecision {
+            kind,
+            allowed,
+            limit,
+            remaining: limit.saturating_sub(count),
+            reset_secs,
+        })
+    }
+
+    /// Учитывает запрос во всех счетчиках `keys` сразу: `costs` - цена по каждому
+    /// виду лимита. Если хоть один лимит исчерпан, уже учтенное возвращается и
+    /// запрос не попадает ни в один счетчик. Выключенные лимиты пропускаются
+    pub async fn acquire_all(
+        &self,
+        keys: &[String],
+        costs: &[(LimitKind, u64)],
+    ) -> Result<Vec<RateLimitDecision>, RateLimitDecision> {
+        let mut decisions = Vec::new();
+        let mut charged = Vec::new();
+
+        for &(kind, cost) in costs {
+            for key in keys {
+                let Some(decision) = self.acquire(kind, key, cost).await else {
+                    continue;
+                };
+                if !decision.allowed {
+                    for (kind, key, cost) in charged {
+                        if cost > 0 {
+                            let (counter_key, _) = self.counter_key(kind, key);
+                            self.sub(&counter_key, cost).await;
+                        }
+                    }
+                    return Err(decision);
+                }
+                charged.push((kind, key.as_str(), cost));
+                decisions.push(decision);
+            }
+        }
+        Ok(decisions)
+    }
+
+    /// Учитывает `amount` без проверки лимита, например байты тела, длина
+    /// которого не была известна заранее
+    pub async fn charge(&self, kind: LimitKind, key: &str, amount: u64) {
+        if self.limit(kind) == 0 || amount == 0 {
+            return;
+        }
+        let (counter_key, reset_secs) = self.counter_key(kind, key);
+        self.add(&counter_key, amount, reset_secs).await;
+    }
+
+    /// Ключ счетчика текущего окна и время до конца окна
+    fn counter_key(&self, kind: LimitKind, key: &str) -> (String, u64) {
+        let now = Utc::now().timestamp().max(0) as u64;
+        let window_start = now - now % self.window_secs;
+        let reset_secs = window_start + self.window_secs - now;
+        (format!("{COUNTER_KEY_PREFIX}:{}:{key}:{window_start}", kind.as_str()), reset_secs)
+    }
+
+    async fn add(&self, counter_key: &str, amount: u64, ttl_secs: u64) -> u64 {
+        if let Some(redis) = &self.redis {
+            let mut redis = redis.clone();
+            let result: redis::RedisResult<(u64,)> = redis::pipe()
+                .cmd("INCRBY").arg(counter_key).arg(amount)
+                .cmd("EXPIRE").arg(counter_key).arg(ttl_secs + 1).ignore()
+                .query_async(&mut redis)
+                .await;
+            match result {
+                Ok((count,)) => return count,
+                Err(err) => tracing::warn!("Rate limit counter in Redis failed, counting in memory: {}", err),
+            }
+        }
+
+        let mut memory = self.memory.lock().await;
+        // Счетчики прошедших окон больше не нужны
+        let window_suffix = counter_key.rsplit(':').next().unwrap_or_default();
+        memory.retain(|key, _| key.ends_with(window_suffix));
+        let count = memory.entry(counter_key.to_string()).or_default();
+        *count += amount;
+        *count
+    }
+
+    async fn sub(&self, counter_key: &str, amount: u64) -> u64 {
+        if let Some(redis) = &self.redis {
+            let mut redis = redis.clone();
+            let result: redis::RedisResult<i64> = redis::cmd("DECRBY")
+                .arg(counter_key)
+                .arg(amount)
+                .query_async(&mut redis)
+                .await;
+            match result {
+                Ok(count) => return count.max(0) as u64,
+                Err(err) => tracing::warn!("Rate limit counter in Redis failed, counting in memory: {}", err),
+            }
+        }
+
+        let mut memory = self.memory.lock().await;
+        let count = memory.entry(counter_key.to_string()).or_default();
+        *count = count.saturating_sub(amount);
+        *count
+    }
+}
+
+/// Короткий отпечаток токена для ключа лимита, чтобы сами токены не попадали в Redis
+pub fn token_fingerprint(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .take(8)
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Без Redis счетчики живут в памяти; окно длинное, чтобы тест не пересек его границу
+    fn limiter(requests: u64, bytes: u64) -> RateLimiter {
+        RateLimiter::new(None, Duration::from_secs(3600), requests, bytes).unwrap()
+    }
+
+    async fn remaining(limiter: &RateLimiter, kind: LimitKind, key: &str) -> u64 {
+        limiter.acquire(kind, key, 0).await.unwrap().remaining
+    }
+
+    fn keys() -> Vec<String> {
+        vec!["ip:a".to_string(), "session:s".to_string()]
+    }
+
+    #[tokio::test]
+    async fn rejected_request_is_rolled_back_from_every_counter() {
+        let limiter = limiter(5, 100);
+        limiter.charge(LimitKind::Bytes, "session:s", 90).await;
+
+        let denied = limiter.acquire_all(&keys(), &[(LimitKind::Requests, 1), (LimitKind::Bytes, 20)]).await.unwrap_err();
+        assert_eq!(denied.kind, LimitKind::Bytes);
+        assert!(!denied.allowed);
+
+        // Уже учтенные запросы и байты по другим ключам возвращены
+        assert_eq!(remaining(&limiter, LimitKind::Requests, "ip:a").await, 5);
+        assert_eq!(remaining(&limiter, LimitKind::Requests, "session:s").await, 5);
+        assert_eq!(remaining(&limiter, LimitKind::Bytes, "ip:a").await, 100);
+        assert_eq!(remaining(&limiter, LimitKind::Bytes, "session:s").await, 10);
+    }
+
+    #[tokio::test]
+    async fn allowed_request_is_charged_to_every_key() {
+        let limiter = limiter(2, 100);
+
+        let decisions = limiter.acquire_all(&keys(), &[(LimitKind::Requests, 1), (LimitKind::Bytes, 30)]).await.unwrap();
+        assert_eq!(decisions.len(), 4);
+        assert!(decisions.iter().all(|decision| decision.allowed));
+
+        limiter.acquire_all(&keys(), &[(LimitKind::Requests, 1)]).await.unwrap();
+        let denied = limiter.acquire_all(&keys(), &[(LimitKind::Requests, 1)]).await.unwrap_err();
+        assert_eq!(denied.kind, LimitKind::Requests);
+        assert_eq!(remaining(&limiter, LimitKind::Bytes, "session:s").await, 70);
+    }
+
+    #[tokio::test]
+    async fn disabled_limits_are_skipped() {
+        let limiter = limiter(0, 10);
+
+        let decisions = limiter.acquire_all(&keys(), &[(LimitKind::Requests, 1), (LimitKind::Bytes, 10)]).await.unwrap();
+        assert!(decisions.iter().all(|decision| decision.kind == LimitKind::Bytes));
+        assert_eq!(decisions.len(), 2);
+        assert!(limiter.acquire(LimitKind::Requests, "ip:a", 1).await.is_none());
+        assert!(RateLimiter::new(None, Duration::from_secs(1), 0, 0).is_none());
+    }
+}
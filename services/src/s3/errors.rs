@@ -52,7 +52,7 @@ where
                     None => "UnknownError",
                 };
 
-                if code == "NoSuchKey" || code == "NoSuchBucket" {
+                if code == "NoSuchKey" || code == "NoSuchBucket" || code == "NoSuchUpload" {
                     return S3Error::ObjectNotFound {
                         bucket: "unknown".to_string(),
                         key: "unknown".to_string()
@@ -1,73 +1,128 @@
+use std::collections::HashMap;
 use std::path::Path;
 use aws_sdk_s3::{Client, Config, config::{Credentials, Region, BehaviorVersion}};
-use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart, Delete, MetadataDirective, ObjectIdentifier};
 use aws_sdk_s3::operation::get_object::GetObjectOutput;
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
 use aws_sdk_s3::operation::put_object::PutObjectOutput;
 use bytes::Bytes;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use tokio::io::AsyncReadExt;
 use super::multipart::{MultipartUploadContext, MultipartUploadOptions};
 use super::errors::{Result, S3Error};
-use std::sync::Arc;
+use std::time::Duration;
+use aws_sdk_s3::config::retry::RetryConfig;
+use aws_sdk_s3::config::timeout::TimeoutConfig;
+use my_core::config::{Config as AppConfig, S3RetryMode};
+
+/// Наибольший объект, который можно скопировать одним CopyObject
+const MAX_COPY_OBJECT_SIZE: i64 = 5 * 1024 * 1024 * 1024;
+/// Размер части при копировании через UploadPartCopy
+const COPY_PART_SIZE: i64 = 512 * 1024 * 1024;
+/// Символы ключа, которые передаются в `x-amz-copy-source` без кодирования
+const COPY_SOURCE_SET: &AsciiSet = &NON_ALPHANUMERIC.remove(b'/').remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+
+/// Заголовок `x-amz-copy-source`: ключ должен быть URL-кодирован
+fn copy_source(bucket: &str, key: &str) -> String {
+    format!("{bucket}/{}", utf8_percent_encode(key, COPY_SOURCE_SET))
+}
+
+/// Настройки пула соединений, таймаутов и повторов клиента S3
+#[derive(Debug, Clone)]
+pub struct S3ClientOptions {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    /// Таймаут всей операции с учетом повторов
+    pub operation_timeout: Duration,
+    /// Таймаут одной попытки операции
+    pub operation_attempt_timeout: Duration,
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub retry_mode: S3RetryMode,
+}
+
+impl Default for S3ClientOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(3),
+            read_timeout: Duration::from_secs(30),
+            operation_timeout: Duration::from_secs(300),
+            operation_attempt_timeout: Duration::from_secs(120),
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            retry_mode: S3RetryMode::Standard,
+        }
+    }
+}
+
+impl S3ClientOptions {
+    /// Собирает настройки клиента из конфигурации приложения
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            connect_timeout: Duration::from_millis(config.s3_connect_timeout_ms),
+            read_timeout: Duration::from_millis(config.s3_read_timeout_ms),
+            operation_timeout: Duration::from_millis(config.s3_operation_timeout_ms),
+            operation_attempt_timeout: Duration::from_millis(config.s3_operation_attempt_timeout_ms),
+            max_attempts: config.s3_max_attempts,
+            initial_backoff: Duration::from_millis(config.s3_retry_initial_backoff_ms),
+            retry_mode: config.s3_retry_mode,
+        }
+    }
+
+    fn timeout_config(&self) -> TimeoutConfig {
+        TimeoutConfig::builder()
+            .connect_timeout(self.connect_timeout)
+            .read_timeout(self.read_timeout)
+            .operation_timeout(self.operation_timeout)
+            .operation_attempt_timeout(self.operation_attempt_timeout)
+            .build()
+    }
+
+    fn retry_config(&self) -> RetryConfig {
+        let retry_config = match self.retry_mode {
+            S3RetryMode::Standard => RetryConfig::standard(),
+            S3RetryMode::Adaptive => RetryConfig::adaptive(),
+        };
+        retry_config
+            .with_max_attempts(self.max_attempts.max(1))
+            .with_initial_backoff(self.initial_backoff)
+    }
+}
 
 /// Менеджер для взаимодействия с S3 или совместимым объектным хранилищем
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct S3Manager {
-    // client: Client,
-    client_factory: Arc<dyn Fn() -> Client + Send + Sync>,
+    // Клиент дешево клонируется и разделяет один пул HTTP-соединений
+    client: Client,
 }
 
 impl S3Manager {
-    /// Создает новый экземпляр S3Manager
-    // pub async fn new(region: String, endpoint: Option<String>, credentials: Credentials) -> Result<Self> {
-    //     let region = Region::new(region);
-    //     let mut config_builder = Config::builder()
-    //         .region(region)
-    //         .credentials_provider(credentials)
-    //         .behavior_version(BehaviorVersion::latest());
-    // 
-    //     if let Some(endpoint_url) = endpoint {
-    //         config_builder = config_builder.endpoint_url(endpoint_url);
-    //     }
-    // 
-    //     let config = config_builder.build();
-    //     let client = Client::from_conf(config);
-    // 
-    //     Ok(Self { client })
-    // }
-    // 
-    // /// Получает клиент AWS S3
-    // pub fn client(&self) -> &Client {
-    //     &self.client
-    // }
-    pub async fn new(region: String, endpoint: Option<String>, credentials: Credentials) -> Result<Self> {
-        let region = Region::new(region);
-
-        // Сохраняем параметры настройки
-        let region_clone = region.clone();
-        let endpoint_clone = endpoint.clone();
-        let credentials_clone = credentials.clone();
-
-        // Создаем фабрику клиентов вместо одного клиента
-        let client_factory = Arc::new(move || {
-            let mut config_builder = Config::builder()
-                .region(region_clone.clone())
-                .credentials_provider(credentials_clone.clone())
-                .behavior_version(BehaviorVersion::latest());
-
-            if let Some(endpoint_url) = &endpoint_clone {
-                config_builder = config_builder.endpoint_url(endpoint_url.clone());
-            }
+    /// Создает новый экземпляр S3Manager с одним общим клиентом
+    pub async fn new(
+        region: String,
+        endpoint: Option<String>,
+        credentials: Credentials,
+        options: S3ClientOptions,
+    ) -> Result<Self> {
+        let mut config_builder = Config::builder()
+            .region(Region::new(region))
+            .credentials_provider(credentials)
+            .timeout_config(options.timeout_config())
+            .retry_config(options.retry_config())
+            .behavior_version(BehaviorVersion::latest());
+
+        if let Some(endpoint_url) = endpoint {
+            config_builder = config_builder.endpoint_url(endpoint_url);
+        }
 
-            let config = config_builder.build();
-            Client::from_conf(config)
-        });
+        let client = Client::from_conf(config_builder.build());
 
-        Ok(Self { client_factory })
+        Ok(Self { client })
     }
 
-    // Получаем новый клиент для каждой операции
-    fn get_client(&self) -> Client {
-        (self.client_factory)()
+    /// Возвращает общий клиент AWS S3
+    pub(crate) fn get_client(&self) -> &Client {
+        &self.client
     }
 
 
@@ -112,7 +167,7 @@ impl S3Manager {
         let source_key = format!("{source_bucket}/{source_object}");
         let response = self.get_client()
             .copy_object()
-            .copy_source(&source_key)
+            .copy_source(copy_source(source_bucket, source_object))
             .bucket(destination_bucket)
             .key(destination_object)
             .send()
@@ -124,6 +179,106 @@ impl S3Manager {
         Ok(())
     }
 
+    /// Заменяет пользовательские метаданные объекта копированием его в себя.
+    /// Тип содержимого при замене сбрасывается, поэтому передается заново.
+    /// Объекты больше лимита CopyObject копируются по частям
+    pub async fn replace_metadata(&self, bucket: &str, key: &str, metadata: HashMap<String, String>) -> Result<()> {
+        let head = self.get_client()
+            .head_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await?;
+
+        let size = head.content_length().unwrap_or_default();
+        if size > MAX_COPY_OBJECT_SIZE {
+            return self.copy_in_parts(bucket, key, size, metadata, &head).await;
+        }
+
+        self.get_client()
+            .copy_object()
+            .copy_source(copy_source(bucket, key))
+            .bucket(bucket)
+            .key(key)
+            .metadata_directive(MetadataDirective::Replace)
+            .set_metadata(Some(metadata))
+            .set_content_type(head.content_type().map(ToString::to_string))
+            .set_content_disposition(head.content_disposition().map(ToString::to_string))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Копирует объект в себя через UploadPartCopy с новыми метаданными
+    async fn copy_in_parts(
+        &self,
+        bucket: &str,
+        key: &str,
+        size: i64,
+        metadata: HashMap<String, String>,
+        head: &HeadObjectOutput,
+    ) -> Result<()> {
+        let client = self.get_client();
+        let output = client
+            .create_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .set_metadata(Some(metadata))
+            .set_content_type(head.content_type().map(ToString::to_string))
+            .set_content_disposition(head.content_disposition().map(ToString::to_string))
+            .send()
+            .await
+            .map_err(|err| S3Error::MultipartCreateError(err.to_string()))?;
+        let upload_id = output
+            .upload_id()
+            .ok_or_else(|| S3Error::MultipartCreateError("No upload ID returned".to_string()))?;
+
+        let mut parts = Vec::new();
+        for (index, start) in (0..size).step_by(COPY_PART_SIZE as usize).enumerate() {
+            let end = (start + COPY_PART_SIZE).min(size) - 1;
+            let part_number = index as i32 + 1;
+            let result = client
+                .upload_part_copy()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .copy_source(copy_source(bucket, key))
+                .copy_source_range(format!("bytes={start}-{end}"))
+                .send()
+                .await;
+
+            match result {
+                Ok(output) => parts.push(
+                    CompletedPart::builder()
+                        .part_number(part_number)
+                        .set_e_tag(output.copy_part_result().and_then(|part| part.e_tag()).map(ToString::to_string))
+                        .build(),
+                ),
+                Err(err) => {
+                    // Недокопированная загрузка не должна остаться в бакете
+                    if let Err(abort_err) = self.abort_multipart_upload(bucket, key, upload_id).await {
+                        tracing::error!("Failed to abort copy of {}/{}: {}", bucket, key, abort_err);
+                    }
+                    return Err(S3Error::PartUploadError(err.to_string()));
+                }
+            }
+        }
+
+        client
+            .complete_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+            .send()
+            .await
+            .map_err(|err| S3Error::MultipartCompleteError(err.to_string()))?;
+
+        Ok(())
+    }
+
     /// Удаляет объект из бакета
     pub async fn delete_object(&self, bucket: &str, key: &str) -> Result<()> {
         self.get_client()
@@ -172,7 +327,66 @@ impl S3Manager {
         key: &str,
         options: Option<MultipartUploadOptions>
     ) -> Result<MultipartUploadContext> {
-        MultipartUploadContext::new(self.get_client(), bucket, key, options).await
+        MultipartUploadContext::new(self.get_client().clone(), bucket, key, options).await
+    }
+
+    /// Перечисляет незавершенные многочастные загрузки в бакете
+    pub async fn list_multipart_uploads(&self, bucket: &str) -> Result<Vec<aws_sdk_s3::types::MultipartUpload>> {
+        let mut uploads = Vec::new();
+        let mut key_marker: Option<String> = None;
+        let mut upload_id_marker: Option<String> = None;
+
+        loop {
+            let output = self.get_client()
+                .list_multipart_uploads()
+                .bucket(bucket)
+                .set_key_marker(key_marker.take())
+                .set_upload_id_marker(upload_id_marker.take())
+                .send()
+                .await?;
+
+            uploads.extend(output.uploads().iter().cloned());
+
+            if !output.is_truncated().unwrap_or(false) {
+                break;
+            }
+            key_marker = output.next_key_marker().map(ToString::to_string);
+            upload_id_marker = output.next_upload_id_marker().map(ToString::to_string);
+            if key_marker.is_none() && upload_id_marker.is_none() {
+                break;
+            }
+        }
+
+        Ok(uploads)
+    }
+
+    /// Отменяет многочастную загрузку по ее идентификатору
+    pub async fn abort_multipart_upload(&self, bucket: &str, key: &str, upload_id: &str) -> Result<()> {
+        self.get_client()
+            .abort_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Восстанавливает контекст уже начатой многочастной загрузки
+    pub async fn resume_multipart_upload_context(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<MultipartUploadContext> {
+        MultipartUploadContext::resume(self.get_client().clone(), bucket, key, upload_id).await
+    }
+
+    /// Контекст уже начатой многочастной загрузки без запроса списка частей.
+    /// Нужен только бенчмарку загрузки частей
+    #[doc(hidden)]
+    pub fn attach_multipart_upload_context(&self, bucket: &str, key: &str, upload_id: &str) -> MultipartUploadContext {
+        MultipartUploadContext::attach(self.get_client().clone(), bucket, key, upload_id)
     }
 
     /// Высокоуровневый метод для загрузки больших данных с автоматическим
@@ -274,7 +488,6 @@ impl S3Manager {
                     for object in output.contents() {
                         if let Some(key) = object.key() {
                             keys.push(key.to_string());
-                            tracing::info!(" - {}", key);
                         }
                     }
                 }
@@ -340,6 +553,12 @@ impl S3Manager {
         }
     }
 
+    /// Проверяет, что бакет существует и доступен
+    pub async fn head_bucket(&self, bucket: &str) -> Result<()> {
+        self.get_client().head_bucket().bucket(bucket).send().await?;
+        Ok(())
+    }
+
     /// Создает бакет, если он не существует
     pub async fn ensure_bucket_exists(&self, bucket: &str) -> Result<()> {
         // Проверяем существование бакета
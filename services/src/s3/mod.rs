@@ -1,10 +1,12 @@
 mod manager;
 mod multipart;
 mod errors;
+mod presign;
 mod utils;
 
 
-pub use manager::S3Manager;
+pub use manager::{S3Manager, S3ClientOptions};
 pub use multipart::{MultipartUploadContext, MultipartUploadOptions};
 pub use errors::{S3Error, Result};
+pub use presign::PresignedUrl;
 pub use utils::*;
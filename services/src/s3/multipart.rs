@@ -1,9 +1,10 @@
 use std::sync::Arc;
 use aws_sdk_s3::{Client};
-use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::types::{ChecksumAlgorithm, CompletedMultipartUpload, CompletedPart};
 use bytes::Bytes;
 use tokio::sync::Mutex;
 use super::errors::{Result, S3Error};
+use crate::storage::checksum::sha256_base64;
 
 /// Опции для мультичастной загрузки
 #[derive(Debug, Clone)]
@@ -11,6 +12,9 @@ pub struct MultipartUploadOptions {
     pub content_type: Option<String>,
     pub content_disposition: Option<String>,
     pub chunk_size: usize,
+    /// Отправлять SHA-256 каждой части, чтобы S3 проверял ее целостность.
+    /// Не подходит для подписанных ссылок: клиент не пришлет заголовок суммы
+    pub part_checksums: bool,
 }
 
 impl Default for MultipartUploadOptions {
@@ -19,6 +23,7 @@ impl Default for MultipartUploadOptions {
             content_type: None,
             content_disposition: None,
             chunk_size: 5 * 1024 * 1024, // 5MB по умолчанию
+            part_checksums: false,
         }
     }
 }
@@ -30,6 +35,7 @@ pub struct MultipartUploadContext {
     bucket: String,
     key: String,
     upload_id: String,
+    part_checksums: bool,
     parts: Arc<Mutex<Vec<CompletedPart>>>,
 }
 
@@ -56,6 +62,10 @@ impl MultipartUploadContext {
             create_req = create_req.content_disposition(disposition);
         }
 
+        if options.part_checksums {
+            create_req = create_req.checksum_algorithm(ChecksumAlgorithm::Sha256);
+        }
+
         let output = create_req
             .send()
             .await
@@ -71,18 +81,100 @@ impl MultipartUploadContext {
             bucket: bucket.to_string(),
             key: key.to_string(),
             upload_id,
+            part_checksums: options.part_checksums,
             parts: Arc::new(Mutex::new(Vec::new())),
         })
     }
 
+    /// Восстанавливает контекст уже начатой загрузки, подтягивая список
+    /// загруженных частей из S3
+    pub(crate) async fn resume(client: Client, bucket: &str, key: &str, upload_id: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut part_checksums = false;
+        let mut part_number_marker: Option<String> = None;
+
+        loop {
+            let output = client
+                .list_parts()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .set_part_number_marker(part_number_marker.take())
+                .send()
+                .await?;
+
+            // Загрузка, начатая с контрольными суммами частей, продолжается с ними же
+            part_checksums |= output.checksum_algorithm() == Some(&ChecksumAlgorithm::Sha256);
+            for part in output.parts() {
+                let (Some(part_number), Some(etag)) = (part.part_number(), part.e_tag()) else { continue };
+                parts.push(
+                    CompletedPart::builder()
+                        .e_tag(etag)
+                        .part_number(part_number)
+                        .set_checksum_sha256(part.checksum_sha256().map(ToString::to_string))
+                        .build(),
+                );
+            }
+
+            if !output.is_truncated().unwrap_or(false) {
+                break;
+            }
+            part_number_marker = output.next_part_number_marker().map(ToString::to_string);
+            if part_number_marker.is_none() {
+                break;
+            }
+        }
+
+        Ok(Self {
+            client,
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            upload_id: upload_id.to_string(),
+            part_checksums,
+            parts: Arc::new(Mutex::new(parts)),
+        })
+    }
+
+    /// Подключается к уже начатой загрузке без запросов к S3. Список частей
+    /// пуст: собрать загрузку сможет только контекст, загрузивший все части
+    pub(crate) fn attach(client: Client, bucket: &str, key: &str, upload_id: &str) -> Self {
+        Self {
+            client,
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            upload_id: upload_id.to_string(),
+            part_checksums: false,
+            parts: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Идентификатор многочастной загрузки в S3
+    pub fn upload_id(&self) -> &str {
+        &self.upload_id
+    }
+
+    /// Бакет, в который идет загрузка
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    /// Ключ собираемого объекта
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
     /// Загружает часть файла
     pub async fn upload_part(&self, part_number: i32, body: Bytes) -> Result<()> {
+        // S3 сверит сумму с полученными байтами и отклонит поврежденную часть
+        let checksum = self.part_checksums.then(|| sha256_base64(&body));
+
         let result = self.client
             .upload_part()
             .bucket(&self.bucket)
             .key(&self.key)
             .upload_id(&self.upload_id)
             .part_number(part_number)
+            .set_checksum_sha256(checksum.clone())
             .body(body.into())
             .send()
             .await
@@ -95,9 +187,12 @@ impl MultipartUploadContext {
         let part = CompletedPart::builder()
             .e_tag(etag)
             .part_number(part_number)
+            .set_checksum_sha256(checksum)
             .build();
 
+        // Повторная загрузка части заменяет предыдущую с тем же номером
         let mut parts = self.parts.lock().await;
+        parts.retain(|existing| existing.part_number() != Some(part_number));
         parts.push(part);
 
         Ok(())
@@ -109,7 +204,28 @@ impl MultipartUploadContext {
             let parts = self.parts.lock().await;
             parts.clone()
         };
+        self.complete_with(parts).await
+    }
+
+    /// Завершает загрузку из частей `1..=last_part`; остальные части,
+    /// в том числе подтянутые `resume`, в объект не попадают
+    pub async fn complete_parts(&self, last_part: i32) -> Result<()> {
+        let parts: Vec<_> = {
+            let parts = self.parts.lock().await;
+            parts.iter()
+                .filter(|part| part.part_number().is_some_and(|number| (1..=last_part).contains(&number)))
+                .cloned()
+                .collect()
+        };
+        if parts.len() != last_part.max(0) as usize {
+            return Err(S3Error::MultipartCompleteError(format!(
+                "Expected parts 1..={last_part}, found {} of them", parts.len()
+            )));
+        }
+        self.complete_with(parts).await
+    }
 
+    async fn complete_with(&self, parts: Vec<CompletedPart>) -> Result<()> {
         // Сортируем части по номеру для корректной сборки файла
         let mut sorted_parts = parts;
         sorted_parts.sort_by_key(|part| part.part_number());
@@ -133,16 +249,20 @@ impl MultipartUploadContext {
 
     /// Отменяет мультичастную загрузку, если что-то пошло не так
     pub async fn abort(&self) -> Result<()> {
-        self.client
+        let result = self.client
             .abort_multipart_upload()
             .bucket(&self.bucket)
             .key(&self.key)
             .upload_id(&self.upload_id)
             .send()
-            .await
-            .map_err(|err| S3Error::Other(format!("Failed to abort multipart upload: {}", err)))?;
+            .await;
 
-        Ok(())
+        match result {
+            Ok(_) => Ok(()),
+            // Уже отмененная загрузка: отмена идемпотентна, как в других бэкендах
+            Err(err) if err.as_service_error().is_some_and(|err| err.is_no_such_upload()) => Ok(()),
+            Err(err) => Err(S3Error::Other(format!("Failed to abort multipart upload: {}", err))),
+        }
     }
 }
 
@@ -449,6 +569,7 @@ impl Clone for MultipartUploadContext {
             bucket: self.bucket.clone(),
             key: self.key.clone(),
             upload_id: self.upload_id.clone(),
+            part_checksums: self.part_checksums,
             parts: self.parts.clone(),
         }
     }
@@ -0,0 +1,107 @@
+use std::time::Duration;
+use aws_sdk_s3::presigning::{PresignedRequest, PresigningConfig};
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use super::errors::{Result, S3Error};
+use super::manager::S3Manager;
+
+
+/// Подписанный запрос, который клиент выполняет напрямую в S3
+#[derive(Debug, Clone)]
+pub struct PresignedUrl {
+    pub url: String,
+    pub method: String,
+    /// Заголовки, вошедшие в подпись: клиент обязан отправить их без изменений
+    pub headers: Vec<(String, String)>,
+}
+
+impl From<PresignedRequest> for PresignedUrl {
+    fn from(request: PresignedRequest) -> Self {
+        Self {
+            url: request.uri().to_string(),
+            method: request.method().to_string(),
+            headers: request
+                .headers()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+}
+
+fn presigning_config(expires_in: Duration) -> Result<PresigningConfig> {
+    PresigningConfig::expires_in(expires_in)
+        .map_err(|err| S3Error::Other(format!("Invalid presigned URL lifetime: {}", err)))
+}
+
+impl S3Manager {
+    /// Подписывает PUT одного объекта целиком. Размер входит в подпись:
+    /// S3 отклонит тело другой длины
+    pub async fn presign_put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        content_type: Option<&str>,
+        content_length: u64,
+        expires_in: Duration,
+    ) -> Result<PresignedUrl> {
+        let request = self.get_client()
+            .put_object()
+            .bucket(bucket)
+            .key(key)
+            .set_content_type(content_type.map(ToString::to_string))
+            .content_length(content_length as i64)
+            .presigned(presigning_config(expires_in)?)
+            .await?;
+
+        Ok(request.into())
+    }
+
+    /// Подписывает загрузку одной части многочастной загрузки; размер части
+    /// входит в подпись, как и в `presign_put_object`
+    pub async fn presign_upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        content_length: u64,
+        expires_in: Duration,
+    ) -> Result<PresignedUrl> {
+        let request = self.get_client()
+            .upload_part()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .content_length(content_length as i64)
+            .presigned(presigning_config(expires_in)?)
+            .await?;
+
+        Ok(request.into())
+    }
+
+    /// Завершает многочастную загрузку по ETag частей, полученным от клиента
+    pub async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[(i32, String)],
+    ) -> Result<()> {
+        let mut parts: Vec<CompletedPart> = parts
+            .iter()
+            .map(|(part_number, e_tag)| CompletedPart::builder().part_number(*part_number).e_tag(e_tag).build())
+            .collect();
+        parts.sort_by_key(|part| part.part_number());
+
+        self.get_client()
+            .complete_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
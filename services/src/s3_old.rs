@@ -37,7 +37,7 @@ impl S3Manager {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```ignore
     /// use aws_sdk_s3::config::Credentials;
     ///
     /// #[tokio::main]
@@ -408,7 +408,7 @@ impl S3Manager {
     ///
     /// Result с MultipartUploadContext или ошибкой.
     /// # Example
-    /// ```
+    /// ```ignore
     ///     #[tokio::main]
     ///     async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ///         let s3_manager = S3Manager::new(/* ... */).await?;
@@ -511,7 +511,7 @@ impl S3Manager {
 /// Represents the context for a multipart upload operation.
 /// Example of how to use MultipartUploadContext
 ///
-/// ```
+/// ```ignore
 /// use bytes::Bytes;
 ///
 /// #[tokio::main]
@@ -0,0 +1,43 @@
+use thiserror::Error;
+use super::TrackRole;
+
+
+/// Результат операций с реестром сессий
+pub type Result<T> = std::result::Result<T, SessionError>;
+
+/// Ошибки реестра сессий загрузки
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("Session not found: {0}")]
+    SessionNotFound(String),
+
+    #[error("Session already exists: {0}")]
+    SessionAlreadyExists(String),
+
+    #[error("Track not found: session={session_id}, track={track_id}")]
+    TrackNotFound { session_id: String, track_id: String },
+
+    #[error("Track already exists: session={session_id}, track={track_id}")]
+    TrackAlreadyExists { session_id: String, track_id: String },
+
+    #[error("Track {track_id} already has an uploaded {} file", role.as_str())]
+    SlotOccupied { track_id: String, role: TrackRole },
+
+    #[error("Upload into the {} slot of track {track_id} is already in progress", role.as_str())]
+    SlotBusy { track_id: String, role: TrackRole },
+
+    #[error("Track {track_id} is finalized")]
+    TrackFinalized { track_id: String },
+
+    #[error("Track {track_id} cannot be finalized: {reason}")]
+    TrackNotReady { track_id: String, reason: &'static str },
+
+    #[error("Track {track_id} is being changed concurrently")]
+    Contended { track_id: String },
+
+    #[error("Redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::sync::Mutex;
+use super::{Session, SessionBackend, SessionRegistry, Track, TrackRole, UploadRecord};
+use super::errors::{Result, SessionError};
+
+
+/// Реестр сессий в памяти процесса (для тестов и локальной разработки)
+#[derive(Clone)]
+pub struct MemorySessionRegistry {
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+    ttl: Duration,
+}
+
+impl MemorySessionRegistry {
+    pub fn new(ttl: Duration) -> Self {
+        Self { sessions: Arc::default(), ttl }
+    }
+
+    /// Блокирует реестр, предварительно удалив истекшие сессии
+    async fn lock(&self) -> tokio::sync::MutexGuard<'_, HashMap<String, Session>> {
+        let mut sessions = self.sessions.lock().await;
+        let now = Utc::now();
+        sessions.retain(|_, session| session.expires_at > now);
+        sessions
+    }
+}
+
+fn track_mut<'a>(session: &'a mut Session, track_id: &str) -> Result<&'a mut Track> {
+    let session_id = session.id.clone();
+    session.tracks
+        .iter_mut()
+        .find(|track| track.id == track_id)
+        .ok_or_else(|| SessionError::TrackNotFound { session_id, track_id: track_id.to_string() })
+}
+
+#[async_trait]
+impl SessionRegistry for MemorySessionRegistry {
+    fn backend(&self) -> SessionBackend {
+        SessionBackend::Memory
+    }
+
+    async fn create_session(&self, session_id: &str) -> Result<Session> {
+        let mut sessions = self.lock().await;
+        if sessions.contains_key(session_id) {
+            return Err(SessionError::SessionAlreadyExists(session_id.to_string()));
+        }
+
+        let now = Utc::now();
+        let session = Session {
+            id: session_id.to_string(),
+            created_at: now,
+            expires_at: now + self.ttl,
+            tracks: Vec::new(),
+        };
+        sessions.insert(session_id.to_string(), session.clone());
+        Ok(session)
+    }
+
+    async fn get_session(&self, session_id: &str) -> Result<Session> {
+        self.lock().await
+            .get(session_id)
+            .cloned()
+            .ok_or_else(|| SessionError::SessionNotFound(session_id.to_string()))
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<()> {
+        self.lock().await
+            .remove(session_id)
+            .map(|_| ())
+            .ok_or_else(|| SessionError::SessionNotFound(session_id.to_string()))
+    }
+
+    async fn add_track(&self, session_id: &str, track_id: &str) -> Result<Track> {
+        let mut sessions = self.lock().await;
+        let session = sessions.get_mut(session_id)
+            .ok_or_else(|| SessionError::SessionNotFound(session_id.to_string()))?;
+        if session.tracks.iter().any(|track| track.id == track_id) {
+            return Err(SessionError::TrackAlreadyExists {
+                session_id: session_id.to_string(),
+                track_id: track_id.to_string(),
+            });
+        }
+
+        let track = Track::new(track_id);
+        session.tracks.push(track.clone());
+        Ok(track)
+    }
+
+    async fn get_track(&self, session_id: &str, track_id: &str) -> Result<Track> {
+        let mut sessions = self.lock().await;
+        let session = sessions.get_mut(session_id)
+            .ok_or_else(|| SessionError::SessionNotFound(session_id.to_string()))?;
+        track_mut(session, track_id).map(|track| track.clone())
+    }
+
+    async fn finalize_track(&self, session_id: &str, track_id: &str) -> Result<Track> {
+        let mut sessions = self.lock().await;
+        let session = sessions.get_mut(session_id)
+            .ok_or_else(|| SessionError::SessionNotFound(session_id.to_string()))?;
+        let track = track_mut(session, track_id)?;
+        track.finalize()?;
+        Ok(track.clone())
+    }
+
+    async fn claim_upload(
+        &self,
+        session_id: &str,
+        track_id: &str,
+        role: TrackRole,
+        record: UploadRecord,
+    ) -> Result<Track> {
+        let mut sessions = self.lock().await;
+        let session = sessions.get_mut(session_id)
+            .ok_or_else(|| SessionError::SessionNotFound(session_id.to_string()))?;
+        let track = track_mut(session, track_id)?;
+        track.claim(role, record)?;
+        Ok(track.clone())
+    }
+
+    async fn record_upload(
+        &self,
+        session_id: &str,
+        track_id: &str,
+        role: TrackRole,
+        record: UploadRecord,
+    ) -> Result<Track> {
+        let mut sessions = self.lock().await;
+        let session = sessions.get_mut(session_id)
+            .ok_or_else(|| SessionError::SessionNotFound(session_id.to_string()))?;
+        let track = track_mut(session, track_id)?;
+        track.record(role, record)?;
+        Ok(track.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::UploadStatus;
+
+    fn record(key: &str, status: UploadStatus) -> UploadRecord {
+        UploadRecord { key: key.to_string(), size: 1, status, updated_at: Utc::now() }
+    }
+
+    async fn registry_with_track() -> Arc<MemorySessionRegistry> {
+        let registry = Arc::new(MemorySessionRegistry::new(Duration::from_secs(60)));
+        registry.create_session("s").await.unwrap();
+        registry.add_track("s", "t").await.unwrap();
+        registry
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn only_one_concurrent_claim_wins_the_slot() {
+        let registry = registry_with_track().await;
+
+        let claims = (0..16).map(|index| {
+            let registry = Arc::clone(&registry);
+            tokio::spawn(async move {
+                registry.claim_upload("s", "t", TrackRole::Vocal, record(&format!("key-{index}"), UploadStatus::Uploading)).await
+            })
+        });
+        let results = futures::future::join_all(claims).await;
+
+        let won = results.iter().filter(|result| matches!(result, Ok(Ok(_)))).count();
+        assert_eq!(won, 1);
+        assert!(results.iter().all(|result| matches!(result, Ok(Ok(_) | Err(SessionError::SlotBusy { .. })))));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn finalize_and_claim_race_never_both_succeed() {
+        for _ in 0..32 {
+            let registry = registry_with_track().await;
+            registry.record_upload("s", "t", TrackRole::Vocal, record("vocal", UploadStatus::Uploaded)).await.unwrap();
+
+            let finalize = tokio::spawn({
+                let registry = Arc::clone(&registry);
+                async move { registry.finalize_track("s", "t").await }
+            });
+            let claim = tokio::spawn({
+                let registry = Arc::clone(&registry);
+                async move { registry.claim_upload("s", "t", TrackRole::Instrumental, record("inst", UploadStatus::Uploading)).await }
+            });
+
+            match (finalize.await.unwrap(), claim.await.unwrap()) {
+                (Ok(_), Err(SessionError::TrackFinalized { .. })) => {}
+                (Err(SessionError::TrackNotReady { .. }), Ok(_)) => {}
+                results => panic!("Unexpected outcome: {results:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn finalized_track_refuses_uploads() {
+        let registry = registry_with_track().await;
+        assert!(matches!(registry.finalize_track("s", "t").await, Err(SessionError::TrackNotReady { .. })));
+
+        registry.claim_upload("s", "t", TrackRole::Vocal, record("vocal", UploadStatus::Uploading)).await.unwrap();
+        assert!(matches!(registry.finalize_track("s", "t").await, Err(SessionError::TrackNotReady { .. })));
+
+        registry.record_upload("s", "t", TrackRole::Vocal, record("vocal", UploadStatus::Uploaded)).await.unwrap();
+        let track = registry.finalize_track("s", "t").await.unwrap();
+        assert!(track.finalized_at.is_some());
+        // Повторная финализация ничего не меняет
+        assert_eq!(registry.finalize_track("s", "t").await.unwrap().finalized_at, track.finalized_at);
+
+        let err = registry.claim_upload("s", "t", TrackRole::Instrumental, record("inst", UploadStatus::Uploading)).await;
+        assert!(matches!(err, Err(SessionError::TrackFinalized { .. })));
+        let err = registry.record_upload("s", "t", TrackRole::Vocal, record("vocal", UploadStatus::Failed)).await;
+        assert!(matches!(err, Err(SessionError::TrackFinalized { .. })));
+    }
+
+    #[tokio::test]
+    async fn slot_is_replaced_only_after_failure() {
+        let registry = registry_with_track().await;
+
+        registry.claim_upload("s", "t", TrackRole::Vocal, record("first", UploadStatus::Uploading)).await.unwrap();
+        let err = registry.record_upload("s", "t", TrackRole::Vocal, record("other", UploadStatus::Uploaded)).await;
+        assert!(matches!(err, Err(SessionError::SlotBusy { .. })));
+
+        registry.record_upload("s", "t", TrackRole::Vocal, record("first", UploadStatus::Failed)).await.unwrap();
+        registry.claim_upload("s", "t", TrackRole::Vocal, record("second", UploadStatus::Uploading)).await.unwrap();
+        registry.record_upload("s", "t", TrackRole::Vocal, record("second", UploadStatus::Uploaded)).await.unwrap();
+
+        let err = registry.claim_upload("s", "t", TrackRole::Vocal, record("third", UploadStatus::Uploading)).await;
+        assert!(matches!(err, Err(SessionError::SlotOccupied { .. })));
+        let track = registry.get_track("s", "t").await.unwrap();
+        assert_eq!(track.slot(TrackRole::Vocal).unwrap().key, "second");
+    }
+
+    #[tokio::test]
+    async fn expired_sessions_disappear() {
+        let registry = MemorySessionRegistry::new(Duration::ZERO);
+        registry.create_session("s").await.unwrap();
+        assert!(matches!(registry.get_session("s").await, Err(SessionError::SessionNotFound(_))));
+        assert!(matches!(registry.add_track("s", "t").await, Err(SessionError::SessionNotFound(_))));
+    }
+}
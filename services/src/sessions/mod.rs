@@ -0,0 +1,228 @@
+mod errors;
+mod memory;
+mod redis;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub use errors::{SessionError, Result};
+pub use memory::MemorySessionRegistry;
+pub use self::redis::RedisSessionRegistry;
+pub use my_core::config::SessionBackend;
+
+
+/// Роль файла в треке
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackRole {
+    Vocal,
+    Instrumental,
+}
+
+impl TrackRole {
+    pub const ALL: [TrackRole; 2] = [TrackRole::Vocal, TrackRole::Instrumental];
+
+    /// Роль по имени поля формы
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "vocal" => Some(Self::Vocal),
+            "instrumental" => Some(Self::Instrumental),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Vocal => "vocal",
+            Self::Instrumental => "instrumental",
+        }
+    }
+}
+
+/// Состояние загрузки файла в слот трека
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadStatus {
+    Uploading,
+    Uploaded,
+    Failed,
+}
+
+/// Состояние слота или трека целиком
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackStatus {
+    /// Файл еще не загружался
+    Pending,
+    Uploading,
+    Uploaded,
+    Failed,
+}
+
+impl From<Option<&UploadRecord>> for TrackStatus {
+    fn from(record: Option<&UploadRecord>) -> Self {
+        match record.map(|record| record.status) {
+            None => Self::Pending,
+            Some(UploadStatus::Uploading) => Self::Uploading,
+            Some(UploadStatus::Uploaded) => Self::Uploaded,
+            Some(UploadStatus::Failed) => Self::Failed,
+        }
+    }
+}
+
+/// Загрузка, записанная в слот трека
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadRecord {
+    /// Ключ объекта в хранилище
+    pub key: String,
+    pub size: u64,
+    pub status: UploadStatus,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Трек сессии со слотами вокала и минуса
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Track {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub vocal: Option<UploadRecord>,
+    pub instrumental: Option<UploadRecord>,
+    /// Время финализации; финализированный трек закрыт для загрузок
+    pub finalized_at: Option<DateTime<Utc>>,
+}
+
+impl Track {
+    fn new(id: &str) -> Self {
+        Self { id: id.to_string(), created_at: Utc::now(), vocal: None, instrumental: None, finalized_at: None }
+    }
+
+    /// Состояние трека: загрузка в любой слот важнее ошибки, ошибка важнее
+    /// пустого слота; `Uploaded` - только когда загружены оба файла
+    pub fn status(&self) -> TrackStatus {
+        let statuses = TrackRole::ALL.map(|role| TrackStatus::from(self.slot(role)));
+        [TrackStatus::Uploading, TrackStatus::Failed, TrackStatus::Pending]
+            .into_iter()
+            .find(|status| statuses.contains(status))
+            .unwrap_or(TrackStatus::Uploaded)
+    }
+
+    pub fn slot(&self, role: TrackRole) -> Option<&UploadRecord> {
+        match role {
+            TrackRole::Vocal => self.vocal.as_ref(),
+            TrackRole::Instrumental => self.instrumental.as_ref(),
+        }
+    }
+
+    fn slot_mut(&mut self, role: TrackRole) -> &mut Option<UploadRecord> {
+        match role {
+            TrackRole::Vocal => &mut self.vocal,
+            TrackRole::Instrumental => &mut self.instrumental,
+        }
+    }
+
+    /// Записывает загрузку в слот. Завершенную загрузку заменить нельзя,
+    /// кроме повторной записи того же ключа; идущую - тоже, если это чужой ключ
+    fn record(&mut self, role: TrackRole, record: UploadRecord) -> Result<()> {
+        if self.finalized_at.is_some() {
+            return Err(SessionError::TrackFinalized { track_id: self.id.clone() });
+        }
+        if let Some(existing) = self.slot(role) {
+            if existing.key != record.key {
+                match existing.status {
+                    UploadStatus::Uploaded => return Err(SessionError::SlotOccupied { track_id: self.id.clone(), role }),
+                    UploadStatus::Uploading => return Err(SessionError::SlotBusy { track_id: self.id.clone(), role }),
+                    UploadStatus::Failed => {}
+                }
+            }
+        }
+        *self.slot_mut(role) = Some(record);
+        Ok(())
+    }
+
+    /// Занимает слот под новую загрузку: пока она идет, вторую в тот же слот
+    /// не начать, даже с тем же ключом
+    fn claim(&mut self, role: TrackRole, record: UploadRecord) -> Result<()> {
+        if self.slot(role).is_some_and(|existing| existing.status == UploadStatus::Uploading) {
+            return Err(SessionError::SlotBusy { track_id: self.id.clone(), role });
+        }
+        self.record(role, UploadRecord { status: UploadStatus::Uploading, ..record })
+    }
+
+    /// Закрывает трек для загрузок. Нужен хотя бы один загруженный файл и ни
+    /// одной незавершенной загрузки; повторная финализация ничего не меняет
+    fn finalize(&mut self) -> Result<()> {
+        if self.finalized_at.is_some() {
+            return Ok(());
+        }
+
+        let statuses = TrackRole::ALL.map(|role| TrackStatus::from(self.slot(role)));
+        let reason = if statuses.contains(&TrackStatus::Uploading) {
+            Some("Upload is still in progress")
+        } else if !statuses.contains(&TrackStatus::Uploaded) {
+            Some("No uploaded files")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            return Err(SessionError::TrackNotReady { track_id: self.id.clone(), reason });
+        }
+
+        self.finalized_at = Some(Utc::now());
+        Ok(())
+    }
+}
+
+/// Сессия загрузки; удаляется вместе с треками по истечении `expires_at`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Треки в порядке создания
+    pub tracks: Vec<Track>,
+}
+
+/// Реестр сессий загрузки (Redis или память процесса)
+#[async_trait]
+pub trait SessionRegistry: Send + Sync {
+    /// Тип бэкенда
+    fn backend(&self) -> SessionBackend;
+
+    /// Создает пустую сессию, живущую заданное при создании реестра время
+    async fn create_session(&self, session_id: &str) -> Result<Session>;
+
+    /// Возвращает сессию со всеми треками
+    async fn get_session(&self, session_id: &str) -> Result<Session>;
+
+    /// Удаляет сессию и ее треки
+    async fn delete_session(&self, session_id: &str) -> Result<()>;
+
+    /// Добавляет в сессию трек с пустыми слотами
+    async fn add_track(&self, session_id: &str, track_id: &str) -> Result<Track>;
+
+    async fn get_track(&self, session_id: &str, track_id: &str) -> Result<Track>;
+
+    /// Закрывает трек для дальнейших загрузок
+    async fn finalize_track(&self, session_id: &str, track_id: &str) -> Result<Track>;
+
+    /// Занимает слот трека под загрузку `record` (состояние `Uploading`);
+    /// слот, в который уже идет загрузка, занять нельзя
+    async fn claim_upload(
+        &self,
+        session_id: &str,
+        track_id: &str,
+        role: TrackRole,
+        record: UploadRecord,
+    ) -> Result<Track>;
+
+    /// Записывает ключ, размер и состояние загрузки в слот трека;
+    /// в финализированный трек записать нельзя
+    async fn record_upload(
+        &self,
+        session_id: &str,
+        track_id: &str,
+        role: TrackRole,
+        record: UploadRecord,
+    ) -> Result<Track>;
+}
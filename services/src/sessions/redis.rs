@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Script};
+use serde::{Deserialize, Serialize};
+use super::{Session, SessionBackend, SessionRegistry, Track, TrackRole, UploadRecord};
+use super::errors::{Result, SessionError};
+
+
+/// Префикс ключей реестра: `{prefix}:{session_id}` - сессия,
+/// `{prefix}:{session_id}:tracks` - хеш треков
+const KEY_PREFIX: &str = "upload:session";
+/// Сколько раз повторять изменение трека, если его одновременно изменил другой запрос
+const UPDATE_ATTEMPTS: usize = 16;
+
+/// Записывает поле хеша, только если сверяемые поля не изменились с момента
+/// чтения (отсутствующее поле передается пустой строкой), и продлевает хеш.
+/// KEYS[1] - хеш треков; ARGV: EXPIREAT, число сверяемых полей n,
+/// n пар (поле, прежнее значение), записываемое поле и его значение
+const COMPARE_AND_SET: &str = r#"
+local n = tonumber(ARGV[2])
+for i = 0, n - 1 do
+    local current = redis.call('HGET', KEYS[1], ARGV[3 + 2 * i]) or ''
+    if current ~= ARGV[4 + 2 * i] then
+        return 0
+    end
+end
+redis.call('HSET', KEYS[1], ARGV[3 + 2 * n], ARGV[4 + 2 * n])
+redis.call('EXPIREAT', KEYS[1], ARGV[1])
+return 1
+"#;
+
+/// Сессия без треков, как она хранится в Redis
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionInfo {
+    id: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+/// Трек без слотов; слоты хранятся в отдельных полях хеша `{track_id}:{role}`,
+/// чтобы загрузки вокала и минуса не перезаписывали друг друга
+#[derive(Debug, Serialize, Deserialize)]
+struct TrackInfo {
+    id: String,
+    created_at: DateTime<Utc>,
+    #[serde(default)]
+    finalized_at: Option<DateTime<Utc>>,
+}
+
+impl TrackInfo {
+    fn into_track(self) -> Track {
+        Track {
+            id: self.id,
+            created_at: self.created_at,
+            vocal: None,
+            instrumental: None,
+            finalized_at: self.finalized_at,
+        }
+    }
+}
+
+/// Реестр сессий в Redis; сессия и ее треки истекают одновременно
+#[derive(Clone)]
+pub struct RedisSessionRegistry {
+    redis: ConnectionManager,
+    ttl: Duration,
+    compare_and_set: Arc<Script>,
+}
+
+impl RedisSessionRegistry {
+    pub fn new(redis: ConnectionManager, ttl: Duration) -> Self {
+        Self { redis, ttl, compare_and_set: Arc::new(Script::new(COMPARE_AND_SET)) }
+    }
+
+    fn session_key(session_id: &str) -> String {
+        format!("{KEY_PREFIX}:{session_id}")
+    }
+
+    fn tracks_key(session_id: &str) -> String {
+        format!("{KEY_PREFIX}:{session_id}:tracks")
+    }
+
+    fn slot_field(track_id: &str, role: TrackRole) -> String {
+        format!("{track_id}:{}", role.as_str())
+    }
+
+    async fn session_info(&self, session_id: &str) -> Result<SessionInfo> {
+        let mut redis = self.redis.clone();
+        let info: Option<String> = redis.get(Self::session_key(session_id)).await?;
+        let info = info.ok_or_else(|| SessionError::SessionNotFound(session_id.to_string()))?;
+        Ok(serde_json::from_str(&info)?)
+    }
+
+    /// Добавляет новое поле хеша треков и продлевает хеш до конца жизни сессии;
+    /// `false`, если поле уже есть
+    async fn add_field(&self, info: &SessionInfo, field: &str, value: &str) -> Result<bool> {
+        let mut redis = self.redis.clone();
+        let key = Self::tracks_key(&info.id);
+
+        let (written,): (bool,) = redis::pipe()
+            .cmd("HSETNX").arg(&key).arg(field).arg(value)
+            .cmd("EXPIREAT").arg(&key).arg(info.expires_at.timestamp()).ignore()
+            .query_async(&mut redis)
+            .await?;
+        Ok(written)
+    }
+
+    /// Поля хеша, из которых собирается трек: сам трек и два слота
+    fn track_fields(track_id: &str) -> [String; 3] {
+        [
+            track_id.to_string(),
+            Self::slot_field(track_id, TrackRole::Vocal),
+            Self::slot_field(track_id, TrackRole::Instrumental),
+        ]
+    }
+
+    /// Трек и значения его полей как они есть в Redis
+    async fn read_raw_track(&self, session_id: &str, track_id: &str) -> Result<(Track, [Option<String>; 3])> {
+        let mut redis = self.redis.clone();
+        let values: Vec<Option<String>> = redis.hget(Self::tracks_key(session_id), &Self::track_fields(track_id)).await?;
+        let values = <[Option<String>; 3]>::try_from(values).unwrap_or_default();
+
+        let [Some(info), vocal, instrumental] = &values else {
+            return Err(SessionError::TrackNotFound {
+                session_id: session_id.to_string(),
+                track_id: track_id.to_string(),
+            });
+        };
+        let info: TrackInfo = serde_json::from_str(info)?;
+
+        let track = Track {
+            vocal: vocal.as_deref().map(serde_json::from_str).transpose()?,
+            instrumental: instrumental.as_deref().map(serde_json::from_str).transpose()?,
+            ..info.into_track()
+        };
+        Ok((track, values))
+    }
+
+    async fn read_track(&self, session_id: &str, track_id: &str) -> Result<Track> {
+        self.read_raw_track(session_id, track_id).await.map(|(track, _)| track)
+    }
+
+    /// Читает трек, меняет его через `update` и записывает измененное поле,
+    /// только если трек не изменился с момента чтения; иначе повторяет заново.
+    /// `update` возвращает имя поля и новое значение
+    async fn update_track<F>(&self, session_id: &str, track_id: &str, update: F) -> Result<Track>
+    where
+        F: Fn(&mut Track) -> Result<(String, String)> + Send + Sync,
+    {
+        let info = self.session_info(session_id).await?;
+        let key = Self::tracks_key(session_id);
+        let fields = Self::track_fields(track_id);
+
+        for _ in 0..UPDATE_ATTEMPTS {
+            let (mut track, values) = self.read_raw_track(session_id, track_id).await?;
+            let (field, value) = update(&mut track)?;
+
+            let mut invocation = self.compare_and_set.key(&key);
+            invocation.arg(info.expires_at.timestamp()).arg(fields.len());
+            for (field, value) in fields.iter().zip(&values) {
+                invocation.arg(field).arg(value.as_deref().unwrap_or_default());
+            }
+            invocation.arg(field).arg(value);
+
+            let mut redis = self.redis.clone();
+            let written: bool = invocation.invoke_async(&mut redis).await?;
+            if written {
+                return Ok(track);
+            }
+        }
+
+        tracing::warn!("Gave up updating track {} of session {} after {} attempts", track_id, session_id, UPDATE_ATTEMPTS);
+        Err(SessionError::Contended { track_id: track_id.to_string() })
+    }
+}
+
+#[async_trait]
+impl SessionRegistry for RedisSessionRegistry {
+    fn backend(&self) -> SessionBackend {
+        SessionBackend::Redis
+    }
+
+    async fn create_session(&self, session_id: &str) -> Result<Session> {
+        let mut redis = self.redis.clone();
+        let now = Utc::now();
+        let info = SessionInfo {
+            id: session_id.to_string(),
+            created_at: now,
+            expires_at: now + self.ttl,
+        };
+
+        // SET NX атомарно отличает новую сессию от существующей
+        let created: Option<String> = redis::cmd("SET")
+            .arg(Self::session_key(session_id))
+            .arg(serde_json::to_string(&info)?)
+            .arg("NX")
+            .arg("EX")
+            .arg(self.ttl.as_secs().max(1))
+            .query_async(&mut redis)
+            .await?;
+        if created.is_none() {
+            return Err(SessionError::SessionAlreadyExists(session_id.to_string()));
+        }
+
+        Ok(Session {
+            id: info.id,
+            created_at: info.created_at,
+            expires_at: info.expires_at,
+            tracks: Vec::new(),
+        })
+    }
+
+    async fn get_session(&self, session_id: &str) -> Result<Session> {
+        let info = self.session_info(session_id).await?;
+        let mut redis = self.redis.clone();
+        let fields: HashMap<String, String> = redis.hgetall(Self::tracks_key(session_id)).await?;
+
+        let mut tracks = Vec::new();
+        let mut slots = Vec::new();
+        for (field, value) in &fields {
+            match field.rsplit_once(':') {
+                Some((track_id, role)) => {
+                    if let Some(role) = TrackRole::from_name(role) {
+                        slots.push((track_id, role, serde_json::from_str::<UploadRecord>(value)?));
+                    }
+                }
+                None => tracks.push(serde_json::from_str::<TrackInfo>(value)?.into_track()),
+            }
+        }
+        for (track_id, role, record) in slots {
+            if let Some(track) = tracks.iter_mut().find(|track| track.id == track_id) {
+                *track.slot_mut(role) = Some(record);
+            }
+        }
+        tracks.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+
+        Ok(Session {
+            id: info.id,
+            created_at: info.created_at,
+            expires_at: info.expires_at,
+            tracks,
+        })
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<()> {
+        let mut redis = self.redis.clone();
+        let (deleted,): (u32,) = redis::pipe()
+            .del(Self::session_key(session_id))
+            .del(Self::tracks_key(session_id)).ignore()
+            .query_async(&mut redis)
+            .await?;
+        if deleted == 0 {
+            return Err(SessionError::SessionNotFound(session_id.to_string()));
+        }
+        Ok(())
+    }
+
+    async fn add_track(&self, session_id: &str, track_id: &str) -> Result<Track> {
+        let info = self.session_info(session_id).await?;
+        let track = Track::new(track_id);
+        let track_info = TrackInfo { id: track.id.clone(), created_at: track.created_at, finalized_at: None };
+
+        if !self.add_field(&info, track_id, &serde_json::to_string(&track_info)?).await? {
+            return Err(SessionError::TrackAlreadyExists {
+                session_id: session_id.to_string(),
+                track_id: track_id.to_string(),
+            });
+        }
+        Ok(track)
+    }
+
+    async fn get_track(&self, session_id: &str, track_id: &str) -> Result<Track> {
+        self.session_info(session_id).await?;
+        self.read_track(session_id, track_id).await
+    }
+
+    async fn finalize_track(&self, session_id: &str, track_id: &str) -> Result<Track> {
+        self.update_track(session_id, track_id, |track| {
+            track.finalize()?;
+            let track_info = TrackInfo { id: track.id.clone(), created_at: track.created_at, finalized_at: track.finalized_at };
+            Ok((track_id.to_string(), serde_json::to_string(&track_info)?))
+        }).await
+    }
+
+    async fn claim_upload(
+        &self,
+        session_id: &str,
+        track_id: &str,
+        role: TrackRole,
+        record: UploadRecord,
+    ) -> Result<Track> {
+        self.update_track(session_id, track_id, |track| {
+            track.claim(role, record.clone())?;
+            Ok((Self::slot_field(track_id, role), serde_json::to_string(&track.slot(role))?))
+        }).await
+    }
+
+    async fn record_upload(
+        &self,
+        session_id: &str,
+        track_id: &str,
+        role: TrackRole,
+        record: UploadRecord,
+    ) -> Result<Track> {
+        self.update_track(session_id, track_id, |track| {
+            let value = serde_json::to_string(&record)?;
+            track.record(role, record.clone())?;
+            Ok((Self::slot_field(track_id, role), value))
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::UploadStatus;
+
+    /// Тесты идут против Redis (или совместимой заглушки) из `REDIS_TEST_URL`
+    /// и пропускаются, если переменная не задана
+    async fn registry_with_track() -> Option<(Arc<RedisSessionRegistry>, String)> {
+        let Ok(url) = std::env::var("REDIS_TEST_URL") else {
+            eprintln!("REDIS_TEST_URL is not set, skipping");
+            return None;
+        };
+        let client = redis::Client::open(url).unwrap();
+        let redis = ConnectionManager::new(client).await.unwrap();
+        let registry = Arc::new(RedisSessionRegistry::new(redis, Duration::from_secs(60)));
+
+        let session_id = ulid::Ulid::new().to_string();
+        registry.create_session(&session_id).await.unwrap();
+        registry.add_track(&session_id, "t").await.unwrap();
+        Some((registry, session_id))
+    }
+
+    fn record(key: &str, status: UploadStatus) -> UploadRecord {
+        UploadRecord { key: key.to_string(), size: 1, status, updated_at: Utc::now() }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn only_one_concurrent_claim_wins_the_slot() {
+        let Some((registry, session_id)) = registry_with_track().await else { return };
+
+        let claims = (0..16).map(|index| {
+            let registry = Arc::clone(&registry);
+            let session_id = session_id.clone();
+            tokio::spawn(async move {
+                registry.claim_upload(&session_id, "t", TrackRole::Vocal, record(&format!("key-{index}"), UploadStatus::Uploading)).await
+            })
+        });
+        let results = futures::future::join_all(claims).await;
+
+        let won = results.iter().filter(|result| matches!(result, Ok(Ok(_)))).count();
+        assert_eq!(won, 1);
+        assert!(results.iter().all(|result| matches!(result, Ok(Ok(_) | Err(SessionError::SlotBusy { .. })))));
+        registry.delete_session(&session_id).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_claims_of_different_roles_both_succeed() {
+        let Some((registry, session_id)) = registry_with_track().await else { return };
+
+        let claim = |role: TrackRole| {
+            let registry = Arc::clone(&registry);
+            let session_id = session_id.clone();
+            tokio::spawn(async move {
+                registry.claim_upload(&session_id, "t", role, record(role.as_str(), UploadStatus::Uploading)).await
+            })
+        };
+        let (vocal, instrumental) = tokio::join!(claim(TrackRole::Vocal), claim(TrackRole::Instrumental));
+        vocal.unwrap().unwrap();
+        instrumental.unwrap().unwrap();
+
+        let track = registry.get_track(&session_id, "t").await.unwrap();
+        assert_eq!(track.slot(TrackRole::Vocal).unwrap().key, "vocal");
+        assert_eq!(track.slot(TrackRole::Instrumental).unwrap().key, "instrumental");
+        registry.delete_session(&session_id).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn finalize_and_claim_race_never_both_succeed() {
+        for _ in 0..8 {
+            let Some((registry, session_id)) = registry_with_track().await else { return };
+            registry.record_upload(&session_id, "t", TrackRole::Vocal, record("vocal", UploadStatus::Uploaded)).await.unwrap();
+
+            let finalize = tokio::spawn({
+                let registry = Arc::clone(&registry);
+                let session_id = session_id.clone();
+                async move { registry.finalize_track(&session_id, "t").await }
+            });
+            let claim = tokio::spawn({
+                let registry = Arc::clone(&registry);
+                let session_id = session_id.clone();
+                async move { registry.claim_upload(&session_id, "t", TrackRole::Instrumental, record("inst", UploadStatus::Uploading)).await }
+            });
+
+            match (finalize.await.unwrap(), claim.await.unwrap()) {
+                (Ok(_), Err(SessionError::TrackFinalized { .. })) => {}
+                (Err(SessionError::TrackNotReady { .. }), Ok(_)) => {}
+                results => panic!("Unexpected outcome: {results:?}"),
+            }
+            registry.delete_session(&session_id).await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Notify;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
+use crate::storage::MultipartUpload;
+
+
+/// Как часто во время остановки в лог пишется, сколько загрузок еще идет
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Координатор остановки сервера. После `drain` новые загрузки не принимаются,
+/// начатые получают время завершиться, а оставшиеся к сроку многочастные
+/// загрузки отменяются и записываются
+#[derive(Default)]
+pub struct ShutdownCoordinator {
+    draining: AtomicBool,
+    next_id: AtomicU64,
+    /// Запросы загрузки, которые сейчас обрабатываются
+    requests: Mutex<HashMap<u64, InFlightRequest>>,
+    /// Незавершенные многочастные загрузки этих запросов
+    multipart: Mutex<HashMap<u64, InFlightMultipart>>,
+    /// Отмена обработчиков, не успевших к сроку
+    cancel: CancellationToken,
+    /// Будит `drain`, когда запрос завершился
+    finished: Notify,
+    /// Остановка завершена: загрузки завершились или отменены
+    drained: CancellationToken,
+}
+
+struct InFlightRequest {
+    description: String,
+    started_at: Instant,
+}
+
+struct InFlightMultipart {
+    bucket: String,
+    key: String,
+    upload: Arc<dyn MultipartUpload>,
+}
+
+/// Многочастная загрузка, отмененная при остановке
+#[derive(Debug, Clone, Serialize)]
+pub struct AbortedUpload {
+    pub bucket: String,
+    pub key: String,
+    pub upload_id: String,
+    pub aborted_at: DateTime<Utc>,
+    /// Удалось ли отменить загрузку в хранилище
+    pub aborted: bool,
+}
+
+/// Итог остановки
+#[derive(Debug, Default)]
+pub struct DrainReport {
+    /// Запросы, завершившиеся во время ожидания
+    pub completed: usize,
+    /// Запросы, прерванные по сроку
+    pub cancelled: usize,
+    pub aborted: Vec<AbortedUpload>,
+}
+
+impl ShutdownCoordinator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Идет ли остановка; новые загрузки в это время не принимаются
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Acquire)
+    }
+
+    /// Завершается, когда `drain` закончил ждать загрузки
+    pub async fn drained(&self) {
+        self.drained.cancelled().await
+    }
+
+    /// Сколько запросов загрузки сейчас обрабатывается
+    pub fn in_flight(&self) -> usize {
+        self.requests().len()
+    }
+
+    /// Регистрирует запрос загрузки; `None`, если сервер уже останавливается
+    pub fn begin_upload(self: &Arc<Self>, description: impl Into<String>) -> Option<UploadTicket> {
+        if self.is_draining() {
+            return None;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = InFlightRequest { description: description.into(), started_at: Instant::now() };
+        self.requests().insert(id, request);
+        Some(UploadTicket { coordinator: Arc::clone(self), id })
+    }
+
+    /// Запоминает незавершенную многочастную загрузку, чтобы отменить ее, если
+    /// она не успеет завершиться до срока. Снимается при сбросе результата
+    pub fn track_multipart(
+        self: &Arc<Self>,
+        bucket: &str,
+        key: &str,
+        upload: &Arc<dyn MultipartUpload>,
+    ) -> TrackedMultipart {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let entry = InFlightMultipart { bucket: bucket.to_string(), key: key.to_string(), upload: Arc::clone(upload) };
+        self.multipart().insert(id, entry);
+        TrackedMultipart { coordinator: Arc::clone(self), id }
+    }
+
+    /// Перестает принимать загрузки и ждет начатые не дольше `deadline`.
+    /// Затем отменяет оставшиеся многочастные загрузки, записывает их
+    /// в `aborted_uploads_path` (если задан) и прерывает их обработчики
+    pub async fn drain(&self, deadline: Duration, aborted_uploads_path: &str) -> DrainReport {
+        self.draining.store(true, Ordering::Release);
+        let started = Instant::now();
+        let initial = self.in_flight();
+        tracing::info!("Draining {} in-flight uploads, waiting up to {}s", initial, deadline.as_secs());
+
+        loop {
+            // Подписываемся до проверки, чтобы не пропустить завершение между ними
+            let finished = self.finished.notified();
+            let in_flight = self.in_flight();
+            if in_flight == 0 {
+                tracing::info!("All uploads finished in {:.1}s", started.elapsed().as_secs_f64());
+                self.drained.cancel();
+                return DrainReport { completed: initial, ..Default::default() };
+            }
+
+            let left = deadline.saturating_sub(started.elapsed());
+            if left.is_zero() {
+                break;
+            }
+            if tokio::time::timeout(left.min(PROGRESS_INTERVAL), finished).await.is_err() {
+                self.log_progress(left);
+            }
+        }
+
+        let cancelled = self.in_flight();
+        tracing::warn!("Drain deadline reached, cancelling {} uploads", cancelled);
+        let aborted = self.abort_multipart().await;
+        if !aborted_uploads_path.is_empty() && !aborted.is_empty() {
+            if let Err(err) = record_aborted(aborted_uploads_path, &aborted).await {
+                tracing::error!("Failed to record aborted uploads in {}: {}", aborted_uploads_path, err);
+            }
+        }
+        self.cancel.cancel();
+        self.drained.cancel();
+
+        DrainReport { completed: initial.saturating_sub(cancelled), cancelled, aborted }
+    }
+
+    fn requests(&self) -> MutexGuard<'_, HashMap<u64, InFlightRequest>> {
+        self.requests.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn multipart(&self) -> MutexGuard<'_, HashMap<u64, InFlightMultipart>> {
+        self.multipart.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn log_progress(&self, left: Duration) {
+        let requests = self.requests();
+        let mut uploads: Vec<_> = requests.values().collect();
+        uploads.sort_by_key(|request| request.started_at);
+        let uploads: Vec<_> = uploads
+            .iter()
+            .map(|request| format!("{} ({}s)", request.description, request.started_at.elapsed().as_secs()))
+            .collect();
+        tracing::info!("Draining: {} uploads in flight, {}s left: {}", uploads.len(), left.as_secs(), uploads.join(", "));
+    }
+
+    /// Отменяет все незавершенные многочастные загрузки; guard'ы обработчиков
+    /// потом отменят их еще раз, но отмена идемпотентна
+    async fn abort_multipart(&self) -> Vec<AbortedUpload> {
+        let uploads: Vec<_> = self.multipart().drain().map(|(_, upload)| upload).collect();
+
+        let mut aborted = Vec::with_capacity(uploads.len());
+        for InFlightMultipart { bucket, key, upload } in uploads {
+            let upload_id = upload.upload_id().to_string();
+            let result = upload.abort().await;
+            match &result {
+                Ok(()) => tracing::warn!("Aborted multipart upload {} of {}/{} on shutdown", upload_id, bucket, key),
+                Err(err) => tracing::error!(
+                    "Failed to abort multipart upload {} of {}/{} on shutdown: {}",
+                    upload_id, bucket, key, err
+                ),
+            }
+            aborted.push(AbortedUpload { bucket, key, upload_id, aborted_at: Utc::now(), aborted: result.is_ok() });
+        }
+        aborted
+    }
+}
+
+/// Дописывает отмененные загрузки в файл, по одной JSON-строке на загрузку
+async fn record_aborted(path: &str, aborted: &[AbortedUpload]) -> std::io::Result<()> {
+    let mut lines = Vec::new();
+    for upload in aborted {
+        serde_json::to_writer(&mut lines, upload)?;
+        lines.push(b'\n');
+    }
+    let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+    file.write_all(&lines).await?;
+    file.flush().await
+}
+
+
+/// Запрос загрузки, учтенный координатором; снимается с учета при сбросе
+pub struct UploadTicket {
+    coordinator: Arc<ShutdownCoordinator>,
+    id: u64,
+}
+
+impl UploadTicket {
+    /// Завершается, когда срок остановки истек и обработчик нужно прервать
+    pub fn cancelled(&self) -> WaitForCancellationFuture<'_> {
+        self.coordinator.cancel.cancelled()
+    }
+}
+
+impl Drop for UploadTicket {
+    fn drop(&mut self) {
+        self.coordinator.requests().remove(&self.id);
+        self.coordinator.finished.notify_waiters();
+    }
+}
+
+/// Многочастная загрузка на учете у координатора; снимается при сбросе
+pub struct TrackedMultipart {
+    coordinator: Arc<ShutdownCoordinator>,
+    id: u64,
+}
+
+impl Drop for TrackedMultipart {
+    fn drop(&mut self) {
+        self.coordinator.multipart().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ulid::Ulid;
+    use crate::storage::{MemoryObjectStore, ObjectStore};
+    use super::*;
+
+    const BUCKET: &str = "bucket";
+
+    #[tokio::test]
+    async fn drain_waits_for_uploads_that_finish_in_time() {
+        let coordinator = Arc::new(ShutdownCoordinator::new());
+        let ticket = coordinator.begin_upload("upload").unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(ticket);
+        });
+
+        let report = coordinator.drain(Duration::from_secs(10), "").await;
+        assert_eq!((report.completed, report.cancelled), (1, 0));
+        assert!(report.aborted.is_empty());
+
+        // После остановки новые загрузки не принимаются
+        assert!(coordinator.is_draining());
+        assert!(coordinator.begin_upload("late").is_none());
+        coordinator.drained().await;
+    }
+
+    #[tokio::test]
+    async fn deadline_cancels_handlers_and_aborts_their_uploads() {
+        let store = MemoryObjectStore::new();
+        let coordinator = Arc::new(ShutdownCoordinator::new());
+        let path = std::env::temp_dir().join(format!("svaha-aborted-{}.jsonl", Ulid::new()));
+
+        let ticket = coordinator.begin_upload("stuck").unwrap();
+        let stuck: Arc<dyn MultipartUpload> = store.create_multipart_upload(BUCKET, "stuck", None).await.unwrap().into();
+        let _tracked = coordinator.track_multipart(BUCKET, "stuck", &stuck);
+        // Загрузка, снятая с учета до срока, не отменяется
+        let finished: Arc<dyn MultipartUpload> = store.create_multipart_upload(BUCKET, "finished", None).await.unwrap().into();
+        drop(coordinator.track_multipart(BUCKET, "finished", &finished));
+
+        let report = coordinator.drain(Duration::from_millis(50), path.to_str().unwrap()).await;
+        assert_eq!((report.completed, report.cancelled), (0, 1));
+        assert_eq!(report.aborted.len(), 1);
+        assert_eq!(report.aborted[0].key, "stuck");
+        assert!(report.aborted[0].aborted);
+
+        // Обработчик узнает об отмене, а в хранилище остается только снятая с учета загрузка
+        ticket.cancelled().await;
+        let pending = store.list_multipart_uploads(BUCKET).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].key, "finished");
+
+        let recorded = tokio::fs::read_to_string(&path).await.unwrap();
+        let _ = tokio::fs::remove_file(&path).await;
+        let line: serde_json::Value = serde_json::from_str(recorded.lines().next().unwrap()).unwrap();
+        assert_eq!(line["key"], "stuck");
+        assert_eq!(line["upload_id"], stuck.upload_id());
+    }
+
+    #[tokio::test]
+    async fn drain_without_uploads_finishes_at_once() {
+        let coordinator = Arc::new(ShutdownCoordinator::new());
+        let report = coordinator.drain(Duration::from_secs(10), "").await;
+        assert_eq!((report.completed, report.cancelled), (0, 0));
+        coordinator.drained().await;
+    }
+}
@@ -0,0 +1,127 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use sha2::{Digest, Sha256};
+
+
+/// Контрольные суммы объекта, считаемые по мере чтения потока
+pub struct ObjectHasher {
+    sha256: Sha256,
+    crc32c: Option<u32>,
+}
+
+impl ObjectHasher {
+    pub fn new(with_crc32c: bool) -> Self {
+        Self {
+            sha256: Sha256::new(),
+            crc32c: with_crc32c.then_some(0),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.sha256.update(data);
+        if let Some(crc) = self.crc32c.as_mut() {
+            *crc = crc32c::crc32c_append(*crc, data);
+        }
+    }
+
+    pub fn finalize(self) -> ObjectDigest {
+        ObjectDigest {
+            sha256: self.sha256.finalize().into(),
+            crc32c: self.crc32c,
+        }
+    }
+}
+
+/// Итоговые контрольные суммы объекта
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectDigest {
+    pub sha256: [u8; 32],
+    pub crc32c: Option<u32>,
+}
+
+impl ObjectDigest {
+    pub fn sha256_hex(&self) -> String {
+        to_hex(&self.sha256)
+    }
+
+    pub fn crc32c_hex(&self) -> Option<String> {
+        self.crc32c.map(|crc| to_hex(&crc.to_be_bytes()))
+    }
+}
+
+/// Разбирает дайджест, присланный клиентом: hex или base64
+pub fn decode_digest(value: &str) -> Option<Vec<u8>> {
+    let value = value.trim();
+    let is_hex = value.len().is_multiple_of(2) && value.bytes().all(|byte| byte.is_ascii_hexdigit());
+
+    if is_hex {
+        (0..value.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&value[index..index + 2], 16).ok())
+            .collect()
+    } else {
+        BASE64.decode(value).ok()
+    }
+}
+
+/// SHA-256 в base64 - формат заголовков контрольных сумм S3
+pub(crate) fn sha256_base64(data: &[u8]) -> String {
+    BASE64.encode(Sha256::digest(data))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_match_known_vectors() {
+        let mut hasher = ObjectHasher::new(true);
+        hasher.update(b"123456789");
+        let digest = hasher.finalize();
+
+        assert_eq!(digest.sha256_hex(), "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225");
+        // Проверочное значение CRC32C (Castagnoli)
+        assert_eq!(digest.crc32c, Some(0xe306_9283));
+        assert_eq!(digest.crc32c_hex().as_deref(), Some("e3069283"));
+    }
+
+    #[test]
+    fn chunked_updates_give_the_same_digest() {
+        let data: Vec<u8> = (0..10_000u32).map(|value| value as u8).collect();
+
+        let mut whole = ObjectHasher::new(true);
+        whole.update(&data);
+
+        let mut chunked = ObjectHasher::new(true);
+        for chunk in data.chunks(777) {
+            chunked.update(chunk);
+        }
+
+        assert_eq!(whole.finalize(), chunked.finalize());
+    }
+
+    #[test]
+    fn crc32c_is_optional() {
+        let mut hasher = ObjectHasher::new(false);
+        hasher.update(b"abc");
+        let digest = hasher.finalize();
+
+        assert_eq!(digest.crc32c, None);
+        assert_eq!(digest.crc32c_hex(), None);
+        assert_eq!(digest.sha256_hex(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn decodes_hex_and_base64_digests() {
+        let sha256 = Sha256::digest(b"abc").to_vec();
+
+        assert_eq!(decode_digest("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"), Some(sha256.clone()));
+        assert_eq!(decode_digest(" BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD "), Some(sha256.clone()));
+        assert_eq!(decode_digest(&sha256_base64(b"abc")), Some(sha256));
+        assert_eq!(decode_digest("not a digest!"), None);
+    }
+}
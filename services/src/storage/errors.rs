@@ -0,0 +1,28 @@
+use thiserror::Error;
+use crate::s3::S3Error;
+
+
+/// Результат операций с хранилищем объектов
+pub type Result<T> = std::result::Result<T, StorageError>;
+
+/// Ошибки, общие для всех бэкендов хранилища
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("Object not found: bucket={bucket}, key={key}")]
+    ObjectNotFound { bucket: String, key: String },
+
+    #[error("Invalid object key: {0}")]
+    InvalidKey(String),
+
+    #[error("Multipart upload error: {0}")]
+    MultipartError(String),
+
+    #[error("{0}")]
+    S3(#[from] S3Error),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Other error: {0}")]
+    Other(String),
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+use super::MultipartUpload;
+use super::errors::Result;
+
+
+/// Владеет незавершенной многочастной загрузкой и гарантирует ее отмену:
+/// явно через `abort` на пути ошибки или в фоне, если guard (например,
+/// вместе с future обработчика) был сброшен до `complete`
+pub struct MultipartUploadGuard {
+    upload: Arc<dyn MultipartUpload>,
+    armed: bool,
+}
+
+impl MultipartUploadGuard {
+    pub fn new(upload: Arc<dyn MultipartUpload>) -> Self {
+        Self { upload, armed: true }
+    }
+
+    /// Загрузка, которой владеет guard
+    pub fn upload(&self) -> &Arc<dyn MultipartUpload> {
+        &self.upload
+    }
+
+    /// Завершает загрузку; при ошибке загрузка отменяется
+    pub async fn complete(mut self) -> Result<()> {
+        match self.upload.complete().await {
+            Ok(()) => {
+                self.armed = false;
+                Ok(())
+            }
+            Err(err) => {
+                tracing::error!("Failed to complete multipart upload {}: {}", self.upload.upload_id(), err);
+                self.abort_now().await;
+                Err(err)
+            }
+        }
+    }
+
+    /// Отменяет загрузку и дожидается ответа бэкенда
+    pub async fn abort(mut self) {
+        self.abort_now().await;
+    }
+
+    async fn abort_now(&mut self) {
+        self.armed = false;
+        match self.upload.abort().await {
+            Ok(()) => tracing::info!("Aborted multipart upload {}", self.upload.upload_id()),
+            Err(err) => tracing::error!("Failed to abort multipart upload {}: {}", self.upload.upload_id(), err),
+        }
+    }
+}
+
+impl Drop for MultipartUploadGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+
+        let upload = Arc::clone(&self.upload);
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::error!("No runtime to abort dropped multipart upload {}", upload.upload_id());
+            return;
+        };
+
+        runtime.spawn(async move {
+            match upload.abort().await {
+                Ok(()) => tracing::warn!("Aborted dropped multipart upload {}", upload.upload_id()),
+                Err(err) => tracing::error!("Failed to abort dropped multipart upload {}: {}", upload.upload_id(), err),
+            }
+        });
+    }
+}
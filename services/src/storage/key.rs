@@ -0,0 +1,274 @@
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use ulid::Ulid;
+use unicode_normalization::UnicodeNormalization;
+use super::validate_key;
+
+
+/// Ключ метаданных с исходным именем файла
+pub const ORIGINAL_FILENAME_METADATA: &str = "original-filename";
+/// Максимальная длина имени файла без расширения (символы)
+const MAX_FILENAME_CHARS: usize = 100;
+/// Максимальная длина идентификаторов сессии и трека
+const MAX_ID_LEN: usize = 64;
+/// Начало, обязательное для шаблона: по префиксу `{session_id}/{track_id}/`
+/// перечисляются файлы трека
+const REQUIRED_PREFIX: &str = "{session_id}/{track_id}/";
+/// Значения метаданных S3 передаются в заголовках, поэтому все, кроме
+/// печатного ASCII, кодируется в %XX
+const METADATA_ESCAPE: &AsciiSet = &CONTROLS.add(b'%').add(b'"');
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyError {
+    #[error("Unknown placeholder {{{0}}} in key template")]
+    UnknownPlaceholder(String),
+    #[error("Unclosed placeholder in key template")]
+    UnclosedPlaceholder,
+    #[error("Key template must start with {REQUIRED_PREFIX}")]
+    MissingPrefix,
+    #[error("Key template must contain {{ulid}}, otherwise uploads overwrite each other")]
+    MissingUlid,
+    #[error("Invalid {name}: {value:?}")]
+    InvalidValue { name: &'static str, value: String },
+    #[error("Key is {len} bytes long, the limit is {max}")]
+    TooLong { len: usize, max: usize },
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// Подстановки, известные шаблону
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    SessionId,
+    TrackId,
+    Role,
+    Ulid,
+    Ext,
+    Filename,
+    Stem,
+}
+
+impl Placeholder {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "session_id" => Some(Self::SessionId),
+            "track_id" => Some(Self::TrackId),
+            "role" => Some(Self::Role),
+            "ulid" => Some(Self::Ulid),
+            "ext" => Some(Self::Ext),
+            "filename" => Some(Self::Filename),
+            "stem" => Some(Self::Stem),
+            _ => None,
+        }
+    }
+}
+
+/// Значения для подстановки в шаблон ключа
+#[derive(Debug, Clone, Copy)]
+pub struct KeyParams<'a> {
+    pub session_id: &'a str,
+    pub track_id: &'a str,
+    /// Роль файла в треке: `vocal`, `instrumental`, ...
+    pub role: &'a str,
+    /// Исходное имя файла от клиента
+    pub filename: &'a str,
+}
+
+/// Шаблон ключа объекта, например `{session_id}/{track_id}/{role}-{ulid}.{ext}`.
+/// Шаблон начинается с `{session_id}/{track_id}/` и содержит `{ulid}`.
+/// Имя файла нормализуется (NFC) и очищается, идентификаторы проверяются,
+/// итоговый ключ ограничен по длине
+#[derive(Debug, Clone)]
+pub struct KeyTemplate {
+    segments: Vec<Segment>,
+    max_len: usize,
+}
+
+impl KeyTemplate {
+    pub fn new(template: &str, max_len: usize) -> Result<Self, KeyError> {
+        if !template.starts_with(REQUIRED_PREFIX) {
+            return Err(KeyError::MissingPrefix);
+        }
+
+        let mut segments = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..].find('}').ok_or(KeyError::UnclosedPlaceholder)? + start;
+            let name = &rest[start + 1..end];
+            let placeholder = Placeholder::from_name(name)
+                .ok_or_else(|| KeyError::UnknownPlaceholder(name.to_string()))?;
+            segments.push(Segment::Placeholder(placeholder));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+
+        if !segments.contains(&Segment::Placeholder(Placeholder::Ulid)) {
+            return Err(KeyError::MissingUlid);
+        }
+        Ok(Self { segments, max_len })
+    }
+
+    /// Строит ключ; `{ulid}` каждый раз новый, поэтому ключи не пересекаются
+    pub fn render(&self, params: &KeyParams) -> Result<String, KeyError> {
+        let session_id = checked_id("session_id", params.session_id)?;
+        let track_id = checked_id("track_id", params.track_id)?;
+        let role = checked_id("role", params.role)?;
+        let (stem, ext) = sanitize_filename(params.filename);
+        let filename = if ext.is_empty() { stem.clone() } else { format!("{stem}.{ext}") };
+        let ulid = Ulid::new().to_string().to_ascii_lowercase();
+
+        let mut key = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => key.push_str(literal),
+                Segment::Placeholder(Placeholder::SessionId) => key.push_str(session_id),
+                Segment::Placeholder(Placeholder::TrackId) => key.push_str(track_id),
+                Segment::Placeholder(Placeholder::Role) => key.push_str(role),
+                Segment::Placeholder(Placeholder::Ulid) => key.push_str(&ulid),
+                Segment::Placeholder(Placeholder::Ext) => key.push_str(&ext),
+                Segment::Placeholder(Placeholder::Filename) => key.push_str(&filename),
+                Segment::Placeholder(Placeholder::Stem) => key.push_str(&stem),
+            }
+        }
+
+        // Пустое расширение не должно оставлять точку в конце ключа
+        let key: String = key.trim_end_matches('.').nfc().collect();
+        if key.len() > self.max_len {
+            return Err(KeyError::TooLong { len: key.len(), max: self.max_len });
+        }
+        validate_key(&key).map_err(|_| KeyError::InvalidKey(key.clone()))?;
+        Ok(key)
+    }
+}
+
+/// Идентификаторы становятся сегментами ключа как есть, поэтому допускаются
+/// только ASCII-буквы, цифры, `-` и `_`
+fn checked_id<'a>(name: &'static str, value: &'a str) -> Result<&'a str, KeyError> {
+    let valid = !value.is_empty()
+        && value.len() <= MAX_ID_LEN
+        && value.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_');
+    if !valid {
+        return Err(KeyError::InvalidValue { name, value: value.to_string() });
+    }
+    Ok(value)
+}
+
+/// Очищает имя файла от клиента: NFC, только последний компонент пути, без
+/// управляющих и служебных символов. Возвращает имя без расширения и
+/// расширение в нижнем регистре
+pub fn sanitize_filename(filename: &str) -> (String, String) {
+    let normalized: String = filename.nfc().collect();
+    let name = normalized.rsplit(['/', '\\']).next().unwrap_or_default();
+
+    let mut cleaned = String::with_capacity(name.len());
+    for char in name.chars().filter(|char| !char.is_control()) {
+        let char = if char.is_alphanumeric() || matches!(char, '-' | '_' | '.') { char } else { '_' };
+        // Повторяющиеся заменители схлопываем
+        if char == '_' && cleaned.ends_with('_') {
+            continue;
+        }
+        cleaned.push(char);
+    }
+
+    let (stem, ext) = match cleaned.rsplit_once('.') {
+        Some((stem, ext)) if !stem.trim_matches(['.', '_']).is_empty()
+            && !ext.is_empty()
+            && ext.chars().all(|char| char.is_ascii_alphanumeric()) => (stem, ext.to_ascii_lowercase()),
+        _ => (cleaned.as_str(), String::new()),
+    };
+
+    let stem: String = stem.trim_matches(['.', '_', '-']).chars().take(MAX_FILENAME_CHARS).collect();
+    let stem = if stem.is_empty() { "file".to_string() } else { stem };
+    (stem, ext)
+}
+
+/// Кодирует значение метаданных объекта (например, исходное имя файла) в ASCII
+pub fn encode_metadata_value(value: &str) -> String {
+    utf8_percent_encode(value, METADATA_ESCAPE).to_string()
+}
+
+/// Обратное к `encode_metadata_value`
+pub fn decode_metadata_value(value: &str) -> String {
+    percent_decode_str(value).decode_utf8_lossy().into_owned()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(filename: &str) -> KeyParams<'_> {
+        KeyParams { session_id: "s1", track_id: "t-1", role: "vocal", filename }
+    }
+
+    #[test]
+    fn default_template_renders_unique_keys() {
+        let template = KeyTemplate::new("{session_id}/{track_id}/{role}-{ulid}.{ext}", 512).unwrap();
+        let first = template.render(&params("Song.WAV")).unwrap();
+        let second = template.render(&params("Song.WAV")).unwrap();
+
+        assert!(first.starts_with("s1/t-1/vocal-") && first.ends_with(".wav"), "{first}");
+        assert_eq!(first.len(), "s1/t-1/vocal-".len() + 26 + ".wav".len());
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn unsafe_templates_are_rejected() {
+        assert!(matches!(KeyTemplate::new("{track_id}/{session_id}/{ulid}", 512), Err(KeyError::MissingPrefix)));
+        assert!(matches!(KeyTemplate::new("uploads/{session_id}/{track_id}/{ulid}", 512), Err(KeyError::MissingPrefix)));
+        assert!(matches!(KeyTemplate::new("{session_id}/{track_id}/{filename}", 512), Err(KeyError::MissingUlid)));
+        assert!(matches!(KeyTemplate::new("{session_id}/{track_id}/{user}-{ulid}", 512), Err(KeyError::UnknownPlaceholder(name)) if name == "user"));
+        assert!(matches!(KeyTemplate::new("{session_id}/{track_id}/{ulid", 512), Err(KeyError::UnclosedPlaceholder)));
+    }
+
+    #[test]
+    fn ids_and_length_are_checked() {
+        let template = KeyTemplate::new("{session_id}/{track_id}/{ulid}-{filename}", 60).unwrap();
+        let bad = KeyParams { session_id: "../etc", ..params("a.wav") };
+        assert!(matches!(template.render(&bad), Err(KeyError::InvalidValue { name: "session_id", .. })));
+        let bad = KeyParams { role: "", ..params("a.wav") };
+        assert!(matches!(template.render(&bad), Err(KeyError::InvalidValue { name: "role", .. })));
+
+        let long = format!("{}.wav", "a".repeat(80));
+        assert!(matches!(template.render(&params(&long)), Err(KeyError::TooLong { max: 60, .. })));
+    }
+
+    #[test]
+    fn file_names_are_sanitized() {
+        assert_eq!(sanitize_filename("../../etc/passwd"), ("passwd".to_string(), String::new()));
+        assert_eq!(sanitize_filename("C:\\music\\Track 01.MP3"), ("Track_01".to_string(), "mp3".to_string()));
+        assert_eq!(sanitize_filename("a  &  b.flac"), ("a_b".to_string(), "flac".to_string()));
+        assert_eq!(sanitize_filename("..."), ("file".to_string(), String::new()));
+        assert_eq!(sanitize_filename(".wav"), ("wav".to_string(), String::new()));
+        assert_eq!(sanitize_filename("name.tar.gz"), ("name.tar".to_string(), "gz".to_string()));
+        assert_eq!(sanitize_filename("song.w@v"), ("song.w_v".to_string(), String::new()));
+        assert_eq!(sanitize_filename("line\nbreak.ogg"), ("linebreak".to_string(), "ogg".to_string()));
+        // Разложенная «й» нормализуется в один символ
+        assert_eq!(sanitize_filename("и\u{306}.wav").0, "й");
+        assert_eq!(sanitize_filename(&format!("{}.wav", "я".repeat(200))).0.chars().count(), MAX_FILENAME_CHARS);
+    }
+
+    #[test]
+    fn empty_extension_leaves_no_trailing_dot() {
+        let template = KeyTemplate::new("{session_id}/{track_id}/{role}-{ulid}.{ext}", 512).unwrap();
+        assert!(!template.render(&params("noext")).unwrap().ends_with('.'));
+    }
+
+    #[test]
+    fn metadata_values_round_trip() {
+        let encoded = encode_metadata_value("Мой \"трек\" 100%.wav");
+        assert!(encoded.is_ascii());
+        assert_eq!(decode_metadata_value(&encoded), "Мой \"трек\" 100%.wav");
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::{StreamExt, TryStreamExt};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use ulid::Ulid;
+use super::{paginate, validate_key, ByteRange, ListObjectsQuery, MultipartUpload, MultipartUploadOptions, ObjectBody, ObjectListPage, ObjectMeta, ObjectStore, PendingUpload, StorageBackend};
+use super::errors::{Result, StorageError};
+use crate::s3::get_mime_type;
+
+
+/// Каталог для незавершенных загрузок внутри корня хранилища
+const STAGING_DIR: &str = ".multipart";
+/// Файл в каталоге загрузки с бакетом и ключом целевого объекта
+const UPLOAD_INFO_FILE: &str = "upload.info";
+/// Каталог пользовательских метаданных: `{root}/.metadata/{bucket}/{key}.json`
+const METADATA_DIR: &str = ".metadata";
+
+/// Хранилище объектов на локальной файловой системе: `{root}/{bucket}/{key}`
+#[derive(Debug, Clone)]
+pub struct LocalObjectStore {
+    root: PathBuf,
+}
+
+impl LocalObjectStore {
+    /// Создает хранилище в каталоге `root`, создавая его при необходимости
+    pub async fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(root.join(STAGING_DIR)).await?;
+        Ok(Self { root })
+    }
+
+    fn bucket_path(&self, bucket: &str) -> Result<PathBuf> {
+        if bucket.is_empty() || bucket.starts_with('.') || bucket.contains(['/', '\\']) {
+            return Err(StorageError::InvalidKey(format!("Invalid bucket name: {bucket}")));
+        }
+        Ok(self.root.join(bucket))
+    }
+
+    fn object_path(&self, bucket: &str, key: &str) -> Result<PathBuf> {
+        validate_key(key)?;
+        Ok(self.bucket_path(bucket)?.join(key))
+    }
+
+    fn staging_path(&self, name: &str) -> PathBuf {
+        self.root.join(STAGING_DIR).join(name)
+    }
+
+    fn metadata_path(&self, bucket: &str, key: &str) -> Result<PathBuf> {
+        validate_key(key)?;
+        self.bucket_path(bucket)?;
+        Ok(self.root.join(METADATA_DIR).join(bucket).join(format!("{key}.json")))
+    }
+
+    async fn read_user_metadata(&self, bucket: &str, key: &str) -> Result<HashMap<String, String>> {
+        match fs::read(self.metadata_path(bucket, key)?).await {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|err| StorageError::Other(format!("Corrupted metadata of {key}: {err}"))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn write_user_metadata(&self, bucket: &str, key: &str, metadata: &HashMap<String, String>) -> Result<()> {
+        let path = self.metadata_path(bucket, key)?;
+        if metadata.is_empty() {
+            return match fs::remove_file(&path).await {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            };
+        }
+
+        let data = serde_json::to_vec(metadata)
+            .map_err(|err| StorageError::Other(format!("Failed to encode metadata of {key}: {err}")))?;
+        let temp_path = self.staging_path(&format!("{}.tmp", Ulid::new()));
+        fs::write(&temp_path, data).await?;
+        if let Err(err) = self.promote(&temp_path, &path).await {
+            let _ = fs::remove_file(&temp_path).await;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    async fn read_meta(path: &Path, key: &str) -> Result<Option<ObjectMeta>> {
+        let metadata = match fs::metadata(path).await {
+            Ok(metadata) if metadata.is_file() => metadata,
+            Ok(_) => return Ok(None),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let modified = metadata.modified().ok();
+        let modified_nanos = modified
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_nanos())
+            .unwrap_or_default();
+
+        Ok(Some(ObjectMeta {
+            key: key.to_string(),
+            size: metadata.len(),
+            e_tag: Some(format!("\"{:x}-{:x}\"", metadata.len(), modified_nanos)),
+            last_modified: modified.map(DateTime::<Utc>::from),
+            content_type: Some(get_mime_type(key)),
+            storage_class: None,
+            metadata: HashMap::new(),
+        }))
+    }
+
+    /// Атомарно перемещает готовый файл на место объекта
+    async fn promote(&self, temp_path: &Path, destination: &Path) -> Result<()> {
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::rename(temp_path, destination).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ObjectStore for LocalObjectStore {
+    fn backend(&self) -> StorageBackend {
+        StorageBackend::Local
+    }
+
+    async fn put_object(&self, bucket: &str, key: &str, data: Bytes) -> Result<()> {
+        let destination = self.object_path(bucket, key)?;
+        let temp_path = self.staging_path(&format!("{}.tmp", Ulid::new()));
+
+        let mut file = fs::File::create(&temp_path).await?;
+        file.write_all(&data).await?;
+        file.sync_all().await?;
+        drop(file);
+
+        if let Err(err) = self.promote(&temp_path, &destination).await {
+            let _ = fs::remove_file(&temp_path).await;
+            return Err(err);
+        }
+        self.write_user_metadata(bucket, key, &HashMap::new()).await
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        _options: Option<MultipartUploadOptions>,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        let destination = self.object_path(bucket, key)?;
+        let upload_id = Ulid::new().to_string();
+        let parts_dir = self.staging_path(&upload_id);
+        fs::create_dir_all(&parts_dir).await?;
+        fs::write(parts_dir.join(UPLOAD_INFO_FILE), format!("{bucket}\n{key}")).await?;
+
+        Ok(Box::new(LocalMultipartUpload {
+            store: self.clone(),
+            destination,
+            metadata_path: self.metadata_path(bucket, key)?,
+            upload_id,
+            parts_dir,
+        }))
+    }
+
+    async fn resume_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        let destination = self.object_path(bucket, key)?;
+        let not_found = || StorageError::ObjectNotFound {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        };
+        if Ulid::from_string(upload_id).is_err() {
+            return Err(not_found());
+        }
+
+        let parts_dir = self.staging_path(upload_id);
+        let info = fs::read_to_string(parts_dir.join(UPLOAD_INFO_FILE)).await.map_err(|_| not_found())?;
+        if info != format!("{bucket}\n{key}") {
+            return Err(not_found());
+        }
+
+        Ok(Box::new(LocalMultipartUpload {
+            store: self.clone(),
+            destination,
+            metadata_path: self.metadata_path(bucket, key)?,
+            upload_id: upload_id.to_string(),
+            parts_dir,
+        }))
+    }
+
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<ObjectBody> {
+        let path = self.object_path(bucket, key)?;
+        let meta = Self::read_meta(&path, key).await?.ok_or_else(|| StorageError::ObjectNotFound {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })?;
+
+        let file = fs::File::open(&path).await?;
+        let body = ReaderStream::new(file).map_err(StorageError::from).boxed();
+
+        Ok(ObjectBody { meta, body })
+    }
+
+    async fn get_object_range(&self, bucket: &str, key: &str, range: ByteRange) -> Result<ObjectBody> {
+        let path = self.object_path(bucket, key)?;
+        let mut meta = Self::read_meta(&path, key).await?.ok_or_else(|| StorageError::ObjectNotFound {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })?;
+        if range.end >= meta.size {
+            return Err(StorageError::Other(format!("Range {}-{} is outside of {}", range.start, range.end, key)));
+        }
+
+        let mut file = fs::File::open(&path).await?;
+        file.seek(std::io::SeekFrom::Start(range.start)).await?;
+        let body = ReaderStream::new(file.take(range.size())).map_err(StorageError::from).boxed();
+        meta.size = range.size();
+
+        Ok(ObjectBody { meta, body })
+    }
+
+    async fn head_object(&self, bucket: &str, key: &str) -> Result<Option<ObjectMeta>> {
+        let path = self.object_path(bucket, key)?;
+        let Some(mut meta) = Self::read_meta(&path, key).await? else { return Ok(None) };
+        meta.metadata = self.read_user_metadata(bucket, key).await?;
+        Ok(Some(meta))
+    }
+
+    async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<ObjectMeta>> {
+        let bucket_path = self.bucket_path(bucket)?;
+        let prefix = prefix.unwrap_or_default();
+        let mut objects = Vec::new();
+        let mut pending = vec![bucket_path.clone()];
+
+        // Обходим дерево каталогов без рекурсии
+        while let Some(dir) = pending.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if entry.file_type().await?.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+
+                let Ok(relative) = path.strip_prefix(&bucket_path) else { continue };
+                let key = relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+
+                if !key.starts_with(prefix) {
+                    continue;
+                }
+                if let Some(meta) = Self::read_meta(&path, &key).await? {
+                    objects.push(meta);
+                }
+            }
+        }
+
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn list_objects_page(&self, bucket: &str, query: &ListObjectsQuery) -> Result<ObjectListPage> {
+        let objects = self.list_objects(bucket, query.prefix.as_deref()).await?;
+        Ok(paginate(objects, query))
+    }
+
+    async fn delete_object(&self, bucket: &str, key: &str) -> Result<()> {
+        let path = self.object_path(bucket, key)?;
+        match fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        self.write_user_metadata(bucket, key, &HashMap::new()).await
+    }
+
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> Result<()> {
+        let source = self.object_path(source_bucket, source_key)?;
+        let destination = self.object_path(destination_bucket, destination_key)?;
+        let temp_path = self.staging_path(&format!("{}.tmp", Ulid::new()));
+
+        if let Err(err) = fs::copy(&source, &temp_path).await {
+            return Err(if err.kind() == std::io::ErrorKind::NotFound {
+                StorageError::ObjectNotFound {
+                    bucket: source_bucket.to_string(),
+                    key: source_key.to_string(),
+                }
+            } else {
+                err.into()
+            });
+        }
+
+        if let Err(err) = self.promote(&temp_path, &destination).await {
+            let _ = fs::remove_file(&temp_path).await;
+            return Err(err);
+        }
+
+        let metadata = self.read_user_metadata(source_bucket, source_key).await?;
+        self.write_user_metadata(destination_bucket, destination_key, &metadata).await
+    }
+
+    async fn replace_metadata(&self, bucket: &str, key: &str, metadata: HashMap<String, String>) -> Result<()> {
+        let path = self.object_path(bucket, key)?;
+        if Self::read_meta(&path, key).await?.is_none() {
+            return Err(StorageError::ObjectNotFound {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+            });
+        }
+        self.write_user_metadata(bucket, key, &metadata).await
+    }
+
+    async fn list_multipart_uploads(&self, bucket: &str) -> Result<Vec<PendingUpload>> {
+        let mut uploads = Vec::new();
+        let mut entries = fs::read_dir(self.root.join(STAGING_DIR)).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            if !entry.file_type().await?.is_dir() {
+                continue;
+            }
+            let Some(upload_id) = entry.file_name().to_str().map(ToString::to_string) else { continue };
+            let Ok(ulid) = Ulid::from_string(&upload_id) else { continue };
+
+            // Каталог без описания считаем осиротевшим и относим к любому бакету
+            let info = fs::read_to_string(entry.path().join(UPLOAD_INFO_FILE)).await.unwrap_or_default();
+            let (upload_bucket, key) = info.split_once('\n').unwrap_or((bucket, ""));
+            if upload_bucket != bucket {
+                continue;
+            }
+
+            uploads.push(PendingUpload {
+                key: key.to_string(),
+                upload_id,
+                initiated: Some(DateTime::<Utc>::from(ulid.datetime())),
+            });
+        }
+
+        Ok(uploads)
+    }
+
+    async fn abort_multipart_upload(&self, _bucket: &str, _key: &str, upload_id: &str) -> Result<()> {
+        // Идентификатор используется как имя каталога, поэтому принимаем только ULID
+        if Ulid::from_string(upload_id).is_err() {
+            return Err(StorageError::MultipartError(format!("Invalid upload id: {upload_id}")));
+        }
+
+        match fs::remove_dir_all(self.staging_path(upload_id)).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Многочастная загрузка на диск: части пишутся в отдельные файлы,
+/// при завершении склеиваются и атомарно переименовываются
+struct LocalMultipartUpload {
+    store: LocalObjectStore,
+    destination: PathBuf,
+    /// Метаданные прежней версии объекта удаляются при завершении
+    metadata_path: PathBuf,
+    upload_id: String,
+    parts_dir: PathBuf,
+}
+
+impl LocalMultipartUpload {
+    fn part_path(&self, part_number: i32) -> PathBuf {
+        self.parts_dir.join(format!("{part_number:05}"))
+    }
+}
+
+#[async_trait]
+impl MultipartUpload for LocalMultipartUpload {
+    fn upload_id(&self) -> &str {
+        &self.upload_id
+    }
+
+    async fn upload_part(&self, part_number: i32, body: Bytes) -> Result<()> {
+        if part_number < 1 {
+            return Err(StorageError::MultipartError(format!("Invalid part number {part_number}")));
+        }
+
+        let mut file = fs::File::create(self.part_path(part_number)).await?;
+        file.write_all(&body).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    async fn complete(&self) -> Result<()> {
+        let mut part_numbers = Vec::new();
+        let mut entries = fs::read_dir(&self.parts_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(number) = entry.file_name().to_str().and_then(|name| name.parse::<i32>().ok()) {
+                part_numbers.push(number);
+            }
+        }
+        part_numbers.sort_unstable();
+        self.assemble(part_numbers).await
+    }
+
+    async fn complete_parts(&self, last_part: i32) -> Result<()> {
+        let part_numbers: Vec<i32> = (1..=last_part).collect();
+        for number in &part_numbers {
+            if !fs::try_exists(self.part_path(*number)).await? {
+                return Err(StorageError::MultipartError(format!("Part {number} of upload {} is missing", self.upload_id)));
+            }
+        }
+        self.assemble(part_numbers).await
+    }
+
+    async fn abort(&self) -> Result<()> {
+        match fs::remove_dir_all(&self.parts_dir).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl LocalMultipartUpload {
+    /// Склеивает части с номерами `part_numbers` и переносит результат на место;
+    /// каталог частей удаляется вместе с лишними частями
+    async fn assemble(&self, part_numbers: Vec<i32>) -> Result<()> {
+        // Склеиваем части по порядку номеров во временный файл
+        let temp_path = self.store.staging_path(&format!("{}.tmp", self.upload_id));
+        let mut output = fs::File::create(&temp_path).await?;
+        for number in part_numbers {
+            let mut part = fs::File::open(self.part_path(number)).await?;
+            tokio::io::copy(&mut part, &mut output).await?;
+        }
+        output.sync_all().await?;
+        drop(output);
+
+        if let Err(err) = self.store.promote(&temp_path, &self.destination).await {
+            let _ = fs::remove_file(&temp_path).await;
+            return Err(err);
+        }
+
+        match fs::remove_file(&self.metadata_path).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        fs::remove_dir_all(&self.parts_dir).await?;
+        Ok(())
+    }
+}
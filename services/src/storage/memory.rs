@@ -0,0 +1,332 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use tokio::sync::{Mutex, RwLock};
+use ulid::Ulid;
+use super::{paginate, validate_key, ByteRange, ListObjectsQuery, MultipartUpload, MultipartUploadOptions, ObjectBody, ObjectListPage, ObjectMeta, ObjectStore, PendingUpload, StorageBackend};
+use super::errors::{Result, StorageError};
+
+
+/// Объект, хранящийся в памяти
+#[derive(Debug, Clone)]
+struct StoredObject {
+    data: Bytes,
+    e_tag: String,
+    last_modified: DateTime<Utc>,
+    content_type: Option<String>,
+    metadata: HashMap<String, String>,
+}
+
+impl StoredObject {
+    fn new(data: Bytes, content_type: Option<String>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+
+        Self {
+            e_tag: format!("\"{:016x}\"", hasher.finish()),
+            data,
+            last_modified: Utc::now(),
+            content_type,
+            metadata: HashMap::new(),
+        }
+    }
+
+    fn meta(&self, key: &str) -> ObjectMeta {
+        ObjectMeta {
+            key: key.to_string(),
+            size: self.data.len() as u64,
+            e_tag: Some(self.e_tag.clone()),
+            last_modified: Some(self.last_modified),
+            content_type: self.content_type.clone(),
+            storage_class: None,
+            metadata: self.metadata.clone(),
+        }
+    }
+}
+
+/// Незавершенная многочастная загрузка; живет в хранилище, чтобы ее
+/// можно было найти, продолжить или отменить по идентификатору
+#[derive(Debug, Default)]
+struct PendingParts {
+    bucket: String,
+    key: String,
+    content_type: Option<String>,
+    initiated: DateTime<Utc>,
+    parts: BTreeMap<i32, Bytes>,
+}
+
+type Buckets = Arc<RwLock<HashMap<String, BTreeMap<String, StoredObject>>>>;
+type Uploads = Arc<Mutex<HashMap<String, PendingParts>>>;
+
+/// Хранилище объектов в памяти процесса (для тестов и локальной разработки)
+#[derive(Clone, Default)]
+pub struct MemoryObjectStore {
+    buckets: Buckets,
+    uploads: Uploads,
+}
+
+impl MemoryObjectStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    async fn insert(&self, bucket: &str, key: &str, object: StoredObject) {
+        let mut buckets = self.buckets.write().await;
+        buckets
+            .entry(bucket.to_string())
+            .or_default()
+            .insert(key.to_string(), object);
+    }
+
+    async fn find(&self, bucket: &str, key: &str) -> Option<StoredObject> {
+        let buckets = self.buckets.read().await;
+        buckets.get(bucket).and_then(|objects| objects.get(key)).cloned()
+    }
+}
+
+#[async_trait]
+impl ObjectStore for MemoryObjectStore {
+    fn backend(&self) -> StorageBackend {
+        StorageBackend::Memory
+    }
+
+    async fn put_object(&self, bucket: &str, key: &str, data: Bytes) -> Result<()> {
+        validate_key(key)?;
+        self.insert(bucket, key, StoredObject::new(data, None)).await;
+        Ok(())
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        options: Option<MultipartUploadOptions>,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        validate_key(key)?;
+        let options = options.unwrap_or_default();
+        let upload_id = Ulid::new().to_string();
+
+        self.uploads.lock().await.insert(upload_id.clone(), PendingParts {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            content_type: options.content_type,
+            initiated: Utc::now(),
+            parts: BTreeMap::new(),
+        });
+
+        Ok(Box::new(MemoryMultipartUpload { store: self.clone(), upload_id }))
+    }
+
+    async fn resume_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        let uploads = self.uploads.lock().await;
+        match uploads.get(upload_id) {
+            Some(pending) if pending.bucket == bucket && pending.key == key => {
+                Ok(Box::new(MemoryMultipartUpload { store: self.clone(), upload_id: upload_id.to_string() }))
+            }
+            _ => Err(StorageError::ObjectNotFound {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+            }),
+        }
+    }
+
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<ObjectBody> {
+        let object = self.find(bucket, key).await.ok_or_else(|| StorageError::ObjectNotFound {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })?;
+
+        Ok(ObjectBody {
+            meta: object.meta(key),
+            body: stream::once(async move { Ok(object.data) }).boxed(),
+        })
+    }
+
+    async fn get_object_range(&self, bucket: &str, key: &str, range: ByteRange) -> Result<ObjectBody> {
+        let object = self.find(bucket, key).await.ok_or_else(|| StorageError::ObjectNotFound {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })?;
+        if range.end >= object.data.len() as u64 {
+            return Err(StorageError::Other(format!("Range {}-{} is outside of {}", range.start, range.end, key)));
+        }
+
+        let mut meta = object.meta(key);
+        meta.size = range.size();
+        let data = object.data.slice(range.start as usize..=range.end as usize);
+
+        Ok(ObjectBody {
+            meta,
+            body: stream::once(async move { Ok(data) }).boxed(),
+        })
+    }
+
+    async fn head_object(&self, bucket: &str, key: &str) -> Result<Option<ObjectMeta>> {
+        Ok(self.find(bucket, key).await.map(|object| object.meta(key)))
+    }
+
+    async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<ObjectMeta>> {
+        let buckets = self.buckets.read().await;
+        let prefix = prefix.unwrap_or_default();
+
+        Ok(buckets
+            .get(bucket)
+            .map(|objects| {
+                objects
+                    .iter()
+                    .filter(|(key, _)| key.starts_with(prefix))
+                    .map(|(key, object)| object.meta(key))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn list_objects_page(&self, bucket: &str, query: &ListObjectsQuery) -> Result<ObjectListPage> {
+        // BTreeMap уже отдает ключи по порядку
+        let objects = self.list_objects(bucket, query.prefix.as_deref()).await?;
+        Ok(paginate(objects, query))
+    }
+
+    async fn delete_object(&self, bucket: &str, key: &str) -> Result<()> {
+        let mut buckets = self.buckets.write().await;
+        if let Some(objects) = buckets.get_mut(bucket) {
+            objects.remove(key);
+        }
+        Ok(())
+    }
+
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> Result<()> {
+        validate_key(destination_key)?;
+        let object = self.find(source_bucket, source_key).await.ok_or_else(|| StorageError::ObjectNotFound {
+            bucket: source_bucket.to_string(),
+            key: source_key.to_string(),
+        })?;
+
+        // Как и в S3, метаданные копируются вместе с объектом
+        let copy = StoredObject {
+            metadata: object.metadata,
+            ..StoredObject::new(object.data, object.content_type)
+        };
+        self.insert(destination_bucket, destination_key, copy).await;
+        Ok(())
+    }
+
+    async fn replace_metadata(&self, bucket: &str, key: &str, metadata: HashMap<String, String>) -> Result<()> {
+        let mut buckets = self.buckets.write().await;
+        let object = buckets
+            .get_mut(bucket)
+            .and_then(|objects| objects.get_mut(key))
+            .ok_or_else(|| StorageError::ObjectNotFound {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+            })?;
+
+        object.metadata = metadata;
+        object.last_modified = Utc::now();
+        Ok(())
+    }
+
+    async fn list_multipart_uploads(&self, bucket: &str) -> Result<Vec<PendingUpload>> {
+        let uploads = self.uploads.lock().await;
+
+        Ok(uploads
+            .iter()
+            .filter(|(_, pending)| pending.bucket == bucket)
+            .map(|(upload_id, pending)| PendingUpload {
+                key: pending.key.clone(),
+                upload_id: upload_id.clone(),
+                initiated: Some(pending.initiated),
+            })
+            .collect())
+    }
+
+    async fn abort_multipart_upload(&self, bucket: &str, key: &str, upload_id: &str) -> Result<()> {
+        let mut uploads = self.uploads.lock().await;
+        if uploads.get(upload_id).is_some_and(|pending| pending.bucket == bucket && pending.key == key) {
+            uploads.remove(upload_id);
+        }
+        Ok(())
+    }
+}
+
+/// Многочастная загрузка в память: части копятся в хранилище до вызова `complete`
+struct MemoryMultipartUpload {
+    store: MemoryObjectStore,
+    upload_id: String,
+}
+
+impl MemoryMultipartUpload {
+    fn missing(&self) -> StorageError {
+        StorageError::MultipartError(format!("Upload {} does not exist", self.upload_id))
+    }
+
+    async fn assemble(&self, pending: PendingParts, parts: &[Bytes]) {
+        let mut data = BytesMut::with_capacity(parts.iter().map(Bytes::len).sum());
+        for part in parts {
+            data.extend_from_slice(part);
+        }
+
+        self.store
+            .insert(&pending.bucket, &pending.key, StoredObject::new(data.freeze(), pending.content_type))
+            .await;
+    }
+}
+
+#[async_trait]
+impl MultipartUpload for MemoryMultipartUpload {
+    fn upload_id(&self) -> &str {
+        &self.upload_id
+    }
+
+    async fn upload_part(&self, part_number: i32, body: Bytes) -> Result<()> {
+        if part_number < 1 {
+            return Err(StorageError::MultipartError(format!("Invalid part number {part_number}")));
+        }
+        let mut uploads = self.store.uploads.lock().await;
+        let pending = uploads.get_mut(&self.upload_id).ok_or_else(|| self.missing())?;
+        pending.parts.insert(part_number, body);
+        Ok(())
+    }
+
+    async fn complete(&self) -> Result<()> {
+        let pending = self.store.uploads.lock().await.remove(&self.upload_id).ok_or_else(|| self.missing())?;
+        let parts: Vec<_> = pending.parts.values().cloned().collect();
+        self.assemble(pending, &parts).await;
+        Ok(())
+    }
+
+    async fn complete_parts(&self, last_part: i32) -> Result<()> {
+        let mut uploads = self.store.uploads.lock().await;
+        let pending = uploads.get(&self.upload_id).ok_or_else(|| self.missing())?;
+        let parts = (1..=last_part)
+            .map(|number| pending.parts.get(&number).cloned().ok_or_else(|| {
+                StorageError::MultipartError(format!("Part {number} of upload {} is missing", self.upload_id))
+            }))
+            .collect::<Result<Vec<_>>>()?;
+        let pending = uploads.remove(&self.upload_id).ok_or_else(|| self.missing())?;
+        drop(uploads);
+
+        self.assemble(pending, &parts).await;
+        Ok(())
+    }
+
+    async fn abort(&self) -> Result<()> {
+        self.store.uploads.lock().await.remove(&self.upload_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+pub mod checksum;
+mod errors;
+mod guard;
+pub mod key;
+mod local;
+mod memory;
+mod pipeline;
+mod s3;
+
+use std::collections::HashMap;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+
+pub use checksum::{ObjectDigest, ObjectHasher};
+pub use errors::{StorageError, Result};
+pub use guard::MultipartUploadGuard;
+pub use key::{KeyError, KeyParams, KeyTemplate};
+pub use local::LocalObjectStore;
+pub use memory::MemoryObjectStore;
+pub use pipeline::PartUploadPipeline;
+pub use my_core::config::StorageBackend;
+pub use crate::s3::MultipartUploadOptions;
+
+
+/// Поток байтов тела объекта
+pub type ObjectStream = BoxStream<'static, Result<Bytes>>;
+
+/// Метаданные объекта в хранилище
+#[derive(Debug, Clone, Default)]
+pub struct ObjectMeta {
+    pub key: String,
+    pub size: u64,
+    pub e_tag: Option<String>,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub content_type: Option<String>,
+    /// Класс хранения S3 (`STANDARD`, `GLACIER`, ...), если бэкенд его знает
+    pub storage_class: Option<String>,
+    /// Пользовательские метаданные (`x-amz-meta-*`); при перечислении не заполняются
+    pub metadata: HashMap<String, String>,
+}
+
+/// Максимальное число ключей на странице перечисления (как в S3)
+pub const MAX_LIST_KEYS: usize = 1000;
+
+/// Параметры постраничного перечисления объектов
+#[derive(Debug, Clone, Default)]
+pub struct ListObjectsQuery {
+    pub prefix: Option<String>,
+    /// Ключи, содержащие разделитель после префикса, сворачиваются в `common_prefixes`
+    pub delimiter: Option<String>,
+    /// Токен из `next_continuation_token` предыдущей страницы
+    pub continuation_token: Option<String>,
+    /// Размер страницы, не больше `MAX_LIST_KEYS`
+    pub max_keys: Option<usize>,
+}
+
+/// Страница перечисления объектов
+#[derive(Debug, Clone, Default)]
+pub struct ObjectListPage {
+    pub objects: Vec<ObjectMeta>,
+    pub common_prefixes: Vec<String>,
+    /// `None` на последней странице
+    pub next_continuation_token: Option<String>,
+}
+
+/// Незавершенная многочастная загрузка, найденная в бэкенде
+#[derive(Debug, Clone)]
+pub struct PendingUpload {
+    pub key: String,
+    pub upload_id: String,
+    pub initiated: Option<DateTime<Utc>>,
+}
+
+/// Диапазон байтов объекта, обе границы включительно
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn size(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// Объект, отдаваемый хранилищем в виде потока
+pub struct ObjectBody {
+    pub meta: ObjectMeta,
+    pub body: ObjectStream,
+}
+
+/// Разбивает на страницы полный отсортированный по ключу список объектов.
+/// Для бэкендов без собственной пагинации; токен - последний выданный ключ или префикс
+pub(crate) fn paginate(objects: Vec<ObjectMeta>, query: &ListObjectsQuery) -> ObjectListPage {
+    let prefix = query.prefix.as_deref().unwrap_or_default();
+    let delimiter = query.delimiter.as_deref().filter(|delimiter| !delimiter.is_empty());
+    let max_keys = query.max_keys.unwrap_or(MAX_LIST_KEYS).clamp(1, MAX_LIST_KEYS);
+    let token = query.continuation_token.as_deref();
+
+    let mut page = ObjectListPage::default();
+    let mut last_entry: Option<String> = None;
+
+    for object in objects {
+        let Some(rest) = object.key.strip_prefix(prefix) else { continue };
+        if let Some(token) = token {
+            // Пропускаем уже выданное, включая содержимое выданных префиксов
+            let inside_token_prefix = delimiter.is_some_and(|delimiter| token.ends_with(delimiter))
+                && object.key.starts_with(token);
+            if object.key.as_str() <= token || inside_token_prefix {
+                continue;
+            }
+        }
+
+        let common_prefix = delimiter
+            .and_then(|delimiter| rest.find(delimiter).map(|index| index + delimiter.len()))
+            .map(|end| format!("{prefix}{}", &rest[..end]));
+        if let Some(common_prefix) = &common_prefix {
+            if page.common_prefixes.last() == Some(common_prefix) {
+                continue;
+            }
+        }
+
+        if page.objects.len() + page.common_prefixes.len() == max_keys {
+            page.next_continuation_token = last_entry;
+            return page;
+        }
+
+        match common_prefix {
+            Some(common_prefix) => {
+                last_entry = Some(common_prefix.clone());
+                page.common_prefixes.push(common_prefix);
+            }
+            None => {
+                last_entry = Some(object.key.clone());
+                page.objects.push(object);
+            }
+        }
+    }
+
+    page
+}
+
+/// Общий интерфейс хранилища объектов (S3, локальная ФС, память)
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    /// Тип бэкенда
+    fn backend(&self) -> StorageBackend;
+
+    /// Загружает объект целиком
+    async fn put_object(&self, bucket: &str, key: &str, data: Bytes) -> Result<()>;
+
+    /// Начинает потоковую многочастную загрузку
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        options: Option<MultipartUploadOptions>,
+    ) -> Result<Box<dyn MultipartUpload>>;
+
+    /// Продолжает ранее начатую многочастную загрузку (например, после перезапуска)
+    async fn resume_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<Box<dyn MultipartUpload>>;
+
+    /// Отдает объект в виде потока
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<ObjectBody>;
+
+    /// Отдает диапазон байтов объекта; `meta.size` - длина диапазона.
+    /// Диапазон должен лежать внутри объекта
+    async fn get_object_range(&self, bucket: &str, key: &str, range: ByteRange) -> Result<ObjectBody>;
+
+    /// Возвращает метаданные объекта или `None`, если его нет
+    async fn head_object(&self, bucket: &str, key: &str) -> Result<Option<ObjectMeta>>;
+
+    /// Перечисляет объекты с заданным префиксом
+    async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<ObjectMeta>>;
+
+    /// Возвращает одну страницу перечисления с учетом разделителя
+    async fn list_objects_page(&self, bucket: &str, query: &ListObjectsQuery) -> Result<ObjectListPage>;
+
+    /// Удаляет объект (отсутствие объекта не считается ошибкой)
+    async fn delete_object(&self, bucket: &str, key: &str) -> Result<()>;
+
+    /// Копирует объект, в том числе между бакетами
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> Result<()>;
+
+    /// Заменяет пользовательские метаданные существующего объекта
+    async fn replace_metadata(&self, bucket: &str, key: &str, metadata: HashMap<String, String>) -> Result<()>;
+
+    /// Перечисляет незавершенные многочастные загрузки в бакете
+    async fn list_multipart_uploads(&self, bucket: &str) -> Result<Vec<PendingUpload>>;
+
+    /// Отменяет многочастную загрузку по ее идентификатору
+    async fn abort_multipart_upload(&self, bucket: &str, key: &str, upload_id: &str) -> Result<()>;
+}
+
+/// Незавершенная многочастная загрузка
+#[async_trait]
+pub trait MultipartUpload: Send + Sync {
+    /// Идентификатор загрузки в бэкенде
+    fn upload_id(&self) -> &str;
+
+    /// Загружает часть файла (номера частей начинаются с 1)
+    async fn upload_part(&self, part_number: i32, body: Bytes) -> Result<()>;
+
+    /// Собирает объект из загруженных частей в порядке их номеров
+    async fn complete(&self) -> Result<()>;
+
+    /// Собирает объект только из частей `1..=last_part`; части с большими
+    /// номерами (например, от прерванного запроса) в объект не попадают.
+    /// Если какой-то из этих частей нет, возвращает ошибку
+    async fn complete_parts(&self, last_part: i32) -> Result<()>;
+
+    /// Отменяет загрузку и удаляет загруженные части
+    async fn abort(&self) -> Result<()>;
+}
+
+/// Проверяет ключ объекта перед записью в хранилище
+pub(crate) fn validate_key(key: &str) -> Result<()> {
+    if key.is_empty() || key.starts_with('/') {
+        return Err(StorageError::InvalidKey(key.to_string()));
+    }
+    if key.split('/').any(|segment| segment.is_empty() || segment == "." || segment == "..") {
+        return Err(StorageError::InvalidKey(key.to_string()));
+    }
+    if key.chars().any(|c| c.is_control() || c == '\\') {
+        return Err(StorageError::InvalidKey(key.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use super::*;
+
+    const BUCKET: &str = "bucket";
+
+    async fn collect(body: ObjectStream) -> Vec<u8> {
+        body.map_ok(|chunk| chunk.to_vec()).try_concat().await.unwrap()
+    }
+
+    async fn read(store: &dyn ObjectStore, key: &str) -> Vec<u8> {
+        collect(store.get_object(BUCKET, key).await.unwrap().body).await
+    }
+
+    /// Поведение, которого обработчики ждут от любого бэкенда
+    async fn check_contract(store: &dyn ObjectStore) {
+        // Запись, чтение, диапазон и удаление
+        store.put_object(BUCKET, "a/b.wav", Bytes::from_static(b"0123456789")).await.unwrap();
+        assert_eq!(read(store, "a/b.wav").await, b"0123456789");
+        assert_eq!(store.head_object(BUCKET, "a/b.wav").await.unwrap().unwrap().size, 10);
+
+        let range = store.get_object_range(BUCKET, "a/b.wav", ByteRange { start: 2, end: 5 }).await.unwrap();
+        assert_eq!(range.meta.size, 4);
+        assert_eq!(collect(range.body).await, b"2345");
+
+        store.delete_object(BUCKET, "a/b.wav").await.unwrap();
+        store.delete_object(BUCKET, "a/b.wav").await.unwrap();
+        assert!(store.head_object(BUCKET, "a/b.wav").await.unwrap().is_none());
+        assert!(matches!(store.get_object(BUCKET, "a/b.wav").await, Err(StorageError::ObjectNotFound { .. })));
+
+        for key in ["", "/abs", "a/../b", "a//b", "a\\b"] {
+            assert!(matches!(store.put_object(BUCKET, key, Bytes::new()).await, Err(StorageError::InvalidKey(_))), "{key:?}");
+        }
+
+        // Метаданные заменяются целиком и копируются вместе с объектом
+        store.put_object(BUCKET, "meta/src", Bytes::from_static(b"x")).await.unwrap();
+        let metadata = HashMap::from([("original-filename".to_string(), "song.wav".to_string())]);
+        store.replace_metadata(BUCKET, "meta/src", metadata.clone()).await.unwrap();
+        store.copy_object(BUCKET, "meta/src", BUCKET, "meta/dst").await.unwrap();
+        assert_eq!(store.head_object(BUCKET, "meta/dst").await.unwrap().unwrap().metadata, metadata);
+        assert!(store.replace_metadata(BUCKET, "meta/missing", HashMap::new()).await.is_err());
+
+        // Части собираются по номерам, а не по порядку загрузки
+        let upload = store.create_multipart_upload(BUCKET, "multi/all", None).await.unwrap();
+        for (number, body) in [(3, "c"), (1, "a"), (2, "b")] {
+            upload.upload_part(number, Bytes::from(body)).await.unwrap();
+        }
+        upload.complete().await.unwrap();
+        assert_eq!(read(store, "multi/all").await, b"abc");
+
+        // complete_parts не берет части после last_part и требует все до нее
+        let upload = store.create_multipart_upload(BUCKET, "multi/prefix", None).await.unwrap();
+        for (number, body) in [(1, "a"), (3, "c")] {
+            upload.upload_part(number, Bytes::from(body)).await.unwrap();
+        }
+        assert!(upload.complete_parts(2).await.is_err());
+        upload.upload_part(2, Bytes::from("b")).await.unwrap();
+        upload.complete_parts(2).await.unwrap();
+        assert_eq!(read(store, "multi/prefix").await, b"ab");
+
+        // Незавершенную загрузку можно найти, продолжить и отменить
+        let upload = store.create_multipart_upload(BUCKET, "multi/aborted", None).await.unwrap();
+        upload.upload_part(1, Bytes::from("a")).await.unwrap();
+        let pending = store.list_multipart_uploads(BUCKET).await.unwrap();
+        assert!(pending.iter().any(|pending| pending.upload_id == upload.upload_id() && pending.key == "multi/aborted"));
+        let resumed = store.resume_multipart_upload(BUCKET, "multi/aborted", upload.upload_id()).await.unwrap();
+        resumed.abort().await.unwrap();
+        assert!(store.list_multipart_uploads(BUCKET).await.unwrap().is_empty());
+        assert!(store.resume_multipart_upload(BUCKET, "multi/aborted", upload.upload_id()).await.is_err());
+        assert!(store.head_object(BUCKET, "multi/aborted").await.unwrap().is_none());
+
+        // Постраничное перечисление с разделителем
+        for key in ["list/1", "list/2", "list/dir/3", "list/dir/4"] {
+            store.put_object(BUCKET, key, Bytes::new()).await.unwrap();
+        }
+        let mut query = ListObjectsQuery {
+            prefix: Some("list/".to_string()),
+            delimiter: Some("/".to_string()),
+            max_keys: Some(2),
+            ..Default::default()
+        };
+        let first = store.list_objects_page(BUCKET, &query).await.unwrap();
+        assert_eq!(first.objects.iter().map(|object| object.key.as_str()).collect::<Vec<_>>(), ["list/1", "list/2"]);
+        query.continuation_token = first.next_continuation_token;
+        let second = store.list_objects_page(BUCKET, &query).await.unwrap();
+        assert!(second.objects.is_empty());
+        assert_eq!(second.common_prefixes, ["list/dir/"]);
+        assert!(second.next_continuation_token.is_none());
+    }
+
+    #[tokio::test]
+    async fn memory_store_follows_contract() {
+        check_contract(&MemoryObjectStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn local_store_follows_contract() {
+        let root = std::env::temp_dir().join(format!("svaha-storage-{}", ulid::Ulid::new()));
+        let store = LocalObjectStore::new(&root).await.unwrap();
+        check_contract(&store).await;
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+}
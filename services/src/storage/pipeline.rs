@@ -0,0 +1,243 @@
+use std::sync::Arc;
+use bytes::Bytes;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use crate::progress::ProgressTracker;
+use super::MultipartUpload;
+use super::errors::{Result, StorageError};
+
+
+/// Параллельная загрузка частей с ограничением числа частей "в полете"
+/// и объема буферизованных байтов
+pub struct PartUploadPipeline {
+    upload: Arc<dyn MultipartUpload>,
+    tasks: JoinSet<(i32, Result<()>)>,
+    part_slots: Arc<Semaphore>,
+    memory: Arc<Semaphore>,
+    memory_limit: usize,
+    /// Ошибка части с наименьшим номером
+    first_error: Option<(i32, StorageError)>,
+    progress: Option<Arc<ProgressTracker>>,
+}
+
+impl PartUploadPipeline {
+    pub fn new(upload: Arc<dyn MultipartUpload>, max_parallel_parts: usize, max_buffered_bytes: usize) -> Self {
+        let memory_limit = max_buffered_bytes.clamp(1, Semaphore::MAX_PERMITS.min(u32::MAX as usize));
+
+        Self {
+            upload,
+            tasks: JoinSet::new(),
+            part_slots: Arc::new(Semaphore::new(max_parallel_parts.max(1))),
+            memory: Arc::new(Semaphore::new(memory_limit)),
+            memory_limit,
+            first_error: None,
+            progress: None,
+        }
+    }
+
+    /// Публиковать отправку и подтверждение частей в прогресс загрузки
+    pub fn with_progress(mut self, progress: Arc<ProgressTracker>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Ставит часть в очередь на загрузку. Ждет, пока освободится слот и
+    /// память. Если одна из предыдущих частей упала, дожидается остальных и
+    /// возвращает ошибку части с наименьшим номером
+    pub async fn submit(&mut self, part_number: i32, body: Bytes) -> Result<()> {
+        self.reap_finished();
+        if self.first_error.is_some() {
+            return self.drain().await;
+        }
+
+        // Часть больше лимита памяти занимает весь лимит, иначе она бы ждала вечно
+        let weight = body.len().clamp(1, self.memory_limit) as u32;
+        let memory = Arc::clone(&self.memory)
+            .acquire_many_owned(weight)
+            .await
+            .map_err(|err| StorageError::Other(err.to_string()))?;
+        let slot = Arc::clone(&self.part_slots)
+            .acquire_owned()
+            .await
+            .map_err(|err| StorageError::Other(err.to_string()))?;
+
+        let upload = Arc::clone(&self.upload);
+        let progress = self.progress.clone();
+        if let Some(progress) = &progress {
+            progress.part_submitted();
+        }
+        self.tasks.spawn(async move {
+            let result = upload.upload_part(part_number, body).await;
+            drop(slot);
+            drop(memory);
+            if let (Some(progress), Ok(())) = (&progress, &result) {
+                progress.part_completed();
+            }
+            (part_number, result)
+        });
+
+        Ok(())
+    }
+
+    /// Дожидается загрузки всех частей
+    pub async fn finish(mut self) -> Result<()> {
+        self.drain().await
+    }
+
+    fn record(&mut self, part_number: i32, result: Result<()>) {
+        if let Err(err) = result {
+            tracing::error!("Failed to upload part {}: {}", part_number, err);
+            match &self.first_error {
+                Some((first, _)) if *first <= part_number => {}
+                _ => self.first_error = Some((part_number, err)),
+            }
+        }
+    }
+
+    fn reap_finished(&mut self) {
+        while let Some(joined) = self.tasks.try_join_next() {
+            match joined {
+                Ok((part_number, result)) => self.record(part_number, result),
+                Err(err) => self.record(i32::MAX, Err(StorageError::Other(format!("Part upload task failed: {err}")))),
+            }
+        }
+    }
+
+    async fn drain(&mut self) -> Result<()> {
+        while let Some(joined) = self.tasks.join_next().await {
+            match joined {
+                Ok((part_number, result)) => self.record(part_number, result),
+                Err(err) => self.record(i32::MAX, Err(StorageError::Other(format!("Part upload task failed: {err}")))),
+            }
+        }
+
+        match self.first_error.take() {
+            Some((_, err)) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use async_trait::async_trait;
+    use futures::TryStreamExt;
+    use crate::storage::{MemoryObjectStore, MultipartUploadGuard, ObjectStore};
+    use super::*;
+
+    /// Загрузка в память, в которой поздние части завершаются раньше ранних,
+    /// а части из `failing` падают
+    struct SlowUpload {
+        inner: Box<dyn MultipartUpload>,
+        failing: Vec<i32>,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    impl SlowUpload {
+        fn new(inner: Box<dyn MultipartUpload>, failing: Vec<i32>) -> Self {
+            Self { inner, failing, in_flight: AtomicUsize::new(0), max_in_flight: AtomicUsize::new(0) }
+        }
+    }
+
+    #[async_trait]
+    impl MultipartUpload for SlowUpload {
+        fn upload_id(&self) -> &str {
+            self.inner.upload_id()
+        }
+
+        async fn upload_part(&self, part_number: i32, body: Bytes) -> Result<()> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10 * (10 - part_number as u64))).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            if self.failing.contains(&part_number) {
+                return Err(StorageError::MultipartError(format!("part {part_number} failed")));
+            }
+            self.inner.upload_part(part_number, body).await
+        }
+
+        async fn complete(&self) -> Result<()> {
+            self.inner.complete().await
+        }
+
+        async fn complete_parts(&self, last_part: i32) -> Result<()> {
+            self.inner.complete_parts(last_part).await
+        }
+
+        async fn abort(&self) -> Result<()> {
+            self.inner.abort().await
+        }
+    }
+
+    async fn slow_upload(store: &MemoryObjectStore, failing: Vec<i32>) -> Arc<SlowUpload> {
+        let inner = store.create_multipart_upload("bucket", "key", None).await.unwrap();
+        Arc::new(SlowUpload::new(inner, failing))
+    }
+
+    #[tokio::test]
+    async fn parts_are_assembled_in_order_with_bounded_parallelism() {
+        let store = MemoryObjectStore::new();
+        let upload = slow_upload(&store, Vec::new()).await;
+
+        let mut pipeline = PartUploadPipeline::new(upload.clone(), 3, 1024);
+        for part_number in 1..=8 {
+            pipeline.submit(part_number, Bytes::from(part_number.to_string())).await.unwrap();
+        }
+        pipeline.finish().await.unwrap();
+        upload.complete().await.unwrap();
+
+        assert!(upload.max_in_flight.load(Ordering::SeqCst) <= 3);
+        let object = store.get_object("bucket", "key").await.unwrap();
+        let data = object.body.map_ok(|chunk| chunk.to_vec()).try_concat().await.unwrap();
+        assert_eq!(data, b"12345678");
+    }
+
+    #[tokio::test]
+    async fn memory_limit_bounds_buffered_parts() {
+        let store = MemoryObjectStore::new();
+        let upload = slow_upload(&store, Vec::new()).await;
+
+        // Каждая часть занимает весь лимит памяти, поэтому в полете только одна
+        let mut pipeline = PartUploadPipeline::new(upload.clone(), 4, 4);
+        for part_number in 1..=4 {
+            pipeline.submit(part_number, Bytes::from_static(b"abcd")).await.unwrap();
+        }
+        pipeline.finish().await.unwrap();
+        assert_eq!(upload.max_in_flight.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn lowest_failed_part_is_reported_and_upload_can_be_aborted() {
+        let store = MemoryObjectStore::new();
+        // Часть 4 падает раньше части 2, но сообщается ошибка части 2
+        let upload = slow_upload(&store, vec![2, 4]).await;
+
+        let mut pipeline = PartUploadPipeline::new(upload.clone(), 4, 1024);
+        for part_number in 1..=4 {
+            pipeline.submit(part_number, Bytes::from_static(b"x")).await.unwrap();
+        }
+        let err = pipeline.finish().await.unwrap_err();
+        assert_eq!(err.to_string(), "Multipart upload error: part 2 failed");
+
+        MultipartUploadGuard::new(upload).abort().await;
+        assert!(store.list_multipart_uploads("bucket").await.unwrap().is_empty());
+        assert!(store.head_object("bucket", "key").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn submit_after_failure_returns_the_error() {
+        let store = MemoryObjectStore::new();
+        let upload = slow_upload(&store, vec![1]).await;
+
+        let mut pipeline = PartUploadPipeline::new(upload, 2, 1024);
+        pipeline.submit(1, Bytes::from_static(b"x")).await.unwrap();
+        // Часть 1 загружается 90 мс и падает
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let err = pipeline.submit(2, Bytes::from_static(b"x")).await.unwrap_err();
+        assert_eq!(err.to_string(), "Multipart upload error: part 1 failed");
+    }
+}
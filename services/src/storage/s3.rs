@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use super::{ByteRange, ListObjectsQuery, MultipartUpload, MultipartUploadOptions, ObjectBody, ObjectListPage, ObjectMeta, ObjectStore, PendingUpload, StorageBackend, MAX_LIST_KEYS};
+use super::errors::{Result, StorageError};
+use crate::s3::{MultipartUploadContext, S3Error, S3Manager};
+
+
+/// Переводит время из формата AWS SDK в chrono
+fn to_chrono(value: &aws_sdk_s3::primitives::DateTime) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(value.secs(), value.subsec_nanos())
+}
+
+/// Метаданные объекта из ответа ListObjectsV2; тип содержимого S3 там не возвращает
+fn listed_object(object: &aws_sdk_s3::types::Object) -> Option<ObjectMeta> {
+    Some(ObjectMeta {
+        key: object.key()?.to_string(),
+        size: object.size().unwrap_or_default().max(0) as u64,
+        e_tag: object.e_tag().map(ToString::to_string),
+        last_modified: object.last_modified().and_then(to_chrono),
+        content_type: None,
+        storage_class: object.storage_class().map(|class| class.as_str().to_string()),
+        ..Default::default()
+    })
+}
+
+/// Читает объект (или его диапазон) потоком прямо из тела ответа S3
+async fn fetch_object(s3: &S3Manager, bucket: &str, key: &str, range: Option<ByteRange>) -> Result<ObjectBody> {
+    let output = s3.get_client()
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .set_range(range.map(|range| format!("bytes={}-{}", range.start, range.end)))
+        .send()
+        .await
+        .map_err(|err| match err.as_service_error() {
+            Some(service_err) if service_err.is_no_such_key() => StorageError::ObjectNotFound {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+            },
+            _ => S3Error::from(err).into(),
+        })?;
+
+    let meta = ObjectMeta {
+        key: key.to_string(),
+        size: output.content_length().unwrap_or_default().max(0) as u64,
+        e_tag: output.e_tag().map(ToString::to_string),
+        last_modified: output.last_modified().and_then(to_chrono),
+        content_type: output.content_type().map(ToString::to_string),
+        storage_class: output.storage_class().map(|class| class.as_str().to_string()),
+        metadata: output.metadata().cloned().unwrap_or_default(),
+    };
+
+    // Отдаем тело по мере чтения, не накапливая его в памяти
+    let body = stream::unfold(output.body, |mut body| async move {
+        match body.next().await {
+            Some(Ok(chunk)) => Some((Ok(chunk), body)),
+            Some(Err(err)) => Some((Err(S3Error::DownloadError(err.to_string()).into()), body)),
+            None => None,
+        }
+    });
+
+    Ok(ObjectBody { meta, body: body.boxed() })
+}
+
+#[async_trait]
+impl ObjectStore for S3Manager {
+    fn backend(&self) -> StorageBackend {
+        StorageBackend::S3
+    }
+
+    async fn put_object(&self, bucket: &str, key: &str, data: Bytes) -> Result<()> {
+        S3Manager::put_object(self, bucket, key, data).await?;
+        Ok(())
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        options: Option<MultipartUploadOptions>,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        let context = self.create_multipart_upload_context(bucket, key, options).await?;
+        Ok(Box::new(context))
+    }
+
+    async fn resume_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        match self.resume_multipart_upload_context(bucket, key, upload_id).await {
+            Ok(context) => Ok(Box::new(context)),
+            Err(S3Error::ObjectNotFound { .. }) => Err(StorageError::ObjectNotFound {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+            }),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<ObjectBody> {
+        fetch_object(self, bucket, key, None).await
+    }
+
+    async fn get_object_range(&self, bucket: &str, key: &str, range: ByteRange) -> Result<ObjectBody> {
+        fetch_object(self, bucket, key, Some(range)).await
+    }
+
+    async fn head_object(&self, bucket: &str, key: &str) -> Result<Option<ObjectMeta>> {
+        match self.get_client()
+            .head_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(output) => Ok(Some(ObjectMeta {
+                key: key.to_string(),
+                size: output.content_length().unwrap_or_default().max(0) as u64,
+                e_tag: output.e_tag().map(ToString::to_string),
+                last_modified: output.last_modified().and_then(to_chrono),
+                content_type: output.content_type().map(ToString::to_string),
+                storage_class: output.storage_class().map(|class| class.as_str().to_string()),
+                metadata: output.metadata().cloned().unwrap_or_default(),
+            })),
+            Err(err) => {
+                if let Some(service_err) = err.as_service_error() {
+                    if service_err.is_not_found() {
+                        return Ok(None);
+                    }
+                }
+                Err(S3Error::from(err).into())
+            }
+        }
+    }
+
+    async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<ObjectMeta>> {
+        let mut objects = Vec::new();
+        let mut paginator = self.get_client()
+            .list_objects_v2()
+            .bucket(bucket)
+            .set_prefix(prefix.map(ToString::to_string))
+            .into_paginator()
+            .send();
+
+        while let Some(result) = paginator.next().await {
+            let output = result.map_err(S3Error::from)?;
+            objects.extend(output.contents().iter().filter_map(listed_object));
+        }
+
+        Ok(objects)
+    }
+
+    async fn list_objects_page(&self, bucket: &str, query: &ListObjectsQuery) -> Result<ObjectListPage> {
+        let max_keys = query.max_keys.unwrap_or(MAX_LIST_KEYS).clamp(1, MAX_LIST_KEYS);
+        let output = self.get_client()
+            .list_objects_v2()
+            .bucket(bucket)
+            .set_prefix(query.prefix.clone())
+            .set_delimiter(query.delimiter.clone().filter(|delimiter| !delimiter.is_empty()))
+            .set_continuation_token(query.continuation_token.clone())
+            .max_keys(max_keys as i32)
+            .send()
+            .await
+            .map_err(S3Error::from)?;
+
+        Ok(ObjectListPage {
+            objects: output.contents().iter().filter_map(listed_object).collect(),
+            common_prefixes: output
+                .common_prefixes()
+                .iter()
+                .filter_map(|prefix| prefix.prefix().map(ToString::to_string))
+                .collect(),
+            next_continuation_token: output
+                .is_truncated()
+                .unwrap_or_default()
+                .then(|| output.next_continuation_token().map(ToString::to_string))
+                .flatten(),
+        })
+    }
+
+    async fn delete_object(&self, bucket: &str, key: &str) -> Result<()> {
+        S3Manager::delete_object(self, bucket, key).await?;
+        Ok(())
+    }
+
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+    ) -> Result<()> {
+        S3Manager::copy_object(self, source_bucket, destination_bucket, source_key, destination_key).await?;
+        Ok(())
+    }
+
+    async fn replace_metadata(&self, bucket: &str, key: &str, metadata: HashMap<String, String>) -> Result<()> {
+        S3Manager::replace_metadata(self, bucket, key, metadata).await?;
+        Ok(())
+    }
+
+    async fn list_multipart_uploads(&self, bucket: &str) -> Result<Vec<PendingUpload>> {
+        let uploads = S3Manager::list_multipart_uploads(self, bucket).await?;
+
+        Ok(uploads
+            .iter()
+            .filter_map(|upload| {
+                Some(PendingUpload {
+                    key: upload.key()?.to_string(),
+                    upload_id: upload.upload_id()?.to_string(),
+                    initiated: upload.initiated().and_then(to_chrono),
+                })
+            })
+            .collect())
+    }
+
+    async fn abort_multipart_upload(&self, bucket: &str, key: &str, upload_id: &str) -> Result<()> {
+        S3Manager::abort_multipart_upload(self, bucket, key, upload_id).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MultipartUpload for MultipartUploadContext {
+    fn upload_id(&self) -> &str {
+        MultipartUploadContext::upload_id(self)
+    }
+
+    async fn upload_part(&self, part_number: i32, body: Bytes) -> Result<()> {
+        Ok(MultipartUploadContext::upload_part(self, part_number, body).await?)
+    }
+
+    async fn complete(&self) -> Result<()> {
+        Ok(MultipartUploadContext::complete(self).await?)
+    }
+
+    async fn complete_parts(&self, last_part: i32) -> Result<()> {
+        Ok(MultipartUploadContext::complete_parts(self, last_part).await?)
+    }
+
+    async fn abort(&self) -> Result<()> {
+        Ok(MultipartUploadContext::abort(self).await?)
+    }
+}
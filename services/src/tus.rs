@@ -0,0 +1,606 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use ulid::Ulid;
+use crate::audio::AudioFormat;
+use crate::storage::{MultipartUpload, MultipartUploadOptions, ObjectStore, StorageError};
+
+
+/// Размер части многочастной загрузки, на которые режется поток tus.
+/// Минимально допустимый в S3 для всех частей, кроме последней
+pub const TUS_PART_SIZE: usize = 5 * 1024 * 1024;
+
+/// Алгоритмы расширения `checksum`, в порядке предпочтения
+pub const TUS_CHECKSUM_ALGORITHMS: &[&str] = &["sha1", "sha256"];
+
+const STATE_PREFIX: &str = "tus-state";
+
+#[derive(Error, Debug)]
+pub enum TusError {
+    #[error("Upload {0} not found")]
+    NotFound(String),
+
+    #[error("Upload offset mismatch: expected {expected}, got {actual}")]
+    OffsetMismatch { expected: u64, actual: u64 },
+
+    #[error("Upload {0} is locked by another request")]
+    Locked(String),
+
+    #[error("Unsupported checksum algorithm: {0}")]
+    UnsupportedChecksum(String),
+
+    #[error("Checksum mismatch")]
+    ChecksumMismatch,
+
+    #[error("Upload exceeds declared length of {0} bytes")]
+    ExceedsLength(u64),
+
+    #[error("File content does not match its extension: expected {expected:?}, detected {detected:?}")]
+    WrongFormat { expected: AudioFormat, detected: Option<AudioFormat> },
+
+    #[error("Failed to read request body: {0}")]
+    Body(String),
+
+    #[error("Invalid upload state: {0}")]
+    State(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+pub type Result<T> = std::result::Result<T, TusError>;
+
+/// Контрольная сумма тела PATCH-запроса (заголовок `Upload-Checksum`)
+#[derive(Debug, Clone)]
+pub struct TusChecksum {
+    algorithm: String,
+    digest: Vec<u8>,
+}
+
+impl TusChecksum {
+    pub fn new(algorithm: &str, digest: Vec<u8>) -> Result<Self> {
+        let algorithm = algorithm.to_ascii_lowercase();
+        if !TUS_CHECKSUM_ALGORITHMS.contains(&algorithm.as_str()) {
+            return Err(TusError::UnsupportedChecksum(algorithm));
+        }
+        Ok(Self { algorithm, digest })
+    }
+
+    fn hasher(&self) -> ChecksumHasher {
+        match self.algorithm.as_str() {
+            "sha1" => ChecksumHasher::Sha1(Sha1::new()),
+            _ => ChecksumHasher::Sha256(Sha256::new()),
+        }
+    }
+}
+
+enum ChecksumHasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl ChecksumHasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha1(hasher) => hasher.update(data),
+            Self::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Self::Sha1(hasher) => hasher.finalize().to_vec(),
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+
+/// Состояние tus-загрузки; хранится рядом с файлами, чтобы пережить
+/// перезапуск процесса
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TusUpload {
+    pub id: String,
+    /// Ключ итогового объекта
+    pub key: String,
+    /// Идентификатор многочастной загрузки в хранилище
+    upload_id: Option<String>,
+    pub length: u64,
+    pub offset: u64,
+    pub metadata: BTreeMap<String, String>,
+    pub completed: bool,
+    /// Ожидаемый формат файла; пока начало файла не проверено, части
+    /// в хранилище не отправляются
+    pub format: Option<AudioFormat>,
+    format_checked: bool,
+    part_size: u64,
+    /// Сколько полных частей уже загружено в хранилище
+    parts_uploaded: i32,
+    /// Хвост меньше одной части, ожидающий следующих PATCH-запросов
+    tail_key: Option<String>,
+}
+
+/// Хранилище tus-загрузок: переводит смещения протокола в части
+/// многочастной загрузки `ObjectStore`
+#[derive(Clone)]
+pub struct TusStore {
+    storage: Arc<dyn ObjectStore>,
+    bucket: String,
+    locks: Arc<Mutex<HashSet<String>>>,
+}
+
+/// Захват загрузки на время запроса; освобождается при сбросе
+struct UploadLock {
+    locks: Arc<Mutex<HashSet<String>>>,
+    id: String,
+}
+
+impl Drop for UploadLock {
+    fn drop(&mut self) {
+        self.locks.lock().unwrap_or_else(|err| err.into_inner()).remove(&self.id);
+    }
+}
+
+impl TusStore {
+    pub fn new(storage: Arc<dyn ObjectStore>, bucket: impl Into<String>) -> Self {
+        Self {
+            storage,
+            bucket: bucket.into(),
+            locks: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Бакет, в который пишутся загрузки
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    /// Создает загрузку заданной длины в объект `key` (расширение `creation`).
+    /// С `format` тип содержимого объекта берется из формата, а начало
+    /// файла сверяется с ним до того, как данные попадут в хранилище
+    pub async fn create(
+        &self,
+        key: String,
+        length: u64,
+        format: Option<AudioFormat>,
+        metadata: BTreeMap<String, String>,
+    ) -> Result<TusUpload> {
+        let id = Ulid::new().to_string();
+
+        let mut upload = TusUpload {
+            id,
+            key,
+            upload_id: None,
+            length,
+            offset: 0,
+            metadata,
+            completed: false,
+            format,
+            format_checked: format.is_none(),
+            part_size: TUS_PART_SIZE as u64,
+            parts_uploaded: 0,
+            tail_key: None,
+        };
+
+        if length == 0 {
+            check_format(&upload, &[])?;
+            // Пустой файл сразу готов: многочастная загрузка без частей невозможна
+            self.storage.put_object(&self.bucket, &upload.key, Bytes::new()).await?;
+            upload.completed = true;
+        } else {
+            let options = MultipartUploadOptions {
+                content_type: format.map(|format| format.mime_type().to_string()),
+                part_checksums: true,
+                ..Default::default()
+            };
+            let multipart = self.storage
+                .create_multipart_upload(&self.bucket, &upload.key, Some(options))
+                .await?;
+            upload.upload_id = Some(multipart.upload_id().to_string());
+        }
+
+        self.save(&upload).await?;
+        tracing::info!("Created tus upload {} of {} bytes", upload.id, length);
+        Ok(upload)
+    }
+
+    /// Текущее состояние загрузки (HEAD)
+    pub async fn get(&self, id: &str) -> Result<TusUpload> {
+        self.load(id).await
+    }
+
+    /// Дописывает тело PATCH-запроса начиная с `offset`. Полные части сразу
+    /// уходят в хранилище, остаток сохраняется как хвост. С контрольной
+    /// суммой смещение фиксируется только после ее проверки, без нее
+    /// при обрыве соединения фиксируется все, что успели получить
+    pub async fn append<S, E>(
+        &self,
+        id: &str,
+        offset: u64,
+        mut body: S,
+        checksum: Option<TusChecksum>,
+    ) -> Result<TusUpload>
+    where
+        S: Stream<Item = std::result::Result<Bytes, E>> + Unpin + Send,
+        E: std::fmt::Display,
+    {
+        let _lock = self.lock(id)?;
+        let mut upload = self.load(id).await?;
+        if upload.offset != offset {
+            return Err(TusError::OffsetMismatch { expected: upload.offset, actual: offset });
+        }
+        if upload.completed {
+            return Ok(upload);
+        }
+
+        let multipart = self.resume(&upload).await?;
+        let mut buffer = match &upload.tail_key {
+            Some(tail_key) => self.read_object(tail_key).await?,
+            None => BytesMut::new(),
+        };
+        let part_size = upload.part_size as usize;
+        let mut hasher = checksum.as_ref().map(TusChecksum::hasher);
+        let mut part_number = upload.parts_uploaded;
+        let mut received: u64 = 0;
+        let mut body_error = None;
+
+        while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    body_error = Some(err.to_string());
+                    break;
+                }
+            };
+
+            received += chunk.len() as u64;
+            if upload.offset + received > upload.length {
+                return Err(TusError::ExceedsLength(upload.length));
+            }
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&chunk);
+            }
+
+            buffer.extend_from_slice(&chunk);
+            if !upload.format_checked {
+                // Пока формат не проверен, частей в хранилище нет и буфер
+                // содержит файл с самого начала
+                let complete = upload.offset + received == upload.length;
+                if buffer.len() < AudioFormat::sniff_len(&buffer) && !complete {
+                    continue;
+                }
+                check_format(&upload, &buffer)?;
+                upload.format_checked = true;
+            }
+            while buffer.len() >= part_size {
+                part_number += 1;
+                multipart.upload_part(part_number, buffer.split_to(part_size).freeze()).await?;
+            }
+        }
+
+        if let Some(checksum) = checksum {
+            // Без полного тела сумму не проверить: ничего не фиксируем.
+            // Уже загруженные части перезапишутся при повторе, а лишние
+            // не попадут в объект: он собирается только из зафиксированных
+            if let Some(err) = body_error {
+                return Err(TusError::Body(err));
+            }
+            if hasher.map(ChecksumHasher::finalize).as_deref() != Some(checksum.digest.as_slice()) {
+                return Err(TusError::ChecksumMismatch);
+            }
+        } else if let Some(err) = body_error {
+            tracing::warn!("Tus upload {} interrupted after {} bytes: {}", id, received, err);
+        }
+
+        if received == 0 {
+            return Ok(upload);
+        }
+
+        let previous_tail = upload.tail_key.take();
+        upload.parts_uploaded = part_number;
+        upload.offset += received;
+
+        if upload.offset == upload.length {
+            if !buffer.is_empty() {
+                upload.parts_uploaded += 1;
+                multipart.upload_part(upload.parts_uploaded, buffer.freeze()).await?;
+            }
+            // Части с большими номерами могли остаться от неудачного PATCH
+            multipart.complete_parts(upload.parts_uploaded).await?;
+            upload.completed = true;
+            tracing::info!("Completed tus upload {} into {}", upload.id, upload.key);
+        } else if !buffer.is_empty() {
+            let tail_key = format!("{STATE_PREFIX}/{}/tail-{}", upload.id, upload.offset);
+            self.storage.put_object(&self.bucket, &tail_key, buffer.freeze()).await?;
+            upload.tail_key = Some(tail_key);
+        }
+
+        // Старый хвост удаляем только после сохранения состояния,
+        // чтобы при сбое состояние не ссылалось на удаленный объект
+        self.save(&upload).await?;
+        if let Some(previous_tail) = previous_tail {
+            if let Err(err) = self.storage.delete_object(&self.bucket, &previous_tail).await {
+                tracing::warn!("Failed to delete tus tail {}: {}", previous_tail, err);
+            }
+        }
+
+        Ok(upload)
+    }
+
+    /// Удаляет загрузку вместе со всеми данными (расширение `termination`)
+    pub async fn terminate(&self, id: &str) -> Result<()> {
+        let _lock = self.lock(id)?;
+        let upload = self.load(id).await?;
+
+        if upload.completed {
+            self.storage.delete_object(&self.bucket, &upload.key).await?;
+        } else if let Some(upload_id) = &upload.upload_id {
+            self.storage.abort_multipart_upload(&self.bucket, &upload.key, upload_id).await?;
+        }
+        if let Some(tail_key) = &upload.tail_key {
+            self.storage.delete_object(&self.bucket, tail_key).await?;
+        }
+        self.storage.delete_object(&self.bucket, &state_key(id)).await?;
+
+        tracing::info!("Terminated tus upload {}", id);
+        Ok(())
+    }
+
+    fn lock(&self, id: &str) -> Result<UploadLock> {
+        let mut locks = self.locks.lock().unwrap_or_else(|err| err.into_inner());
+        if !locks.insert(id.to_string()) {
+            return Err(TusError::Locked(id.to_string()));
+        }
+        Ok(UploadLock { locks: Arc::clone(&self.locks), id: id.to_string() })
+    }
+
+    async fn resume(&self, upload: &TusUpload) -> Result<Box<dyn MultipartUpload>> {
+        let upload_id = upload.upload_id.as_deref().ok_or_else(|| TusError::NotFound(upload.id.clone()))?;
+        match self.storage.resume_multipart_upload(&self.bucket, &upload.key, upload_id).await {
+            Ok(multipart) => Ok(multipart),
+            // Многочастную загрузку мог отменить janitor
+            Err(StorageError::ObjectNotFound { .. }) => Err(TusError::NotFound(upload.id.clone())),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn load(&self, id: &str) -> Result<TusUpload> {
+        if Ulid::from_string(id).is_err() {
+            return Err(TusError::NotFound(id.to_string()));
+        }
+        match self.read_object(&state_key(id)).await {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(TusError::Storage(StorageError::ObjectNotFound { .. })) => Err(TusError::NotFound(id.to_string())),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn save(&self, upload: &TusUpload) -> Result<()> {
+        let data = serde_json::to_vec(upload)?;
+        self.storage.put_object(&self.bucket, &state_key(&upload.id), Bytes::from(data)).await?;
+        Ok(())
+    }
+
+    async fn read_object(&self, key: &str) -> Result<BytesMut> {
+        let object = self.storage.get_object(&self.bucket, key).await?;
+        let mut data = BytesMut::with_capacity(object.meta.size as usize);
+        let mut body = object.body;
+        while let Some(chunk) = body.next().await {
+            data.extend_from_slice(&chunk?);
+        }
+        Ok(data)
+    }
+}
+
+/// Сверяет начало файла с ожидаемым форматом
+fn check_format(upload: &TusUpload, head: &[u8]) -> Result<()> {
+    let Some(expected) = upload.format else { return Ok(()) };
+    let detected = AudioFormat::sniff(head);
+    if detected != Some(expected) {
+        tracing::warn!("Content of tus upload {} does not match its extension: {:?}", upload.id, detected);
+        return Err(TusError::WrongFormat { expected, detected });
+    }
+    Ok(())
+}
+
+fn state_key(id: &str) -> String {
+    format!("{STATE_PREFIX}/{id}/info.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+    use crate::storage::MemoryObjectStore;
+    use super::*;
+
+    const PART: usize = TUS_PART_SIZE;
+
+    fn store() -> (TusStore, Arc<MemoryObjectStore>) {
+        let storage = Arc::new(MemoryObjectStore::new());
+        (TusStore::new(storage.clone(), "bucket"), storage)
+    }
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|index| (index % 251) as u8).collect()
+    }
+
+    fn body(chunks: &[&[u8]]) -> impl Stream<Item = std::result::Result<Bytes, String>> + Unpin + Send {
+        stream::iter(chunks.iter().map(|chunk| Ok(Bytes::copy_from_slice(chunk))).collect::<Vec<_>>())
+    }
+
+    /// Тело, соединение которого обрывается после `chunks`
+    fn broken_body(chunks: &[&[u8]]) -> impl Stream<Item = std::result::Result<Bytes, String>> + Unpin + Send {
+        body(chunks).chain(stream::iter([Err("connection reset".to_string())]))
+    }
+
+    fn sha256(data: &[u8]) -> TusChecksum {
+        TusChecksum::new("sha256", Sha256::digest(data).to_vec()).unwrap()
+    }
+
+    async fn read(storage: &MemoryObjectStore, key: &str) -> Vec<u8> {
+        let object = storage.get_object("bucket", key).await.unwrap();
+        object.body.map(|chunk| chunk.unwrap().to_vec()).concat().await
+    }
+
+    #[tokio::test]
+    async fn chunks_across_part_boundaries_assemble_the_file() {
+        let (tus, storage) = store();
+        let file = data(2 * PART + 10);
+        let upload = tus.create("s/t/vocal.wav".to_string(), file.len() as u64, None, BTreeMap::new()).await.unwrap();
+
+        let mut offset = 0;
+        for end in [PART - 1, PART + 5, file.len()] {
+            let upload = tus.append(&upload.id, offset as u64, body(&[&file[offset..end]]), None).await.unwrap();
+            assert_eq!(upload.offset, end as u64);
+            assert_eq!(upload.completed, end == file.len());
+            offset = end;
+        }
+
+        assert_eq!(read(&storage, "s/t/vocal.wav").await, file);
+        // От хвостов и незавершенной загрузки ничего не осталось
+        assert!(storage.list_multipart_uploads("bucket").await.unwrap().is_empty());
+        let state = storage.list_objects("bucket", Some(STATE_PREFIX)).await.unwrap();
+        assert_eq!(state.iter().map(|object| object.key.as_str()).collect::<Vec<_>>(), [state_key(&upload.id)]);
+    }
+
+    #[tokio::test]
+    async fn wrong_offset_and_excess_length_are_rejected() {
+        let (tus, _) = store();
+        let upload = tus.create("key".to_string(), 10, None, BTreeMap::new()).await.unwrap();
+
+        let err = tus.append(&upload.id, 3, body(&[b"abc"]), None).await.unwrap_err();
+        assert!(matches!(err, TusError::OffsetMismatch { expected: 0, actual: 3 }));
+
+        let err = tus.append(&upload.id, 0, body(&[b"0123456789x"]), None).await.unwrap_err();
+        assert!(matches!(err, TusError::ExceedsLength(10)));
+        assert_eq!(tus.get(&upload.id).await.unwrap().offset, 0);
+    }
+
+    #[tokio::test]
+    async fn checksum_mismatch_keeps_the_offset() {
+        let (tus, storage) = store();
+        let upload = tus.create("key".to_string(), 6, None, BTreeMap::new()).await.unwrap();
+
+        let err = tus.append(&upload.id, 0, body(&[b"abc"]), Some(sha256(b"abd"))).await.unwrap_err();
+        assert!(matches!(err, TusError::ChecksumMismatch));
+        assert_eq!(tus.get(&upload.id).await.unwrap().offset, 0);
+
+        let upload = tus.append(&upload.id, 0, body(&[b"abc"]), Some(sha256(b"abc"))).await.unwrap();
+        assert_eq!(upload.offset, 3);
+        let upload = tus.append(&upload.id, 3, body(&[b"def"]), Some(sha256(b"def"))).await.unwrap();
+        assert!(upload.completed);
+        assert_eq!(read(&storage, "key").await, b"abcdef");
+
+        assert!(matches!(TusChecksum::new("md5", Vec::new()), Err(TusError::UnsupportedChecksum(_))));
+    }
+
+    #[tokio::test]
+    async fn failed_patch_with_checksum_is_retried_from_the_same_offset() {
+        let (tus, storage) = store();
+        let file = data(PART + 10);
+        let upload = tus.create("key".to_string(), file.len() as u64, None, BTreeMap::new()).await.unwrap();
+
+        // Обрыв после полной части: часть уже в хранилище, но смещение не сдвигается
+        let garbage = vec![0xff; PART + 5];
+        let err = tus.append(&upload.id, 0, broken_body(&[&garbage]), Some(sha256(&file))).await.unwrap_err();
+        assert!(matches!(err, TusError::Body(_)));
+        assert_eq!(tus.get(&upload.id).await.unwrap().offset, 0);
+
+        let upload = tus.append(&upload.id, 0, body(&[&file]), Some(sha256(&file))).await.unwrap();
+        assert!(upload.completed);
+        assert_eq!(read(&storage, "key").await, file);
+    }
+
+    #[tokio::test]
+    async fn retry_in_smaller_patches_overwrites_parts_of_a_failed_patch() {
+        let (tus, storage) = store();
+        let file = data(2 * PART + 10);
+        let upload = tus.create("key".to_string(), file.len() as u64, None, BTreeMap::new()).await.unwrap();
+
+        // Неудачный PATCH успел загрузить части 1 и 2
+        let garbage = vec![0xff; 2 * PART];
+        let err = tus.append(&upload.id, 0, broken_body(&[&garbage]), Some(sha256(&file))).await.unwrap_err();
+        assert!(matches!(err, TusError::Body(_)));
+
+        // Повтор двумя запросами: граница части 2 приходится на середину второго
+        let upload = tus.append(&upload.id, 0, body(&[&file[..PART + 5]]), None).await.unwrap();
+        let upload = tus.append(&upload.id, upload.offset, body(&[&file[PART + 5..]]), None).await.unwrap();
+        assert!(upload.completed);
+        assert_eq!(read(&storage, "key").await, file);
+    }
+
+    #[tokio::test]
+    async fn interrupted_patch_without_checksum_keeps_received_bytes() {
+        let (tus, storage) = store();
+        let upload = tus.create("key".to_string(), 6, None, BTreeMap::new()).await.unwrap();
+
+        let upload = tus.append(&upload.id, 0, broken_body(&[b"abcd"]), None).await.unwrap();
+        assert_eq!(upload.offset, 4);
+        let upload = tus.append(&upload.id, 4, body(&[b"ef"]), None).await.unwrap();
+        assert!(upload.completed);
+        assert_eq!(read(&storage, "key").await, b"abcdef");
+    }
+
+    #[tokio::test]
+    async fn terminate_removes_upload_and_state() {
+        let (tus, storage) = store();
+        let upload = tus.create("key".to_string(), 2 * PART as u64, None, BTreeMap::new()).await.unwrap();
+        tus.append(&upload.id, 0, body(&[b"abc"]), None).await.unwrap();
+
+        tus.terminate(&upload.id).await.unwrap();
+        assert!(matches!(tus.get(&upload.id).await, Err(TusError::NotFound(_))));
+        assert!(storage.list_multipart_uploads("bucket").await.unwrap().is_empty());
+        assert!(storage.list_objects("bucket", None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn empty_upload_completes_on_create() {
+        let (tus, storage) = store();
+        let upload = tus.create("key".to_string(), 0, None, BTreeMap::new()).await.unwrap();
+        assert!(upload.completed);
+        assert!(read(&storage, "key").await.is_empty());
+    }
+
+    fn wav(len: usize) -> Vec<u8> {
+        let mut file = data(len);
+        file[..12].copy_from_slice(b"RIFF\0\0\0\0WAVE");
+        file
+    }
+
+    #[tokio::test]
+    async fn format_is_checked_before_parts_are_stored() {
+        let (tus, storage) = store();
+        let file = wav(PART + 10);
+        let upload = tus.create("key".to_string(), file.len() as u64, Some(AudioFormat::Wav), BTreeMap::new()).await.unwrap();
+
+        // Начала файла еще не хватает для проверки: байты ждут в хвосте
+        let upload = tus.append(&upload.id, 0, body(&[&file[..4]]), None).await.unwrap();
+        assert_eq!(upload.offset, 4);
+        let upload = tus.append(&upload.id, 4, body(&[&file[4..]]), None).await.unwrap();
+        assert!(upload.completed);
+
+        let meta = storage.head_object("bucket", "key").await.unwrap().unwrap();
+        assert_eq!(meta.content_type.as_deref(), Some("audio/wav"));
+        assert_eq!(read(&storage, "key").await, file);
+    }
+
+    #[tokio::test]
+    async fn content_that_does_not_match_the_format_is_rejected() {
+        let (tus, storage) = store();
+        let file = wav(2 * PART);
+        let upload = tus.create("key".to_string(), file.len() as u64, Some(AudioFormat::Flac), BTreeMap::new()).await.unwrap();
+
+        let err = tus.append(&upload.id, 0, body(&[&file[..PART + 1]]), None).await.unwrap_err();
+        assert!(matches!(err, TusError::WrongFormat { expected: AudioFormat::Flac, detected: Some(AudioFormat::Wav) }));
+        assert_eq!(tus.get(&upload.id).await.unwrap().offset, 0);
+        assert!(storage.list_objects("bucket", Some("key")).await.unwrap().is_empty());
+
+        // Пустой файл не может быть аудио
+        let err = tus.create("empty".to_string(), 0, Some(AudioFormat::Wav), BTreeMap::new()).await.unwrap_err();
+        assert!(matches!(err, TusError::WrongFormat { detected: None, .. }));
+    }
+}
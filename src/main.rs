@@ -1,7 +1,12 @@
-use axum::middleware::{self};
+use axum::middleware::{self, Next};
+use axum::extract::Request;
+use axum::http::{header, Extensions, HeaderMap, Method, StatusCode, Version};
+use axum::response::IntoResponse;
+use tower::ServiceExt;
 use tokio::net::TcpListener;
 use tokio::signal;
 use tower_http::{compression::CompressionLayer, decompression::RequestDecompressionLayer};
+use tower_http::compression::predicate::{DefaultPredicate, Predicate};
 
 use api::custom_tracing;
 
@@ -17,15 +22,19 @@ use api::custom_exceptions::{ErrorCode, global_error_handler};
 use api::exceptions;
 
 use api::get_api;
-use core::logging::init_logger;
+use my_core::logging::init_logger;
 use tower_http::limit::RequestBodyLimitLayer;
-use core::config::CONFIG;
+use my_core::config::CONFIG;
 use tracing_subscriber;
 use tower_http::trace::TraceLayer;
 use services::AppState;
+use services::shutdown::ShutdownCoordinator;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 
+/// Сколько после отмены загрузок ждать закрытия остальных соединений
+const CONNECTIONS_GRACE: Duration = Duration::from_secs(5);
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> std::io::Result<()> {
     init_logger("svaha_mini_uploader_axum");
@@ -56,23 +65,45 @@ async fn main() -> std::io::Result<()> {
         // .allow_credentials(true)
         .allow_origin(Any)
         .allow_methods(Any)
-        .allow_headers(Any);
+        .allow_headers(Any)
+        // tus-клиенту в браузере нужны Location и Upload-* заголовки ответа
+        .expose_headers(Any);
 
     // let sas = router.into_make_service_with_connect_info();
     let app_state = Arc::new(AppState::new().await.expect("Failed to create AppState"));
+    app_state.spawn_multipart_janitor();
+    let shutdown = Arc::clone(&app_state.shutdown);
     let mut router = get_api(app_state);
 
 
 
+    // CorsLayer отвечает на любой OPTIONS как на preflight. Запросы без
+    // Access-Control-Request-Method (например, discovery tus) отдаем роутеру напрямую
+    let uncors_router = router.clone();
     router = router
         .layer(cors)
+        .layer(middleware::from_fn(move |request: Request, next: Next| {
+            let uncors_router = uncors_router.clone();
+            async move {
+                let is_preflight = request.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+                if request.method() == Method::OPTIONS && !is_preflight {
+                    return uncors_router.oneshot(request).await.into_response();
+                }
+                next.run(request).await
+            }
+        }))
         // .layer(tower::limit::ConcurrencyLimitLayer::new(500))
         .layer(RequestDecompressionLayer::new())  // Сначала разжимаем входящие запросы
-        .layer(CompressionLayer::new())  // Затем сжимаем исходящие ответы
+        // Затем сжимаем исходящие ответы; файлы с поддержкой Range отдаем как есть,
+        // иначе Content-Length и Content-Range перестанут соответствовать телу
+        .layer(CompressionLayer::new().compress_when(DefaultPredicate::new().and(
+            |_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions| !headers.contains_key(header::ACCEPT_RANGES),
+        )))
 
         // 
-        .layer(DefaultBodyLimit::disable())
-        // .layer(RequestBodyLimitLayer::new(CONFIG.body_size_limit))
+        // Общий лимит тела для экстракторов; маршруты загрузки задают свой
+        // (`body_limit::limit_body`), tus ограничен `tus_max_size`
+        .layer(DefaultBodyLimit::max(CONFIG.body_size_limit))
         .layer(custom_tracing::create_tracing_layer())
         // .layer(middleware::from_fn(custom_tracing::request_data_middleware))
         .layer(middleware::from_fn(global_error_handler))
@@ -90,12 +121,25 @@ async fn main() -> std::io::Result<()> {
         .into_make_service_with_connect_info::<SocketAddr>();
 
     let listener = TcpListener::bind(addr).await?;
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
+    // Пока идет ожидание загрузок, сервер принимает соединения: новые загрузки
+    // получают 503, остальные запросы обслуживаются как обычно
+    let server = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal(Arc::clone(&shutdown)));
+
+    tokio::select! {
+        result = server => result,
+        _ = force_shutdown(shutdown) => Ok(()),
+    }
+}
+
+/// Соединения, оставшиеся после загрузок (SSE, скачивания), закрываются через `CONNECTIONS_GRACE`
+async fn force_shutdown(shutdown: Arc<ShutdownCoordinator>) {
+    shutdown.drained().await;
+    tokio::time::sleep(CONNECTIONS_GRACE).await;
+    tracing::warn!("Connections are still open after {}s, forcing shutdown", CONNECTIONS_GRACE.as_secs());
 }
 
-async fn shutdown_signal() {
+async fn shutdown_signal(shutdown: Arc<ShutdownCoordinator>) {
     let ctrl_c = async {
         signal::ctrl_c()
             .await
@@ -119,6 +163,12 @@ async fn shutdown_signal() {
     }
 
     tracing::info!("Shutdown signal received, starting graceful shutdown");
-    tokio::time::sleep(Duration::from_secs(10)).await;
-    tracing::info!("Graceful shutdown period ended, forcing shutdown");
+    let report = shutdown.drain(
+        Duration::from_secs(CONFIG.shutdown_drain_timeout_secs),
+        &CONFIG.shutdown_aborted_uploads_path,
+    ).await;
+    tracing::info!(
+        "Uploads drained: {} completed, {} cancelled, {} multipart uploads aborted",
+        report.completed, report.cancelled, report.aborted.len()
+    );
 }